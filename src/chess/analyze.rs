@@ -0,0 +1,106 @@
+use crate::chess::variant::Variant;
+use crate::chess::{board, pos, san, search, time, uci, validator, Player};
+
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use termion::{clear, cursor};
+
+//a line in san with move numbers, like 12. Nf3 Nc6 13. d4 or 12... Nc6 13. d4 when black starts
+pub fn san_line(board: &board::Board, pv: &[pos::Move], fullmove: u32) -> String {
+    let mut board = board.clone();
+    let mut number = fullmove;
+    let mut text = Vec::<String>::new();
+    for (i, m) in pv.iter().enumerate() {
+        match board.active_player() {
+            Player::White => text.push(format!("{}.", number)),
+            Player::Black if i == 0 => text.push(format!("{}...", number)),
+            Player::Black => {}
+        }
+        text.push(san::to_san(&board, m));
+        if board.active_player() == Player::Black {
+            number += 1;
+        }
+        board.make_move(m);
+    }
+    text.join(" ")
+}
+
+//searches a position for the best few lines until enter is pressed, or for a fixed time or depth,
+//rewriting the lines in place after every depth
+pub fn run(
+    fen: &str,
+    variant: Option::<Variant>,
+    lines: usize,
+    movetime: Option::<u64>,
+    depth: Option::<i32>,
+    hash_mb: usize,
+) -> Result::<(), String> {
+    let mut board = board::Board::from_fen(fen);
+    if let Some(v) = variant {
+        board.set_variant(v);
+    }
+    if validator::get_possible_moves(&mut board.clone()).is_empty() {
+        return Err("no legal moves".to_owned());
+    }
+    let fullmove = fen.split_whitespace().nth(5).and_then(|n| n.parse().ok()).unwrap_or(1);
+    let mut options = search::SearchOptions { threads: 1, ..search::SearchOptions::default() };
+    if hash_mb > 0 {
+        options.hash_mb = hash_mb;
+    }
+    let infinite = movetime.is_none() && depth.is_none();
+    let limits = time::Limits { movetime, depth, infinite, ..time::Limits::default() };
+    let stop = Arc::new(AtomicBool::new(false));
+    let manager = time::TimeManager::new(&limits, board.active_player(), stop.clone());
+    if infinite {
+        println!("press enter to stop");
+    }
+
+    let searcher = {
+        let board = board.clone();
+        std::thread::spawn(move || {
+            let mut searcher = search::Searcher::new(options);
+            let start = Instant::now();
+            // how many lines the last report printed, to go back up over
+            let mut shown = 0;
+            searcher.search_multipv(&board, depth.unwrap_or(search::MAX_DEPTH), lines, manager, &mut |results| {
+                let mut out = std::io::stdout().lock();
+                if shown > 0 {
+                    write!(out, "{}\r", cursor::Up(shown)).expect("couldn't write to stdout");
+                }
+                write!(out, "{}", clear::AfterCursor).expect("couldn't write to stdout");
+                for (i, r) in results.iter().enumerate() {
+                    writeln!(
+                        out,
+                        "{}. {:<9} depth {:<3} nodes {:<10} {}",
+                        i + 1,
+                        uci::format_score(r.score),
+                        r.depth,
+                        r.nodes,
+                        san_line(&board, &r.pv, fullmove)
+                    )
+                    .expect("couldn't write to stdout");
+                }
+                let ms = start.elapsed().as_millis().max(1);
+                let nodes = results.last().map_or(0, |r| r.nodes);
+                writeln!(out, "{:.1}s, {} nodes/s", ms as f64 / 1000.0, nodes as u128 * 1000 / ms).expect("couldn't write to stdout");
+                out.flush().expect("couldn't write to stdout");
+                shown = results.len() as u16 + 1;
+            })
+        })
+    };
+
+    if infinite {
+        let mut line = String::new();
+        //eof stops it too, so piping into it doesn't hang
+        let _ = std::io::stdin().read_line(&mut line);
+        stop.store(true, Ordering::Relaxed);
+    }
+    let results = searcher.join().expect("search thread panicked");
+    if let Some(m) = results.first().and_then(|r| r.best_move) {
+        println!("best move {}", san::to_san(&board, &m));
+    }
+    Ok(())
+}
@@ -1,11 +1,15 @@
 use termion::{color};
 
-use crate::chess::{pos, piece};
+use crate::chess::{nnue, pos, piece};
+use crate::chess::variant::{Variant, CHECKS_TO_WIN};
 
 use piece::{Piece, Player};
 
 use std::vec::Vec;
 type Squares = [[piece::Piece; 8]; 8];
+pub type Pocket = [u8; 5];
+//what a pocket counts, in order
+pub const POCKET_PIECES: [fn(Player) -> Piece; 5] = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
 //A chess piece, and where it is on the board
 //used in a vector for a piece orineted view of the board
 #[derive(Clone)]
@@ -15,11 +19,12 @@ pub struct PieceState {
 }
 
 //players castleing rights, it should be noted that 
-//the kindside and queenside are mirrored for the diff. colors
+//the kindside and queenside are mirrored for the diff. colors.
+//holds the file of the rook that can still castle, which is only a or h in normal chess
 #[derive(Clone)]
 struct CastleRights {
-    pub kingside: bool,
-    pub queenside: bool,
+    pub kingside: Option::<i8>,
+    pub queenside: Option::<i8>,
 }
 
 // gcm of the dist between 2 pieces lookup table
@@ -38,8 +43,8 @@ const FACTOR_LOOKUP: [[i8; 8]; 8] = [
 pub fn chess_factor(old_pos: pos::Square, new_pos: pos::Square) -> pos::Square {
     let rank_diff = new_pos.rank - old_pos.rank;
     let file_diff = new_pos.file - old_pos.file;
-    let dr = rank_diff.abs() as usize;
-    let df = file_diff.abs() as usize;
+    let dr = rank_diff.unsigned_abs() as usize;
+    let df = file_diff.unsigned_abs() as usize;
     let gcm = FACTOR_LOOKUP[dr][df];
     pos::Square {
         rank: rank_diff / gcm,
@@ -68,14 +73,44 @@ fn pieces_generator(player: piece::Player, squares: &Squares) -> std::vec::Vec::
                 .collect()
 }
 
+//the chess960 start position with this number, counted the usual way (scharnagl's) so 518 is normal chess
+pub fn chess960_fen(index: usize) -> String {
+    //which of the knights' spots, out of the 5 left after the bishops and queen
+    const KNIGHTS: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+    let mut back_rank = [' '; 8];
+    let place = |back_rank: &mut [char; 8], nth_empty: usize, p: char| {
+        let file = back_rank.iter().enumerate().filter(|(_, &c)| c == ' ').nth(nth_empty).expect("Board Mangled").0;
+        back_rank[file] = p;
+    };
+    let mut n = index % 960;
+    back_rank[(n % 4) * 2 + 1] = 'b';
+    n /= 4;
+    back_rank[(n % 4) * 2] = 'b';
+    n /= 4;
+    place(&mut back_rank, n % 6, 'q');
+    n /= 6;
+    let (first, second) = KNIGHTS[n];
+    place(&mut back_rank, second, 'n');
+    place(&mut back_rank, first, 'n');
+    //the king always goes between the rooks
+    for p in ['r', 'k', 'r'] {
+        place(&mut back_rank, 0, p);
+    }
+    let back_rank: String = back_rank.iter().collect();
+    format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1", back_rank, back_rank.to_ascii_uppercase())
+}
+
 impl PieceState {
     //ignores enemy kings, cuz it basically only is for finding illegal king moves
     pub fn is_attacking(&self, board: &Board, square: pos::Square) -> bool {
+        if square == self.pos {
+            return false;
+        }
         // check if piece can move to that square
         let move_dir = match self.piece_type {
             Piece::Empty => {return false;},
             Piece::Pawn(pc) => {
-                return ((square.file - self.pos.file).abs()) == 1 && ((square.rank - self.pos.rank) == (1 * pc.sign()));
+                return ((square.file - self.pos.file).abs()) == 1 && ((square.rank - self.pos.rank) == pc.sign());
             },
             Piece::King(_) => {
                 return (square.file - self.pos.file).abs() <= 1 && (square.rank - self.pos.rank).abs() <= 1
@@ -116,11 +151,10 @@ impl PieceState {
         // check if any pieces are in the way
         let mut temp_square = self.pos + move_dir;
         while temp_square != square {
-            if let Some(_) = board.get(temp_square).owner() {
-                if board.get(temp_square) != piece::Piece::King(self.piece_type.owner().unwrap().invert()) {
+            if board.get(temp_square).owner().is_some()
+                && board.get(temp_square) != piece::Piece::King(self.piece_type.owner().unwrap().invert()) {
                     return false;
                 }
-            }
             temp_square += move_dir;
         }
         true
@@ -132,12 +166,25 @@ pub struct Board{
     squares : Squares,
     move_count: u32,
     last_pawn_move: u32,
+    // plies since the last capture or pawn move, for the fifty move rule
+    halfmove_clock: u32,
     last_move: Option::<pos::Move>,
     white_pieces: Vec::<PieceState>,
     black_pieces: Vec::<PieceState>,
     active_player: piece::Player,
     white_castle_rights: CastleRights,
     black_castle_rights: CastleRights, 
+    variant: Variant,
+    //crazyhouse pieces in hand, how many of each of POCKET_PIECES
+    white_pocket: Pocket,
+    black_pocket: Pocket,
+    //squares holding pieces that started out as pawns, in crazyhouse they go back to pawns when captured
+    promoted: u64,
+    //three-check, how many times each player has given check
+    white_checks: u8,
+    black_checks: u8,
+    //the network's sums for these pieces, kept up to date by make_move. only there while a network is loaded
+    accumulator: Option::<Box::<nnue::Accumulator>>,
 }
 
 impl Board{
@@ -166,30 +213,82 @@ impl Board{
             back_rank(Player::Black),
         ];
 
-        Board{
+        let mut board = Board{
             squares,
             move_count: 0,
             last_pawn_move: 0,
+            halfmove_clock: 0,
             last_move: None,
             white_pieces: pieces_generator(Player::White, &squares),
             black_pieces: pieces_generator(Player::Black, &squares),
             active_player: piece::Player::White,
-            white_castle_rights: CastleRights {queenside:true, kingside: true},
-            black_castle_rights: CastleRights {queenside:true, kingside: true},
-        }
+            white_castle_rights: CastleRights {queenside: Some(1), kingside: Some(8)},
+            black_castle_rights: CastleRights {queenside: Some(1), kingside: Some(8)},
+            variant: Variant::Standard,
+            white_pocket: [0; 5],
+            black_pocket: [0; 5],
+            promoted: 0,
+            white_checks: 0,
+            black_checks: 0,
+            accumulator: None,
+        };
+        board.refresh_accumulator();
+        board
     }
 
     pub fn from_fen(fen: &str) -> Self {
         use piece::Piece::*;
         use piece::Player::*;
+        //three-check counters, lichess puts the checks given on the end like +2+1,
+        //others put the checks left where the clocks go like 1+2. either way it means three-check
+        let mut checks: Option::<(u8, u8)> = None;
+        let fields: Vec::<&str> = fen.split_whitespace()
+            .filter(|field| {
+                let counters = field.strip_prefix('+').map(|f| (f, 0)).unwrap_or((field, CHECKS_TO_WIN));
+                let parsed = counters.0.split_once('+').and_then(|(w, b)| Some((w.parse::<u8>().ok()?, b.parse::<u8>().ok()?)));
+                match parsed {
+                    Some((w, b)) if counters.1 == 0 => checks = Some((w, b)),
+                    Some((w, b)) => checks = Some((CHECKS_TO_WIN.saturating_sub(w), CHECKS_TO_WIN.saturating_sub(b))),
+                    None => return true,
+                }
+                false
+            })
+            .collect();
+        let fen = fields.join(" ");
         let mut squares = [[Empty; 8]; 8];
         let mut rank: usize = 7;
         let mut file: usize = 0;
         let mut chiter = fen.chars();
 
+        //crazyhouse pockets come after the pieces, either in brackets or as a ninth rank
+        let mut pocket: Option::<String> = None;
+        //promoted pieces have a ~ after them
+        let mut promoted = 0u64;
+        let mut last_square = 0;
+
         //set up board
         chiter.any(|c| {
+            if let Some(pocket) = pocket.as_mut() {
+                match c {
+                    ' ' => return true,
+                    ']' => (),
+                    _ => pocket.push(c),
+                }
+                return false;
+            }
             match c {
+                '[' => {
+                    pocket = Some(String::new());
+                    return false;
+                },
+                '/' if rank == 0 && file > 7 => {
+                    pocket = Some(String::new());
+                    return false;
+                },
+                '~' => {
+                    promoted |= 1 << last_square;
+                    return false;
+                },
                 '1'..='8' => {file += c.to_digit(10).unwrap() as usize - 1;},
                 'k' => {squares[rank][file] = King(Black);},
                 'K' => {squares[rank][file] = King(White);},
@@ -207,6 +306,7 @@ impl Board{
                 ' ' => {return true;},
                 _ => {return false},
             }
+            last_square = rank * 8 + file;
             file += 1;
             if file > 7 && rank > 0 {
                 file = 0;
@@ -222,60 +322,183 @@ impl Board{
             }
             active_player = match c {
                 'b' => piece::Player::Black,
-                'w' | _ => piece::Player::White,
+                _ => piece::Player::White,
             };
             false
 
         });
-        //castling rights
-        let mut black_castle_rights = CastleRights {kingside: false, queenside: false};
-        let mut white_castle_rights = CastleRights {kingside: false, queenside: false};
-        chiter.any(|c| {
-            if c == ' ' {
-                return true;
-            }
-            match c {
-                'q' => {black_castle_rights.queenside = true;}
-                'k' => {black_castle_rights.kingside = true;},
-                'Q' => {white_castle_rights.queenside = true;},
-                'K' => {white_castle_rights.kingside = true;},
+        //castling rights. KQkq mean the outermost rook on that side, which covers chess960 x-fen too,
+        //and shredder-fen gives the rook's file as a letter
+        let mut black_castle_rights = CastleRights {kingside: None, queenside: None};
+        let mut white_castle_rights = CastleRights {kingside: None, queenside: None};
+        let castling: String = chiter.by_ref().take_while(|c| *c != ' ').collect();
+        for c in castling.chars() {
+            let player = if c.is_ascii_uppercase() { White } else { Black };
+            let back_rank = if player == White { 0 } else { 7 };
+            let king_file = match squares[back_rank].iter().position(|&p| p == King(player)) {
+                Some(f) => f,
+                None => continue,
+            };
+            let is_rook = |&f: &usize| squares[back_rank][f] == Rook(player);
+            let file = match c.to_ascii_lowercase() {
+                'k' => (king_file + 1..8).rev().find(is_rook),
+                'q' => (0..king_file).find(is_rook),
+                'a'..='h' => Some(c.to_ascii_lowercase() as usize - 'a' as usize),
+                _ => None,
+            };
+            let rights = if player == White { &mut white_castle_rights } else { &mut black_castle_rights };
+            match file {
+                Some(f) if f > king_file => rights.kingside = Some(f as i8 + 1),
+                Some(f) if f < king_file => rights.queenside = Some(f as i8 + 1),
                 _ => (),
             }
-            false
-        });
-        let last_move_pos = pos::Square::from_string(
-            &chiter.as_str()[0..2]
-        ).ok();
-        let last_move = if let Some(m) = last_move_pos {
-            Some(pos::Move{
-                old_pos: m - pos::Square {rank: 2, file: 0},
-                new_pos: m + pos::Square {rank: 1, file: 0},
+        }
+        //en passant square, rebuilt into the double pawn push that made it
+        let en_passant: String = chiter.by_ref().take_while(|c| *c != ' ').collect();
+        let pusher = active_player.invert();
+        let last_move = pos::Square::from_string(&en_passant).ok().map(|m| pos::Move{
+                old_pos: m - pos::Square {rank: pusher.sign(), file: 0},
+                new_pos: m + pos::Square {rank: pusher.sign(), file: 0},
                 move_type: pos::MoveType::Move,
-                piece: piece::Piece::Pawn(active_player.invert()),
-            })
-        } else {
-            None
-        };
-        chiter.any(|c| c == ' ');
+                piece: piece::Piece::Pawn(pusher),
+            });
         
-        let last_pawn_move: String = chiter.by_ref().take_while(|c| c.is_ascii_digit()).collect();
-        let last_pawn_move: u32 = last_pawn_move.parse().ok().unwrap_or(0);
+        let halfmove_clock: String = chiter.by_ref().take_while(|c| c.is_ascii_digit()).collect();
+        let halfmove_clock: u32 = halfmove_clock.parse().ok().unwrap_or(0);
 
         let move_count: String = chiter.by_ref().take_while(|d| d.is_ascii_digit()).collect();
         let move_count: u32 = move_count.parse().ok().unwrap_or(0) * 2 
             + if active_player == piece::Player::Black {1} else {0};
-        return Self {
+        let last_pawn_move = if last_move.is_some() {
+            move_count
+        } else {
+            move_count.saturating_sub(halfmove_clock)
+        };
+        let mut white_pocket: Pocket = [0; 5];
+        let mut black_pocket: Pocket = [0; 5];
+        for c in pocket.iter().flat_map(|p| p.chars()) {
+            let player = if c.is_ascii_uppercase() { White } else { Black };
+            let pocket = if player == White { &mut white_pocket } else { &mut black_pocket };
+            if let Some(i) = "pnbrq".find(c.to_ascii_lowercase()) {
+                pocket[i] += 1;
+            }
+        }
+        let mut board = Self {
             squares,
             white_pieces: pieces_generator(White, &squares),
             black_pieces: pieces_generator(Black, &squares),
+            variant: if pocket.is_some() {
+                Variant::Crazyhouse
+            } else if checks.is_some() {
+                Variant::ThreeCheck
+            } else {
+                Variant::Standard
+            },
+            white_checks: checks.map_or(0, |c| c.0),
+            black_checks: checks.map_or(0, |c| c.1),
+            white_pocket,
+            black_pocket,
+            promoted,
             last_move,
             move_count,
             last_pawn_move,
+            halfmove_clock,
             active_player,
             white_castle_rights,
             black_castle_rights,
+            accumulator: None,
+        };
+        board.refresh_accumulator();
+        board
+    }
+
+    //the position as a fen. castling rights on rooks that aren't in the corner are written shredder style by
+    //file, crazyhouse adds the pockets in brackets and a ~ after promoted pieces, and three-check adds
+    //the checks given on the end, all like lichess does
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for (file, p) in self.squares[rank].iter().enumerate() {
+                if *p == Piece::Empty {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    fen.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                fen.push(p.to_char_ascii());
+                if self.promoted & (1 << (rank * 8 + file)) != 0 {
+                    fen.push('~');
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+        if self.variant == Variant::Crazyhouse {
+            fen.push('[');
+            for (player, pocket) in [(Player::White, &self.white_pocket), (Player::Black, &self.black_pocket)] {
+                for (kind, &count) in POCKET_PIECES.iter().zip(pocket).rev() {
+                    for _ in 0..count {
+                        fen.push(kind(player).to_char_ascii());
+                    }
+                }
+            }
+            fen.push(']');
+        }
+        fen.push_str(if self.active_player == Player::White { " w " } else { " b " });
+        let mut castling = String::new();
+        for (player, rights) in [(Player::White, &self.white_castle_rights), (Player::Black, &self.black_castle_rights)] {
+            for (file, corner, letter) in [(rights.kingside, 8, 'k'), (rights.queenside, 1, 'q')] {
+                let c = match file {
+                    Some(f) if f == corner => letter,
+                    Some(f) => (b'a' + f as u8 - 1) as char,
+                    None => continue,
+                };
+                castling.push(if player == Player::White { c.to_ascii_uppercase() } else { c });
+            }
+        }
+        fen.push_str(if castling.is_empty() { "-" } else { &castling });
+        let en_passant = self.en_passant_square().map_or("-".to_owned(), |s| s.to_string());
+        fen.push_str(&format!(" {} {} {}", en_passant, self.halfmove_clock, (self.move_count / 2).max(1)));
+        if self.variant == Variant::ThreeCheck {
+            fen.push_str(&format!(" +{}+{}", self.white_checks, self.black_checks));
         }
+        fen
+    }
 
+    //a position with just these pieces, no castling or en passant. for building up endgames square by square
+    pub fn from_pieces(pieces: &[(Piece, pos::Square)], active_player: Player) -> Self {
+        let mut squares = [[Piece::Empty; 8]; 8];
+        for &(p, s) in pieces {
+            squares[s.get_rank() - 1][s.get_file() - 1] = p;
+        }
+        let mut board = Self {
+            squares,
+            white_pieces: pieces_generator(Player::White, &squares),
+            black_pieces: pieces_generator(Player::Black, &squares),
+            last_move: None,
+            move_count: 0,
+            last_pawn_move: 0,
+            halfmove_clock: 0,
+            active_player,
+            white_castle_rights: CastleRights {kingside: None, queenside: None},
+            black_castle_rights: CastleRights {kingside: None, queenside: None},
+            variant: Variant::Standard,
+            white_pocket: [0; 5],
+            black_pocket: [0; 5],
+            promoted: 0,
+            white_checks: 0,
+            black_checks: 0,
+            accumulator: None,
+        };
+        board.refresh_accumulator();
+        board
     }
 
     pub fn any_piece_attacking_except(&self, enemy: Player, square: pos::Square, except: pos::Square) -> bool {
@@ -283,27 +506,47 @@ impl Board{
         .iter()
         .filter(|piece| piece.pos != except)
         //if any enemy piece is atacking the where the king could be
-        .any(|enemy_piece| enemy_piece.is_attacking(&self, square))
+        .any(|enemy_piece| enemy_piece.is_attacking(self, square))
     }
 
     pub fn any_piece_attacking(&self, enemy: Player, square: pos::Square) -> bool {
         self.get_pieces(enemy)
         .iter()
         //if any enemy piece is atacking the where the king could be
-        .any(|enemy_piece| enemy_piece.is_attacking(&self, square))
+        .any(|enemy_piece| enemy_piece.is_attacking(self, square))
     }
-    pub fn has_castle_rights(&self, player: piece::Player, rook_pos: pos::Square) -> bool {
-        match rook_pos.file {
-            8 => match player {
-                piece::Player::White => self.white_castle_rights.kingside,
-                piece::Player::Black => self.black_castle_rights.kingside,
-            }
-            1 => match player {
-                piece::Player::White => self.white_castle_rights.queenside,
-                piece::Player::Black => self.black_castle_rights.queenside,
+    //where the rook that can still castle on that side is
+    pub fn castle_rook(&self, player: piece::Player, kingside: bool) -> Option::<pos::Square> {
+        let rights = self.castle_rights(player);
+        let file = if kingside { rights.kingside } else { rights.queenside }?;
+        Some(pos::Square { rank: if player == Player::White { 1 } else { 8 }, file })
+    }
+    fn castle_rights(&self, player: piece::Player) -> &CastleRights {
+        match player {
+            Player::White => &self.white_castle_rights,
+            Player::Black => &self.black_castle_rights,
+        }
+    }
+    //whether the king can walk to king_to without passing through check. the squares it crosses are looked at
+    //with the rook where it is, and where it lands with the rook already beside it, since in chess960 the
+    //rook can be in the way of an attack either before or after
+    pub fn castle_path_safe(&mut self, king_pos: pos::Square, king_to: pos::Square, rook_pos: pos::Square) -> bool {
+        let enemy = self.get(king_pos).owner().expect("Board Mangled").invert();
+        let dir = pos::Square { rank: 0, file: (king_to.file - king_pos.file).signum() };
+        let mut square = king_pos;
+        while square != king_to {
+            if self.square_attacked(enemy, square) {
+                return false;
             }
-            _ => false
+            square += dir;
         }
+        let rook_to = pos::Square { rank: rook_pos.rank, file: if rook_pos.file > king_pos.file { 6 } else { 4 } };
+        let rook = self.set(rook_pos, Piece::Empty);
+        let under = self.set(rook_to, rook);
+        let safe = !self.square_attacked(enemy, king_to);
+        self.set(rook_to, under);
+        self.set(rook_pos, rook);
+        safe
     }
 
     fn player_piece_at(&self, player_color: Player, pos: pos::Square) -> Option::<usize> {
@@ -327,7 +570,7 @@ impl Board{
                             .enumerate()
                             .for_each(|(j,p)| {
                                 let fill = if let Some(m) = &self.last_move{
-                                    if m.new_pos.rank == (i + 1) as i8 && m.new_pos.file == (j + 1) as i8 {true} else {false}
+                                    m.new_pos.rank == (i + 1) as i8 && m.new_pos.file == (j + 1) as i8
                                 }
                                else {
                                    false
@@ -365,7 +608,7 @@ impl Board{
                             .rev()
                             .for_each(|(j,p)| {
                                 let fill = if let Some(m) = &self.last_move{
-                                    if m.new_pos.rank == (i + 1) as i8 && m.new_pos.file == (j + 1) as i8 {true} else {false}
+                                    m.new_pos.rank == (i + 1) as i8 && m.new_pos.file == (j + 1) as i8
                                 }
                                 else{
                                     false
@@ -405,7 +648,7 @@ impl Board{
             }
             {
                 if let Some(last_moved_pawn) = &self.last_move {
-                    if (last_moved_pawn.new_pos - last_moved_pawn.old_pos).rank == 2 {
+                    if (last_moved_pawn.new_pos - last_moved_pawn.old_pos).rank.abs() == 2 {
                         end - pos::Square{rank: player.sign(), file: 0} == last_moved_pawn.new_pos
                     }
                     else{
@@ -421,10 +664,148 @@ impl Board{
             }
         }
     }
+    //the square behind a pawn that just moved 2 squares, if there is one
+    pub fn en_passant_square(&self) -> Option::<pos::Square> {
+        if self.last_pawn_move < self.move_count {
+            return None;
+        }
+        let last_move = self.last_move.as_ref()?;
+        match last_move.piece {
+            Piece::Pawn(_) if (last_move.new_pos - last_move.old_pos).rank.abs() == 2 => Some(pos::Square {
+                rank: (last_move.new_pos.rank + last_move.old_pos.rank) / 2,
+                file: last_move.new_pos.file,
+            }),
+            _ => None,
+        }
+    }
+    //false once a player is down to pawns and a king, where zugzwang makes passing look too good
+    pub fn has_non_pawn_material(&self, player_color: Player) -> bool {
+        self.get_pieces(player_color)
+            .iter()
+            .any(|p| !matches!(p.piece_type, Piece::Pawn(_) | Piece::King(_)))
+    }
+    //whether player could still mate by some series of legal moves, which decides if running out of time loses or draws.
+    //only the clear cases count as not enough: a lone king, one minor piece against a lone king, or nothing but bishops on one colour
+    pub fn has_mating_material(&self, player: Player) -> bool {
+        if self.variant != Variant::Standard {
+            return true;
+        }
+        let own = self.get_pieces(player);
+        let theirs = self.get_pieces(player.invert());
+        if own.iter().all(|p| matches!(p.piece_type, Piece::King(_))) {
+            return false;
+        }
+        if own.len() == 2 && theirs.len() == 1 && own.iter().any(|p| matches!(p.piece_type, Piece::Knight(_) | Piece::Bishop(_))) {
+            return false;
+        }
+        let everything = || own.iter().chain(theirs.iter());
+        let bishop_colour = |p: &&PieceState| matches!(p.piece_type, Piece::Bishop(_)).then_some((p.pos.rank + p.pos.file) % 2);
+        let only_bishops = everything().all(|p| matches!(p.piece_type, Piece::King(_) | Piece::Bishop(_)));
+        let colours: Vec::<i8> = everything().filter_map(|p| bishop_colour(&p)).collect();
+        !(only_bishops && colours.windows(2).all(|w| w[0] == w[1]))
+    }
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+        self.refresh_accumulator();
+    }
+    pub fn accumulator(&self) -> Option::<&nnue::Accumulator> {
+        self.accumulator.as_deref()
+    }
+    //the network only knows standard chess, other variants go without
+    pub fn refresh_accumulator(&mut self) {
+        self.accumulator = nnue::network()
+            .filter(|_| self.variant == Variant::Standard)
+            .map(|network| Box::new(nnue::Accumulator::new(network, self)));
+    }
+    pub fn get_pocket(&self, player: Player) -> &Pocket {
+        match player {
+            Player::White => &self.white_pocket,
+            Player::Black => &self.black_pocket,
+        }
+    }
+    fn get_pocket_mut(&mut self, player: Player) -> &mut Pocket {
+        match player {
+            Player::White => &mut self.white_pocket,
+            Player::Black => &mut self.black_pocket,
+        }
+    }
+    pub fn promoted_squares(&self) -> u64 {
+        self.promoted
+    }
+    pub fn checks_given(&self, player: Player) -> u8 {
+        match player {
+            Player::White => self.white_checks,
+            Player::Black => self.black_checks,
+        }
+    }
+    //crazyhouse bookkeeping before a move is made: the captured piece goes in the pocket, as a pawn if it
+    //was promoted, and the promoted marks follow the pieces
+    fn update_pockets(&mut self, m: &pos::Move) {
+        let captured = match m.move_type {
+            pos::MoveType::Capture(p) | pos::MoveType::CapturePromotion(p, _) => Some((p, m.new_pos)),
+            pos::MoveType::EnPassant => Some((Piece::Pawn(m.piece.owner().expect("Board Mangled").invert()), m.new_pos)),
+            _ => None,
+        };
+        let owner = m.piece.owner().expect("Board Mangled");
+        if let Some((p, square)) = captured {
+            let was_promoted = self.promoted & (1 << square.index()) != 0;
+            let index = if was_promoted { Some(0) } else { POCKET_PIECES.iter().position(|kind| kind(owner.invert()) == p) };
+            if let Some(i) = index {
+                self.get_pocket_mut(owner)[i] += 1;
+            }
+        }
+        let from = 1u64 << m.old_pos.index();
+        let to = 1u64 << m.new_pos.index();
+        let moved_promoted = self.promoted & from != 0;
+        self.promoted &= !(from | to);
+        if moved_promoted || m.promotion().is_some() {
+            self.promoted |= to;
+        }
+    }
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+    pub fn has_any_castle_rights(&self) -> bool {
+        [&self.white_castle_rights, &self.black_castle_rights]
+            .iter()
+            .any(|r| r.kingside.is_some() || r.queenside.is_some())
+    }
+    pub fn is_in_check(&self, player_color: Player) -> bool {
+        if self.variant == Variant::Antichess {
+            return false;
+        }
+        if self.variant == Variant::Atomic {
+            return match self.king_pos(player_color) {
+                Some(king) => self.square_attacked(player_color.invert(), king),
+                None => false,
+            };
+        }
+        !self.in_check(player_color).1.is_empty()
+    }
+    fn king_pos(&self, player: Player) -> Option::<pos::Square> {
+        self.get_pieces(player).iter().find(|s| s.piece_type == Piece::King(player)).map(|s| s.pos)
+    }
+    //whether the enemy could take on a square. in atomic kings never capture, and
+    //a square next to the enemy king cant be taken on without blowing that king up too
+    fn square_attacked(&self, enemy: Player, square: pos::Square) -> bool {
+        if self.variant != Variant::Atomic {
+            return self.any_piece_attacking(enemy, square);
+        }
+        if self.king_pos(enemy).is_some_and(|k| (k.rank - square.rank).abs() <= 1 && (k.file - square.file).abs() <= 1) {
+            return false;
+        }
+        self.get_pieces(enemy)
+            .iter()
+            .filter(|p| !matches!(p.piece_type, Piece::King(_)))
+            .any(|p| p.is_attacking(self, square))
+    }
     // moves a piece and updates its piece state in the piece oriented view
     fn move_piece(&mut self, owner: Player, old_pos: pos::Square, new_pos: pos::Square) {
         let index = self.player_piece_at(owner, old_pos).unwrap();
-        let mut state = &mut self.get_pieces_mut(owner)[index];
+        let state = &mut self.get_pieces_mut(owner)[index];
         state.pos = new_pos; 
         self.force_move(old_pos, new_pos);
     }
@@ -434,21 +815,14 @@ impl Board{
         //find king's position
         let king_pos = match self.get_pieces(player_color)
         .iter()
-        .find(|p| {
-            if let Piece::King(_) = p.piece_type {
-                true
-            } 
-            else {
-                false
-            }
-        }) {
+        .find(|p| matches!(p.piece_type, Piece::King(_))) {
             Some(king) => king.pos,
             None => {return (None, vec![])},
         };
         // any enemy pieces atacking king?
         let res_vec = self.get_pieces(player_color.invert())
         .iter()
-        .filter(|p| p.is_attacking(&self, king_pos) )
+        .filter(|p| p.is_attacking(self, king_pos) )
         .map(|p| p.pos)
         .collect::<std::vec::Vec::<pos::Square>>();
         (Some(king_pos), res_vec)
@@ -460,35 +834,42 @@ impl Board{
     fn set(&mut self, pos: pos::Square, p:Piece) -> Piece {
         let res = self.squares[pos.get_rank() - 1][pos.get_file() - 1];
         self.squares[pos.get_rank() - 1][pos.get_file() - 1] = p;
-        return res;
+        res
     }
     //makes a chess move, panics if not legal. Ensure move is legal with legal_move first!
     pub fn make_move(&mut self, valid_move: &pos::Move){
+        if self.variant == Variant::Crazyhouse {
+            self.update_pockets(valid_move);
+        }
         match valid_move.move_type {
+            pos::MoveType::Drop => {
+                let owner = valid_move.piece.owner().expect("Board Mangled");
+                let index = POCKET_PIECES.iter().position(|kind| kind(owner) == valid_move.piece).expect("Board Mangled");
+                self.get_pocket_mut(owner)[index] -= 1;
+                self.set(valid_move.new_pos, valid_move.piece);
+                self.get_pieces_mut(owner).push(PieceState { piece_type: valid_move.piece, pos: valid_move.new_pos });
+            }
             pos::MoveType::Capture(captured) => {
                 let owner = valid_move.piece.owner().unwrap();
-                let captured_pos = self.get_pieces(captured.owner().expect("Board Mangled"))
-                    .iter()
-                    .position(|p| {
-                        p.pos == valid_move.new_pos
-                    }).expect("Board Mangled");
-                
-                self.get_pieces_mut(captured.owner().expect("Board Mangled"))
-                    .swap_remove(captured_pos);
-                    
+                self.remove_captured(captured, valid_move.new_pos);
                 self.move_piece(owner, valid_move.old_pos, valid_move.new_pos);
             }
             pos::MoveType::Castle(rook_pos) => {
-                let dir = pos::Square{
-                    rank: 0,
-                    file: if rook_pos.file == 8 {1} else {-1},
-                };
+                //the rook ends up next to the king on the inside, f or d. in chess960 either piece can
+                //land where the other started, so both come off the board before going back on
                 let owner = valid_move.piece.owner().expect("Board Mangled");
-
-                //move rook
-                self.move_piece(owner, rook_pos, valid_move.new_pos - dir);
-                //move king
-                self.move_piece(owner, valid_move.old_pos, valid_move.new_pos);
+                let rook_to = pos::Square {
+                    rank: rook_pos.rank,
+                    file: if rook_pos.file > valid_move.old_pos.file {6} else {4},
+                };
+                let king_index = self.player_piece_at(owner, valid_move.old_pos).expect("Board Mangled");
+                let rook_index = self.player_piece_at(owner, rook_pos).expect("Board Mangled");
+                self.set(valid_move.old_pos, Piece::Empty);
+                self.set(rook_pos, Piece::Empty);
+                self.set(valid_move.new_pos, Piece::King(owner));
+                self.set(rook_to, Piece::Rook(owner));
+                self.get_pieces_mut(owner)[king_index].pos = valid_move.new_pos;
+                self.get_pieces_mut(owner)[rook_index].pos = rook_to;
             }
             pos::MoveType::EnPassant => {
                 let owner = valid_move.piece.owner().expect("Board Mangled");
@@ -514,7 +895,10 @@ impl Board{
                 let owner = valid_move.piece.owner().expect("Board Mangled");
                 self.move_piece(owner, valid_move.old_pos, valid_move.new_pos);
             },
-            pos::MoveType::Promotion(to_piece) => {
+            pos::MoveType::Promotion(to_piece) | pos::MoveType::CapturePromotion(_, to_piece) => {
+                if let pos::MoveType::CapturePromotion(captured, _) = valid_move.move_type {
+                    self.remove_captured(captured, valid_move.new_pos);
+                }
                 let owner = valid_move.piece.owner().expect("Board Mangled");
                 let pawn_index = self.player_piece_at(owner, valid_move.old_pos).expect("Board Mangled");
                 let pawn_state = &mut self.get_pieces_mut(owner)[pawn_index];
@@ -524,12 +908,129 @@ impl Board{
                 self.set(valid_move.old_pos, piece::Piece::Empty);
             },
         }
+        self.update_accumulator(valid_move);
+        if self.variant == Variant::Atomic && valid_move.is_capture() {
+            self.explode(valid_move.new_pos);
+        }
+        self.update_castle_rights(valid_move);
         self.move_count += 1;
-        self.last_move = Some(valid_move.clone());
+        let zeroing = matches!(valid_move.piece, piece::Piece::Pawn(_))
+            || matches!(valid_move.move_type, pos::MoveType::Capture(_) | pos::MoveType::CapturePromotion(..));
+        self.halfmove_clock = if zeroing { 0 } else { self.halfmove_clock + 1 };
+        self.last_move = Some(*valid_move);
         if let piece::Piece::Pawn(_) = valid_move.piece {
             self.last_pawn_move = self.move_count;
         }
         self.active_player = self.active_player.invert();
+        if self.variant == Variant::ThreeCheck && self.is_in_check(self.active_player) {
+            match self.active_player {
+                Player::White => self.black_checks += 1,
+                Player::Black => self.white_checks += 1,
+            }
+        }
+    }
+    //moves the pieces in the network's accumulator the way the move moved them on the board.
+    //the king isn't a feature, a king move means starting that side's point of view over
+    fn update_accumulator(&mut self, m: &pos::Move) {
+        let mut accumulator = match self.accumulator.take() {
+            Some(a) => a,
+            None => return,
+        };
+        let owner = m.piece.owner().expect("Board Mangled");
+        let nothing = (Piece::Empty, m.new_pos);
+        let (removed, added) = match m.move_type {
+            pos::MoveType::Move => ([(m.piece, m.old_pos), nothing], [(m.piece, m.new_pos), nothing]),
+            pos::MoveType::Capture(captured) => ([(m.piece, m.old_pos), (captured, m.new_pos)], [(m.piece, m.new_pos), nothing]),
+            pos::MoveType::EnPassant => {
+                let captured_square = m.new_pos - pos::Square { rank: owner.sign(), file: 0 };
+                ([(m.piece, m.old_pos), (Piece::Pawn(owner.invert()), captured_square)], [(m.piece, m.new_pos), nothing])
+            }
+            pos::MoveType::Promotion(to_piece) => ([(m.piece, m.old_pos), nothing], [(to_piece, m.new_pos), nothing]),
+            pos::MoveType::CapturePromotion(captured, to_piece) => {
+                ([(m.piece, m.old_pos), (captured, m.new_pos)], [(to_piece, m.new_pos), nothing])
+            }
+            pos::MoveType::Castle(rook_pos) => {
+                let rook_to = pos::Square { rank: rook_pos.rank, file: if rook_pos.file > m.old_pos.file {6} else {4} };
+                ([(Piece::Rook(owner), rook_pos), nothing], [(Piece::Rook(owner), rook_to), nothing])
+            }
+            pos::MoveType::Drop => ([nothing, nothing], [(m.piece, m.new_pos), nothing]),
+        };
+        for perspective in [Player::White, Player::Black] {
+            if perspective == owner && m.piece == Piece::King(owner) {
+                accumulator.refresh(self, perspective);
+            } else {
+                accumulator.update(self, perspective, &removed, &added);
+            }
+        }
+        self.accumulator = Some(accumulator);
+    }
+    //takes a captured piece out of the piece oriented view, the square gets overwritten by the capturing piece
+    fn remove_captured(&mut self, captured: Piece, square: pos::Square) {
+        let owner = captured.owner().expect("Board Mangled");
+        let captured_pos = self.player_piece_at(owner, square).expect("Board Mangled");
+        self.get_pieces_mut(owner).swap_remove(captured_pos);
+    }
+    //castling is gone for good once the king or that side's rook moves, or the rook gets captured
+    fn update_castle_rights(&mut self, m: &pos::Move) {
+        if let Piece::King(p) = m.piece {
+            let rights = match p {
+                Player::White => &mut self.white_castle_rights,
+                Player::Black => &mut self.black_castle_rights,
+            };
+            rights.kingside = None;
+            rights.queenside = None;
+        }
+        for square in [m.old_pos, m.new_pos] {
+            self.rook_gone(square);
+        }
+    }
+    //no more castling with a rook that was on this square
+    fn rook_gone(&mut self, square: pos::Square) {
+        let rights = match square.rank {
+            1 => &mut self.white_castle_rights,
+            8 => &mut self.black_castle_rights,
+            _ => return,
+        };
+        if rights.kingside == Some(square.file) {
+            rights.kingside = None;
+        }
+        if rights.queenside == Some(square.file) {
+            rights.queenside = None;
+        }
+    }
+    //atomic captures blow up the capturing piece and every piece around it that isnt a pawn, kings included
+    fn explode(&mut self, center: pos::Square) {
+        for rank in center.rank - 1..=center.rank + 1 {
+            for file in center.file - 1..=center.file + 1 {
+                let square = pos::Square { rank, file };
+                if !(1..=8).contains(&rank) || !(1..=8).contains(&file) {
+                    continue;
+                }
+                let p = self.get(square);
+                let owner = match p.owner() {
+                    Some(owner) if square == center || !matches!(p, Piece::Pawn(_)) => owner,
+                    _ => continue,
+                };
+                let index = self.player_piece_at(owner, square).expect("Board Mangled");
+                self.get_pieces_mut(owner).swap_remove(index);
+                self.set(square, Piece::Empty);
+                self.rook_gone(square);
+                if let Piece::King(_) = p {
+                    let rights = match owner {
+                        Player::White => &mut self.white_castle_rights,
+                        Player::Black => &mut self.black_castle_rights,
+                    };
+                    rights.kingside = None;
+                    rights.queenside = None;
+                }
+            }
+        }
+    }
+    //passes the turn without moving, used by null move pruning
+    pub fn make_null_move(&mut self) {
+        self.move_count += 1;
+        self.halfmove_clock += 1;
+        self.active_player = self.active_player.invert();
     }
     //for seeing if the king is in check after a move
     pub fn king_safe_after_move(&mut self, king_pos: pos::Square, temp_move: pos::Move) -> Option::<pos::Move>{
@@ -537,18 +1038,14 @@ impl Board{
         let king_safe = match temp_move.move_type {
            Castle(_) => {return Some(temp_move);},
            EnPassant => {
+               let captured_square = temp_move.new_pos - pos::Square{rank: temp_move.piece.owner().unwrap().sign(), file: 0};
                self.force_move(temp_move.old_pos, temp_move.new_pos);
-               let captured_pawn = self.set(
-                   temp_move.new_pos - pos::Square{rank: temp_move.piece.owner().unwrap().sign(), file: 0}, 
-                    piece::Piece::Empty
-                );
+               let captured_pawn = self.set(captured_square, piece::Piece::Empty);
 
-                let king_safe = !self.any_piece_attacking(self.active_player().invert(), king_pos);
+                //the captured pawn is still in the piece list, so leave it out
+                let king_safe = !self.any_piece_attacking_except(self.active_player().invert(), king_pos, captured_square);
                 self.force_move(temp_move.new_pos, temp_move.old_pos);
-                self.set(
-                    temp_move.new_pos - pos::Square{rank: temp_move.piece.owner().unwrap().sign(), file: 0}, 
-                    captured_pawn
-                );
+                self.set(captured_square, captured_pawn);
                 king_safe
             },
            _ => {
@@ -575,7 +1072,7 @@ impl Board{
     fn force_move(&mut self, old_pos: pos::Square, new_pos: pos::Square) -> Piece{
         let old_piece = self.get(old_pos);
         self.set(old_pos, Piece::Empty);
-        return self.set(new_pos, old_piece);
+        self.set(new_pos, old_piece)
     }
     pub fn get_pieces(&self, pc: Player) -> &Vec::<PieceState> {
         match pc {
@@ -592,4 +1089,38 @@ impl Board{
     pub fn active_player(&self) -> piece::Player {
         self.active_player
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn three_check_fen_round_trips() {
+        let fen = "rnbqkbnr/ppp2ppp/8/3pp3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3 +2+1";
+        let board = Board::from_fen(fen);
+        assert_eq!(board.variant(), Variant::ThreeCheck);
+        assert_eq!(board.to_fen(), fen);
+        //checks left where the clocks go reads the same, and comes back out as checks given
+        let board = Board::from_fen("rnbqkbnr/ppp2ppp/8/3pp3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1+2 0 3");
+        assert_eq!(board.to_fen(), fen);
+    }
+
+    #[test]
+    fn crazyhouse_fen_round_trips() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+            "2k5/8/8/8/8/8/8/4K3[QRBNPPqrbnp] w - - 0 1",
+            "4k3/1Q~6/8/8/4b3/8/Kpp5/8[Nn] b - - 0 1",
+        ] {
+            let board = Board::from_fen(fen);
+            assert_eq!(board.variant(), Variant::Crazyhouse);
+            assert_eq!(board.to_fen(), fen);
+        }
+        //the ninth rank way of writing pockets comes back out in brackets
+        assert_eq!(
+            Board::from_fen("4k3/1Q~6/8/8/4b3/8/Kpp5/8/Nn b - - 0 1").to_fen(),
+            "4k3/1Q~6/8/8/4b3/8/Kpp5/8[Nn] b - - 0 1"
+        );
+    }
+}
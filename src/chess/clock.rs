@@ -0,0 +1,343 @@
+use crate::chess::piece::Player;
+
+use std::time::{Duration, Instant};
+
+// how far ahead moves_to_go looks for the next time to be added
+const MAX_STAGE_MOVES: u32 = 1000;
+
+//what a player gets back for each move
+#[derive(Clone, Copy, PartialEq)]
+pub enum Bonus {
+    None,
+    // fischer, added after every move
+    Increment(Duration),
+    // simple delay, the clock only starts once this much time has passed
+    Delay(Duration),
+    // bronstein delay, time used is given back up to this much
+    Bronstein(Duration),
+}
+
+//one part of a time control, like 40 moves in 90 minutes. no move count means the rest of the game
+#[derive(Clone, Copy)]
+pub struct Stage {
+    pub moves: Option::<u32>,
+    pub time: Duration,
+    pub bonus: Bonus,
+}
+
+//stages are played in order, and if the last one has a move count it starts over each time it's done
+#[derive(Clone)]
+pub struct TimeControl {
+    pub stages: Vec::<Stage>,
+}
+
+fn parse_seconds(s: &str, scale: f64) -> Result::<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|n| *n >= 0.0 && n.is_finite())
+        .map(|n| Duration::from_secs_f64(n * scale))
+        .ok_or_else(|| format!("bad time {}", s))
+}
+
+//h:mm:ss the way %clk comments have it, with tenths once there's under ten seconds left
+pub fn format_clock(time: Duration) -> String {
+    let secs = time.as_secs();
+    let base = format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    if time < Duration::from_secs(10) {
+        format!("{}.{}", base, time.subsec_millis() / 100)
+    } else {
+        base
+    }
+}
+
+impl TimeControl {
+    // [moves/]minutes[+increment | d delay | b bronstein delay], stages split by :
+    // like 5+3, 3d2, 40/90+30:30+30 or 40/120:20/60:30
+    pub fn parse(text: &str) -> Result::<Self, String> {
+        let stages = text.split(':').map(|stage| {
+            let (moves, rest) = match stage.split_once('/') {
+                Some((m, rest)) => (Some(m.parse::<u32>().ok().filter(|&m| m > 0).ok_or_else(|| format!("bad move count {}", m))?), rest),
+                None => (None, stage),
+            };
+            let (time, bonus) = match rest.find(['+', 'd', 'b']) {
+                Some(i) => {
+                    let extra = parse_seconds(&rest[i + 1..], 1.0)?;
+                    (&rest[..i], match &rest[i..i + 1] {
+                        "+" => Bonus::Increment(extra),
+                        "d" => Bonus::Delay(extra),
+                        _ => Bonus::Bronstein(extra),
+                    })
+                }
+                None => (rest, Bonus::None),
+            };
+            Ok(Stage { moves, time: parse_seconds(time, 60.0)?, bonus })
+        }).collect::<Result::<Vec::<Stage>, String>>()?;
+        Ok(Self { stages })
+    }
+
+    //which stage a player is in after making this many moves
+    fn stage_at(&self, moves_made: u32) -> usize {
+        let mut moves_made = moves_made;
+        for (i, stage) in self.stages.iter().enumerate() {
+            match stage.moves {
+                Some(m) if moves_made >= m && i + 1 < self.stages.len() => moves_made -= m,
+                _ => return i,
+            }
+        }
+        self.stages.len() - 1
+    }
+
+    //time added to the clock when the move that finishes a stage is made, zero otherwise
+    fn time_added(&self, moves_made: u32) -> Duration {
+        let mut start = 0;
+        for (i, stage) in self.stages.iter().enumerate() {
+            let m = match stage.moves {
+                Some(m) => m,
+                None => return Duration::ZERO,
+            };
+            let last = i + 1 == self.stages.len();
+            if last {
+                //the last stage repeats
+                return if moves_made > start && (moves_made - start).is_multiple_of(m) { stage.time } else { Duration::ZERO };
+            }
+            if moves_made == start + m {
+                return self.stages[i + 1].time;
+            }
+            start += m;
+        }
+        Duration::ZERO
+    }
+}
+
+//the pgn TimeControl tag, in seconds. delays arent in the standard so they're written the way we read them
+impl std::fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let stages: Vec::<String> = self.stages.iter().map(|s| {
+            let moves = s.moves.map_or(String::new(), |m| format!("{}/", m));
+            let bonus = match s.bonus {
+                Bonus::None => String::new(),
+                Bonus::Increment(t) => format!("+{}", t.as_secs_f64()),
+                Bonus::Delay(t) => format!("d{}", t.as_secs_f64()),
+                Bonus::Bronstein(t) => format!("b{}", t.as_secs_f64()),
+            };
+            format!("{}{}{}", moves, s.time.as_secs_f64(), bonus)
+        }).collect();
+        write!(f, "{}", stages.join(":"))
+    }
+}
+
+//a chess clock for both players. only the player to move has their time running
+#[derive(Clone)]
+pub struct Clock {
+    control: TimeControl,
+    white: Duration,
+    black: Duration,
+    white_moves: u32,
+    black_moves: u32,
+    // whose clock is running, and since when
+    running: Option::<(Player, Instant)>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        let start = control.stages[0].time;
+        Self {
+            control,
+            white: start,
+            black: start,
+            white_moves: 0,
+            black_moves: 0,
+            running: None,
+        }
+    }
+
+    pub fn control(&self) -> &TimeControl {
+        &self.control
+    }
+
+    fn slot(&mut self, player: Player) -> (&mut Duration, &mut u32) {
+        match player {
+            Player::White => (&mut self.white, &mut self.white_moves),
+            Player::Black => (&mut self.black, &mut self.black_moves),
+        }
+    }
+
+    fn bonus(&self, player: Player) -> Bonus {
+        let moves = match player {
+            Player::White => self.white_moves,
+            Player::Black => self.black_moves,
+        };
+        self.control.stages[self.control.stage_at(moves)].bonus
+    }
+
+    //how much of the thinking time so far comes off the clock
+    fn charged(&self, player: Player, thought: Duration) -> Duration {
+        match self.bonus(player) {
+            Bonus::Delay(delay) => thought.saturating_sub(delay),
+            _ => thought,
+        }
+    }
+
+    //the fischer increment the player gets right now, for telling engines about it
+    pub fn increment(&self, player: Player) -> Duration {
+        match self.bonus(player) {
+            Bonus::Increment(inc) => inc,
+            _ => Duration::ZERO,
+        }
+    }
+
+    //moves until more time is added, none when no more is coming
+    pub fn moves_to_go(&self, player: Player) -> Option::<u32> {
+        let moves = match player {
+            Player::White => self.white_moves,
+            Player::Black => self.black_moves,
+        };
+        (moves + 1..=moves + MAX_STAGE_MOVES).find(|&m| self.control.time_added(m) > Duration::ZERO).map(|m| m - moves)
+    }
+
+    pub fn start(&mut self, player: Player) {
+        self.running = Some((player, Instant::now()));
+    }
+
+    //time left right now, counting the thinking time of whoever is on move
+    pub fn remaining(&self, player: Player) -> Duration {
+        let left = match player {
+            Player::White => self.white,
+            Player::Black => self.black,
+        };
+        match self.running {
+            Some((p, since)) if p == player => left.saturating_sub(self.charged(player, since.elapsed())),
+            _ => left,
+        }
+    }
+
+    pub fn flagged(&self, player: Player) -> bool {
+        self.remaining(player) == Duration::ZERO
+    }
+
+    //the player to move finished their move. false if their time ran out before they did,
+    //otherwise bonuses are added, the next stage's time if one just ended, and the other clock starts
+    pub fn press(&mut self, player: Player) -> bool {
+        let thought = match self.running {
+            Some((p, since)) if p == player => since.elapsed(),
+            _ => Duration::ZERO,
+        };
+        let charged = self.charged(player, thought);
+        let bonus = self.bonus(player);
+        let control = self.control.clone();
+        let (left, moves) = self.slot(player);
+        if charged >= *left {
+            *left = Duration::ZERO;
+            self.running = None;
+            return false;
+        }
+        *left -= charged;
+        *left += match bonus {
+            Bonus::Increment(inc) => inc,
+            Bonus::Bronstein(delay) => thought.min(delay),
+            _ => Duration::ZERO,
+        };
+        *moves += 1;
+        *left += control.time_added(*moves);
+        self.start(player.invert());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //the player to move has been thinking for this long
+    fn think(clock: &mut Clock, player: Player, secs: f64) {
+        let since = Instant::now().checked_sub(Duration::from_secs_f64(secs)).expect("uptime");
+        clock.running = Some((player, since));
+    }
+
+    fn assert_near(left: Duration, secs: f64) {
+        assert!((left.as_secs_f64() - secs).abs() < 0.5, "{:?} left, expected {}s", left, secs);
+    }
+
+    #[test]
+    fn parses_each_format() {
+        let control = TimeControl::parse("5+3").unwrap();
+        assert_eq!(control.stages.len(), 1);
+        assert_eq!((control.stages[0].moves, control.stages[0].time), (None, Duration::from_secs(300)));
+        assert!(control.stages[0].bonus == Bonus::Increment(Duration::from_secs(3)));
+
+        let control = TimeControl::parse("3d2").unwrap();
+        assert_eq!(control.stages[0].time, Duration::from_secs(180));
+        assert!(control.stages[0].bonus == Bonus::Delay(Duration::from_secs(2)));
+
+        let control = TimeControl::parse("1b1.5").unwrap();
+        assert!(control.stages[0].bonus == Bonus::Bronstein(Duration::from_millis(1500)));
+
+        let control = TimeControl::parse("40/90+30:30+30").unwrap();
+        assert_eq!(control.stages.len(), 2);
+        assert_eq!((control.stages[0].moves, control.stages[0].time), (Some(40), Duration::from_secs(5400)));
+        assert_eq!((control.stages[1].moves, control.stages[1].time), (None, Duration::from_secs(1800)));
+        assert!(control.stages[1].bonus == Bonus::Increment(Duration::from_secs(30)));
+        assert_eq!(control.to_string(), "40/5400+30:1800+30");
+
+        let control = TimeControl::parse("40/120:20/60:30").unwrap();
+        assert_eq!(control.stages.iter().map(|s| s.moves).collect::<Vec::<_>>(), vec![Some(40), Some(20), None]);
+        assert!(control.stages.iter().all(|s| s.bonus == Bonus::None));
+    }
+
+    #[test]
+    fn rejects_malformed_controls() {
+        for text in ["", "abc", "5+", "5+x", "-1", "0/5", "x/5", "40/", "5:", "5+3d1"] {
+            assert!(TimeControl::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn increment_and_delays() {
+        //fischer, the increment comes on top of whatever was used
+        let mut clock = Clock::new(TimeControl::parse("1+2").unwrap());
+        think(&mut clock, Player::White, 10.0);
+        assert!(clock.press(Player::White));
+        assert_near(clock.remaining(Player::White), 52.0);
+        assert_eq!(clock.increment(Player::White), Duration::from_secs(2));
+
+        //simple delay, nothing comes off until the delay has gone by
+        let mut clock = Clock::new(TimeControl::parse("1d5").unwrap());
+        think(&mut clock, Player::White, 3.0);
+        assert_near(clock.remaining(Player::White), 60.0);
+        assert!(clock.press(Player::White));
+        assert_near(clock.remaining(Player::White), 60.0);
+        think(&mut clock, Player::Black, 8.0);
+        assert!(clock.press(Player::Black));
+        assert_near(clock.remaining(Player::Black), 57.0);
+
+        //bronstein, the time used is given back up to the delay
+        let mut clock = Clock::new(TimeControl::parse("1b5").unwrap());
+        think(&mut clock, Player::White, 3.0);
+        assert!(clock.press(Player::White));
+        assert_near(clock.remaining(Player::White), 60.0);
+        think(&mut clock, Player::Black, 8.0);
+        assert!(clock.press(Player::Black));
+        assert_near(clock.remaining(Player::Black), 57.0);
+    }
+
+    #[test]
+    fn flags_and_stages() {
+        let mut clock = Clock::new(TimeControl::parse("0.1+5").unwrap());
+        think(&mut clock, Player::White, 10.0);
+        assert!(clock.flagged(Player::White));
+        assert!(!clock.press(Player::White));
+        assert_eq!(clock.remaining(Player::White), Duration::ZERO);
+
+        //the next stage's time comes when the move that ends a stage is made, and the last one repeats
+        let mut clock = Clock::new(TimeControl::parse("2/1:1/1").unwrap());
+        assert_eq!(clock.moves_to_go(Player::White), Some(2));
+        for expected in [59.0, 118.0, 177.0] {
+            think(&mut clock, Player::White, 1.0);
+            assert!(clock.press(Player::White));
+            assert_near(clock.remaining(Player::White), expected);
+            assert!(clock.press(Player::Black));
+        }
+        assert_eq!(clock.moves_to_go(Player::White), Some(1));
+        assert_near(clock.remaining(Player::Black), 180.0);
+    }
+}
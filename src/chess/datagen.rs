@@ -0,0 +1,239 @@
+use crate::chess::piece::{Piece, Player};
+use crate::chess::tournament::{adjudicate, Opening};
+use crate::chess::{board, ordering, search, validator, Game, GameResult};
+
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// a side this far ahead for RESIGN_PLIES plies in a row wins, the rest of the game would only be mopping up
+const RESIGN_SCORE: i32 = 1500;
+const RESIGN_PLIES: usize = 8;
+// openings that come out of the random moves more lopsided than this get thrown away
+const MAX_OPENING_SCORE: i32 = 400;
+// games still going this long are drawn
+const MAX_PLIES: usize = 400;
+// starts thrown away in a row before giving up on the openings
+const MAX_TRIES: usize = 100;
+// hash per thread when none is given, the searches are shallow
+const DEFAULT_HASH_MB: usize = 16;
+
+pub struct DatagenSettings {
+    pub out: String,
+    pub games: usize,
+    pub threads: usize,
+    pub depth: i32,
+    // random moves played on top of the opening
+    pub random_plies: usize,
+    pub openings: Vec::<Opening>,
+    pub hash_mb: usize,
+}
+
+//a position worth keeping, with the search's score from white's side until the result is known
+struct Sample {
+    board: board::Board,
+    score: i32,
+}
+
+//xorshift, seeded from std's random hasher keys so every thread gets its own games
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Self {
+        Self(std::collections::hash_map::RandomState::new().build_hasher().finish() | 1)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+// binary records are 32 bytes:
+//   u64   occupied squares, bit 0 is a1 and bit 63 h8
+//   16    a nibble for each occupied square in bit order, low nibble first: pawn, knight, bishop, rook,
+//         queen, king as 0 to 5, plus 8 for black
+//   u8    side to move, 0 white 1 black
+//   u8    result, 0 black won, 1 draw, 2 white won
+//   i16   search score in centipawns, from white's side
+//   u8    halfmove clock
+//   u8    en passant square, 64 for none
+//   2     unused
+// numbers are little endian. text lines are "<fen> | <score> | <result>" with the same score and a result
+// of 1.0, 0.5 or 0.0 for white, which the tuner reads as is
+fn binary_record(sample: &Sample, result: f64) -> [u8; 32] {
+    let mut record = [0u8; 32];
+    let mut occupied = 0u64;
+    let mut nibbles = Vec::<(usize, u8)>::new();
+    for player in [Player::White, Player::Black] {
+        for state in sample.board.get_pieces(player) {
+            let square = (state.pos.rank as usize - 1) * 8 + state.pos.file as usize - 1;
+            let kind = match state.piece_type {
+                Piece::Pawn(_) => 0,
+                Piece::Knight(_) => 1,
+                Piece::Bishop(_) => 2,
+                Piece::Rook(_) => 3,
+                Piece::Queen(_) => 4,
+                Piece::King(_) => 5,
+                Piece::Empty => continue,
+            };
+            occupied |= 1 << square;
+            nibbles.push((square, kind | if player == Player::Black { 8 } else { 0 }));
+        }
+    }
+    nibbles.sort_unstable();
+    record[..8].copy_from_slice(&occupied.to_le_bytes());
+    for (i, (_, nibble)) in nibbles.iter().take(32).enumerate() {
+        record[8 + i / 2] |= nibble << (4 * (i % 2));
+    }
+    record[24] = (sample.board.active_player() == Player::Black) as u8;
+    record[25] = (result * 2.0) as u8;
+    record[26..28].copy_from_slice(&(sample.score as i16).to_le_bytes());
+    record[28] = sample.board.halfmove_clock().min(255) as u8;
+    record[29] = sample.board.en_passant_square().map_or(64, |s| ((s.rank - 1) * 8 + s.file - 1) as u8);
+    record
+}
+
+//a start position: the opening, then random legal moves. none if the game ended on the way there
+fn random_start(opening: &Opening, random_plies: usize, rng: &mut Rng) -> Option::<Game> {
+    let mut game = Game::from_fen(&opening.fen);
+    for m in &opening.moves {
+        game.play_move(m).ok()?;
+    }
+    for _ in 0..random_plies {
+        let moves = validator::get_possible_moves(&mut game.board);
+        if moves.is_empty() {
+            return None;
+        }
+        let m = moves[rng.below(moves.len())];
+        game.record_move(&m, None);
+    }
+    game.result().is_none().then_some(game)
+}
+
+//one self play game, the samples it kept and white's score at the end
+fn play_game(
+    searcher: &mut search::Searcher,
+    opening: &Opening,
+    settings: &DatagenSettings,
+    rng: &mut Rng,
+) -> Option::<(Vec::<Sample>, f64)> {
+    let mut game = random_start(opening, settings.random_plies, rng)?;
+    let mut samples = Vec::<Sample>::new();
+    let mut seen = HashMap::<u64, u32>::new();
+    let mut winning = (Player::White, 0);
+    for ply in 0..MAX_PLIES {
+        adjudicate(&mut game, &mut seen);
+        if let Some(result) = game.result() {
+            let score = match result {
+                GameResult::Win(Player::White, _) => 1.0,
+                GameResult::Win(Player::Black, _) => 0.0,
+                GameResult::Draw => 0.5,
+            };
+            return Some((samples, score));
+        }
+        let player = game.board.active_player();
+        searcher.set_history(game.history());
+        let result = searcher.search(&game.board, settings.depth);
+        let best = result.best_move.expect("a game that isn't over has a move");
+        if ply == 0 && result.score.abs() > MAX_OPENING_SCORE {
+            return None;
+        }
+        //in check or about to take something, the static eval of these is noise
+        let quiet = !game.board.is_in_check(player) && ordering::is_quiet(&best) && result.score.abs() < search::TB_BOUND;
+        if quiet {
+            samples.push(Sample { board: game.board.clone(), score: result.score * player.sign() as i32 });
+        }
+        winning = match winning {
+            (side, plies) if result.score >= RESIGN_SCORE && side == player => (side, plies + 1),
+            _ if result.score >= RESIGN_SCORE => (player, 1),
+            (side, plies) if result.score <= -RESIGN_SCORE && side != player => (side, plies + 1),
+            _ if result.score <= -RESIGN_SCORE => (player.invert(), 1),
+            _ => (player, 0),
+        };
+        if winning.1 >= RESIGN_PLIES {
+            return Some((samples, if winning.0 == Player::White { 1.0 } else { 0.0 }));
+        }
+        game.record_move(&best, None);
+    }
+    Some((samples, 0.5))
+}
+
+//plays self play games on every thread and writes the quiet positions out, binary for a .bin file and text otherwise
+pub fn run(settings: &DatagenSettings) -> Result::<(), String> {
+    let binary = settings.out.to_ascii_lowercase().ends_with(".bin");
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&settings.out)
+        .map_err(|e| format!("couldn't open {}: {}", settings.out, e))?;
+    let out = Mutex::new(std::io::BufWriter::new(file));
+    let default_opening = Opening { fen: board::Board::new().to_fen(), moves: vec![] };
+    let next = AtomicUsize::new(0);
+    // games finished and positions written
+    let done = Mutex::new((0usize, 0usize));
+    let errors = Mutex::new(Vec::<String>::new());
+    let options = search::SearchOptions {
+        threads: 1,
+        hash_mb: if settings.hash_mb > 0 { settings.hash_mb } else { DEFAULT_HASH_MB },
+        ..search::SearchOptions::default()
+    };
+
+    std::thread::scope(|scope| {
+        for _ in 0..settings.threads.clamp(1, settings.games.max(1)) {
+            scope.spawn(|| {
+                let mut searcher = search::Searcher::new(options);
+                let mut rng = Rng::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= settings.games {
+                        return;
+                    }
+                    //lopsided or finished starts get another go from a fresh opening, a few times at most
+                    let played = (0..MAX_TRIES).find_map(|_| {
+                        let opening = match settings.openings.len() {
+                            0 => &default_opening,
+                            n => &settings.openings[rng.below(n)],
+                        };
+                        play_game(&mut searcher, opening, settings, &mut rng)
+                    });
+                    let (samples, result) = match played {
+                        Some(p) => p,
+                        None => {
+                            errors.lock().expect("error lock").push("no playable openings".to_owned());
+                            return;
+                        }
+                    };
+                    let mut bytes = Vec::<u8>::new();
+                    for sample in &samples {
+                        if binary {
+                            bytes.extend_from_slice(&binary_record(sample, result));
+                        } else {
+                            bytes.extend(format!("{} | {} | {:.1}\n", sample.board.to_fen(), sample.score, result).bytes());
+                        }
+                    }
+                    if let Err(e) = out.lock().expect("output lock").write_all(&bytes) {
+                        errors.lock().expect("error lock").push(format!("couldn't write {}: {}", settings.out, e));
+                        return;
+                    }
+                    let mut done = done.lock().expect("progress lock");
+                    done.0 += 1;
+                    done.1 += samples.len();
+                    if done.0.is_multiple_of(10) || done.0 == settings.games {
+                        println!("{} of {} games, {} positions", done.0, settings.games, done.1);
+                    }
+                }
+            });
+        }
+    });
+
+    out.into_inner().expect("output lock").flush().map_err(|e| format!("couldn't write {}: {}", settings.out, e))?;
+    match errors.into_inner().expect("error lock").first() {
+        Some(msg) => Err(msg.clone()),
+        None => Ok(()),
+    }
+}
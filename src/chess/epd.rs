@@ -0,0 +1,199 @@
+use crate::chess::{board, san, search, time};
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+//a position from an epd line and the operations after it, like bm Nf3; id "WAC.001";
+pub struct Epd {
+    pub fen: String,
+    pub ops: Vec::<(String, Vec::<String>)>,
+}
+
+impl Epd {
+    pub fn op(&self, name: &str) -> Option::<&[String]> {
+        self.ops.iter().find(|(n, _)| n == name).map(|(_, operands)| operands.as_slice())
+    }
+
+    //the first operand, for ops that only have one like id and c0
+    pub fn op_text(&self, name: &str) -> Option::<&str> {
+        self.op(name).and_then(|o| o.first()).map(|s| s.as_str())
+    }
+}
+
+//splits the operations on semicolons and whitespace, keeping quoted strings whole
+fn parse_ops(text: &str) -> Vec::<(String, Vec::<String>)> {
+    let mut ops = Vec::<(String, Vec::<String>)>::new();
+    let mut words = Vec::<String>::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                if quoted {
+                    words.push(std::mem::take(&mut word));
+                }
+                quoted = !quoted;
+            }
+            _ if quoted => word.push(c),
+            ';' | ' ' | '\t' => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                if c == ';' && !words.is_empty() {
+                    let name = words.remove(0);
+                    ops.push((name, std::mem::take(&mut words)));
+                }
+            }
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    if !words.is_empty() {
+        let name = words.remove(0);
+        ops.push((name, words));
+    }
+    ops
+}
+
+//one epd line, none if it's blank or doesn't have the four position fields.
+//hmvc and fmvn fill in the clocks the fen needs, from_fen is happy without them otherwise.
+//a whole fen works too, like the fen | score | result lines datagen writes, its clocks are kept
+pub fn parse_line(line: &str) -> Option::<Epd> {
+    let line = line.trim();
+    let mut fields = line.splitn(5, char::is_whitespace);
+    let position: Vec::<&str> = fields.by_ref().take(4).collect();
+    if position.len() < 4 {
+        return None;
+    }
+    //opcodes start with a letter, so numbers right after the position can only be clocks
+    let mut rest = fields.next().unwrap_or("").trim_start();
+    let mut clocks = Vec::<&str>::new();
+    while clocks.len() < 2 {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 || !rest[..end].bytes().all(|b| b.is_ascii_digit()) {
+            break;
+        }
+        clocks.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    let ops = parse_ops(rest);
+    let mut epd = Epd { fen: String::new(), ops };
+    let halfmove = epd.op_text("hmvc").or(clocks.first().copied()).unwrap_or("0").to_owned();
+    let fullmove = epd.op_text("fmvn").or(clocks.get(1).copied()).unwrap_or("1").to_owned();
+    epd.fen = format!("{} {} {}", position.join(" "), halfmove, fullmove);
+    Some(epd)
+}
+
+pub fn parse(text: &str) -> Vec::<Epd> {
+    text.lines().filter_map(parse_line).collect()
+}
+
+//runs every suite, searching each position for a fixed time or depth, and counts the positions where the
+//best move is one of bm and none of am. no hash size means the search's default
+pub fn run(files: &[String], movetime: u64, depth: Option::<i32>, threads: usize, hash_mb: usize) -> Result::<(), String> {
+    let mut options = search::SearchOptions { threads, ..search::SearchOptions::default() };
+    if hash_mb > 0 {
+        options.hash_mb = hash_mb;
+    }
+    let mut totals = (0, 0);
+    for file in files {
+        let text = std::fs::read_to_string(file).map_err(|e| format!("couldn't read {}: {}", file, e))?;
+        let mut solved = 0;
+        let mut tried = 0;
+        for (i, epd) in parse(&text).iter().enumerate() {
+            let board = board::Board::from_fen(&epd.fen);
+            let id = epd.op_text("id").map_or_else(|| format!("{}", i + 1), |id| id.to_owned());
+            let moves = |name: &str| -> Result::<Vec::<_>, String> {
+                epd.op(name).unwrap_or(&[]).iter().map(|m| san::parse_san(&board, m)).collect()
+            };
+            let (best, avoid) = match (moves("bm"), moves("am")) {
+                (Ok(best), Ok(avoid)) if !best.is_empty() || !avoid.is_empty() => (best, avoid),
+                (Err(msg), _) | (_, Err(msg)) => {
+                    println!("{}: {}, skipped", id, msg);
+                    continue;
+                }
+                _ => {
+                    println!("{}: no bm or am, skipped", id);
+                    continue;
+                }
+            };
+            let mut searcher = search::Searcher::new(options);
+            //a fixed depth isn't cut short by the clock
+            let limits = time::Limits { movetime: depth.is_none().then_some(movetime), depth, ..time::Limits::default() };
+            let manager = time::TimeManager::new(&limits, board.active_player(), Arc::new(AtomicBool::new(false)));
+            let result = searcher.search_timed(&board, depth.unwrap_or(search::MAX_DEPTH), manager, &mut |_| ());
+            let found = match result.best_move {
+                Some(m) => m,
+                None => {
+                    println!("{}: no legal moves, skipped", id);
+                    continue;
+                }
+            };
+            let ok = (best.is_empty() || best.contains(&found)) && !avoid.contains(&found);
+            tried += 1;
+            if ok {
+                solved += 1;
+            }
+            let expected: Vec::<String> = ["bm", "am"]
+                .iter()
+                .filter_map(|&name| epd.op(name).map(|o| format!("{} {}", name, o.join(" "))))
+                .collect();
+            println!(
+                "{}: {} {} ({}), depth {}",
+                id,
+                if ok { "ok  " } else { "fail" },
+                san::to_san(&board, &found),
+                expected.join(", "),
+                result.depth
+            );
+        }
+        println!("{}: {} of {} solved", file, solved, tried);
+        totals.0 += solved;
+        totals.1 += tried;
+    }
+    if files.len() > 1 {
+        println!("all suites: {} of {} solved", totals.0, totals.1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_and_multiple_operands() {
+        let epd = parse_line(r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6 Rf7; id "WAC.001; mate in 3"; c0 "a  b";"#).unwrap();
+        assert_eq!(epd.fen, "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1");
+        assert_eq!(epd.op("bm").unwrap(), ["Qg6", "Rf7"]);
+        assert_eq!(epd.op_text("id"), Some("WAC.001; mate in 3"));
+        assert_eq!(epd.op_text("c0"), Some("a  b"));
+        assert_eq!(epd.ops.len(), 3);
+        //the last op doesn't need its semicolon
+        let epd = parse_line("8/8/8/8/8/8/8/K6k b - - am Ka2 Kb1").unwrap();
+        assert_eq!(epd.op("am").unwrap(), ["Ka2", "Kb1"]);
+        assert!(epd.op("bm").is_none());
+    }
+
+    #[test]
+    fn clocks_come_from_hmvc_and_fmvn() {
+        let epd = parse_line("8/8/8/8/8/8/8/K6k w - - hmvc 12; fmvn 40; bm Kb2;").unwrap();
+        assert_eq!(epd.fen, "8/8/8/8/8/8/8/K6k w - - 12 40");
+        assert_eq!(epd.op("bm").unwrap(), ["Kb2"]);
+        let epd = parse_line("8/8/8/8/8/8/8/K6k w - - fmvn 7;").unwrap();
+        assert_eq!(epd.fen, "8/8/8/8/8/8/8/K6k w - - 0 7");
+        assert!(parse_line("").is_none());
+        assert!(parse_line("8/8/8/8/8/8/8/K6k w -").is_none());
+    }
+
+    #[test]
+    fn datagen_lines() {
+        let epd = parse_line("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3 | 35 | 1.0").unwrap();
+        assert_eq!(epd.fen, "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        assert_eq!(board::Board::from_fen(&epd.fen).to_fen(), epd.fen);
+        let epd = parse_line("8/8/8/8/8/8/8/K6k b - - 0 61 | -12 | 0.5").unwrap();
+        assert_eq!(epd.fen, "8/8/8/8/8/8/8/K6k b - - 0 61");
+    }
+}
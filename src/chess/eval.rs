@@ -0,0 +1,206 @@
+use crate::chess::{board, nnue, piece, pos, tablebase};
+use crate::chess::variant::Variant;
+
+use piece::{Piece, Player};
+
+pub const PAWN_VALUE: i32 = 100;
+pub const KNIGHT_VALUE: i32 = 320;
+pub const BISHOP_VALUE: i32 = 330;
+pub const ROOK_VALUE: i32 = 500;
+pub const QUEEN_VALUE: i32 = 900;
+
+// three-check, by how many checks a side has given. the third one wins so it never gets looked up
+const CHECKS_GIVEN: [i32; 3] = [0, 150, 400];
+// king of the hill, per step the king is closer to the middle four squares
+const HILL_STEP: i32 = 25;
+// racing kings, per rank the king has climbed
+const RACE_STEP: i32 = 40;
+
+// piece square tables, written the way the board looks from white's side
+// (a8 is the top left), so white pieces have their rank flipped when looking up
+#[rustfmt::skip]
+pub const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+#[rustfmt::skip]
+pub const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+#[rustfmt::skip]
+pub const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+#[rustfmt::skip]
+pub const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+#[rustfmt::skip]
+pub const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+#[rustfmt::skip]
+pub const KING_MIDDLE_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+#[rustfmt::skip]
+pub const KING_END_TABLE: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+// on top of the usual eval, for king and pawn against king positions the bitbase says are won
+const KNOWN_WIN: i32 = 800;
+
+// non pawn material where the king stops hiding and starts walking to the middle
+const ENDGAME_MATERIAL: i32 = 2 * ROOK_VALUE + 2 * KNIGHT_VALUE;
+const OPENING_MATERIAL: i32 = 2 * (QUEEN_VALUE + 2 * ROOK_VALUE + 2 * BISHOP_VALUE + 2 * KNIGHT_VALUE);
+
+pub fn piece_value(p: Piece) -> i32 {
+    match p {
+        Piece::Empty => 0,
+        Piece::Pawn(_) => PAWN_VALUE,
+        Piece::Knight(_) => KNIGHT_VALUE,
+        Piece::Bishop(_) => BISHOP_VALUE,
+        Piece::Rook(_) => ROOK_VALUE,
+        Piece::Queen(_) => QUEEN_VALUE,
+        Piece::King(_) => 0,
+    }
+}
+
+pub fn table_index(player: Player, square: pos::Square) -> usize {
+    let file = square.file as usize - 1;
+    match player {
+        Player::White => (8 - square.rank as usize) * 8 + file,
+        Player::Black => (square.rank as usize - 1) * 8 + file,
+    }
+}
+
+//material of everything but pawns and kings, for both sides
+pub fn non_pawn_material(board: &board::Board) -> i32 {
+    [Player::White, Player::Black].iter()
+        .flat_map(|&p| board.get_pieces(p).iter())
+        .filter(|s| !matches!(s.piece_type, Piece::Pawn(_)))
+        .map(|s| piece_value(s.piece_type))
+        .sum()
+}
+
+//0 in the opening, 256 once we are down to endgame material
+pub fn phase(board: &board::Board) -> i32 {
+    ((OPENING_MATERIAL - non_pawn_material(board)) * 256 / (OPENING_MATERIAL - ENDGAME_MATERIAL)).clamp(0, 256)
+}
+
+//static evaluation in centipawns, from the point of view of the player to move
+pub fn evaluate(board: &board::Board) -> i32 {
+    //a loaded network takes over in standard chess, everything below is the fallback
+    if let Some(score) = nnue::evaluate(board) {
+        return score;
+    }
+    //in antichess every piece is a liability, being down material is being ahead
+    if board.variant() == Variant::Antichess {
+        let player = board.active_player();
+        return (board.get_pieces(player.invert()).len() as i32 - board.get_pieces(player).len() as i32) * PAWN_VALUE;
+    }
+    let phase = phase(board);
+
+    let mut score = 0;
+    for player in [Player::White, Player::Black] {
+        let mut side_score = 0;
+        for state in board.get_pieces(player) {
+            let i = table_index(player, state.pos);
+            side_score += piece_value(state.piece_type) + match state.piece_type {
+                Piece::Empty => 0,
+                Piece::Pawn(_) => PAWN_TABLE[i],
+                Piece::Knight(_) => KNIGHT_TABLE[i],
+                Piece::Bishop(_) => BISHOP_TABLE[i],
+                Piece::Rook(_) => ROOK_TABLE[i],
+                Piece::Queen(_) => QUEEN_TABLE[i],
+                Piece::King(_) => (KING_MIDDLE_TABLE[i] * (256 - phase) + KING_END_TABLE[i] * phase) / 256,
+            };
+        }
+        //crazyhouse pieces in hand are as good as on the board, they can go anywhere
+        for (count, kind) in board.get_pocket(player).iter().zip(board::POCKET_PIECES) {
+            side_score += *count as i32 * piece_value(kind(player));
+        }
+        side_score += match board.variant() {
+            Variant::ThreeCheck => CHECKS_GIVEN[(board.checks_given(player) as usize).min(2)],
+            Variant::KingOfTheHill => board.get_pieces(player)
+                .iter()
+                .find(|s| s.piece_type == Piece::King(player))
+                .map_or(0, |king| {
+                    let from_middle = |x: i8| if x <= 4 { 4 - x } else { x - 5 };
+                    (3 - from_middle(king.pos.rank).max(from_middle(king.pos.file)) as i32) * HILL_STEP
+                }),
+            Variant::RacingKings => board.get_pieces(player)
+                .iter()
+                .find(|s| s.piece_type == Piece::King(player))
+                .map_or(0, |king| (king.pos.rank as i32 - 1) * RACE_STEP),
+            _ => 0,
+        };
+        score += side_score * player.sign() as i32;
+    }
+    let score = score * board.active_player().sign() as i32;
+
+    //king and pawn against king is either won or drawn, and the bitbase knows which
+    let white = board.get_pieces(Player::White).len();
+    let black = board.get_pieces(Player::Black).len();
+    let has_pawn = |p: Player| board.get_pieces(p).iter().any(|s| matches!(s.piece_type, Piece::Pawn(_)));
+    if white + black == 3 && (has_pawn(Player::White) || has_pawn(Player::Black)) {
+        if let Some(won) = tablebase::kpk().and_then(|kpk| kpk.probe(board)) {
+            let pawn_side = if white == 2 { Player::White } else { Player::Black };
+            return match (won, pawn_side == board.active_player()) {
+                (false, _) => 0,
+                (true, true) => score + KNOWN_WIN,
+                (true, false) => score - KNOWN_WIN,
+            };
+        }
+    }
+    score
+}
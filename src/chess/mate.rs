@@ -0,0 +1,246 @@
+use crate::chess::variant::Variant;
+use crate::chess::{analyze, board, pos, san, validator, zobrist, GameResult, Player};
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+// helpmates can have a lot of solutions, only this many get printed
+const MAX_SOLUTIONS: usize = 20;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Stipulation {
+    // the side to move mates in n however the other side defends
+    Mate,
+    // the other side moves first and both work together so the side not to move at the start mates in n
+    Helpmate,
+    // the side to move forces the other side to mate it in n, the other side tries not to
+    Selfmate,
+}
+
+impl Stipulation {
+    pub fn from_name(name: &str) -> Option::<Self> {
+        match name {
+            "mate" => Some(Self::Mate),
+            "helpmate" => Some(Self::Helpmate),
+            "selfmate" => Some(Self::Selfmate),
+            _ => None,
+        }
+    }
+}
+
+//a mate only and/or search. the side to move at the start makes the "first" moves and n counts how many of
+//those are left. nothing is scored, a node is just proven or not, so every result can be kept by position
+struct Solver {
+    stipulation: Stipulation,
+    // the side that has to give the mate
+    mater: Player,
+    // standard chess only mates with a check, so a last move that doesn't check needn't be looked at
+    checks_only: bool,
+    known: HashMap::<(u64, u32), bool>,
+    nodes: u64,
+}
+
+//the legal moves, or how the game ended when there aren't any. fifty moves and repetitions don't count in problems
+fn outcome(board: &board::Board) -> (Vec::<pos::Move>, Option::<GameResult>) {
+    if let Some(result) = board.variant().game_over(board) {
+        return (vec![], Some(result));
+    }
+    let moves = validator::get_possible_moves(&mut board.clone());
+    let result = moves.is_empty().then(|| board.variant().out_of_moves(board));
+    (moves, result)
+}
+
+fn after(board: &board::Board, m: &pos::Move) -> board::Board {
+    let mut child = board.clone();
+    child.make_move(m);
+    child
+}
+
+impl Solver {
+    fn new(board: &board::Board, stipulation: Stipulation) -> Self {
+        let first = board.active_player();
+        Self {
+            stipulation,
+            mater: if stipulation == Stipulation::Mate { first } else { first.invert() },
+            checks_only: board.variant() == Variant::Standard,
+            known: HashMap::<(u64, u32), bool>::new(),
+            nodes: 0,
+        }
+    }
+
+    fn goal(&self, result: GameResult) -> bool {
+        matches!(result, GameResult::Win(winner, _) if winner == self.mater)
+    }
+
+    //whether a move the mater makes could be the mate
+    fn could_mate(&self, board: &board::Board, moves_left: u32) -> bool {
+        !self.checks_only || moves_left > 0 || board.is_in_check(board.active_player())
+    }
+
+    //the first side to move, with n of its moves left. it only needs one move that works
+    fn first(&mut self, board: &board::Board, n: u32) -> bool {
+        self.nodes += 1;
+        let (moves, result) = outcome(board);
+        if let Some(result) = result {
+            return self.goal(result);
+        }
+        if n == 0 {
+            return false;
+        }
+        let key = (zobrist::hash(board), n);
+        if let Some(&proven) = self.known.get(&key) {
+            return proven;
+        }
+        let proven = moves.iter().any(|m| {
+            let child = after(board, m);
+            //in a direct mate the first side's last move has to be the mate
+            let last = self.stipulation == Stipulation::Mate && !self.could_mate(&child, n - 1);
+            !last && self.second(&child, n - 1)
+        });
+        self.known.insert(key, proven);
+        proven
+    }
+
+    //the other side, with n of the first side's moves left. it defends against everything but a helpmate,
+    //where one move that helps is enough
+    fn second(&mut self, board: &board::Board, n: u32) -> bool {
+        self.nodes += 1;
+        let (moves, result) = outcome(board);
+        if let Some(result) = result {
+            return self.goal(result);
+        }
+        if self.stipulation == Stipulation::Mate && n == 0 {
+            return false;
+        }
+        let key = (zobrist::hash(board), n);
+        if let Some(&proven) = self.known.get(&key) {
+            return proven;
+        }
+        let proven = match self.stipulation {
+            Stipulation::Helpmate => moves.iter().any(|m| {
+                let child = after(board, m);
+                self.could_mate(&child, n) && self.first(&child, n)
+            }),
+            _ => moves.iter().all(|m| self.first(&after(board, m), n)),
+        };
+        self.known.insert(key, proven);
+        proven
+    }
+
+    //the fewest first side moves a position after a key needs, a key can do it quicker than asked
+    fn shortest(&mut self, board: &board::Board, n: u32) -> u32 {
+        (1..=n).find(|&k| self.second(board, k - 1)).unwrap_or(n)
+    }
+
+    //every cooperating line of a helpmate, up to MAX_SOLUTIONS
+    fn solutions(&mut self, board: &board::Board, n: u32, first: bool, line: &mut Vec::<pos::Move>, out: &mut Vec::<Vec::<pos::Move>>) {
+        let (moves, result) = outcome(board);
+        if let Some(result) = result {
+            if self.goal(result) {
+                out.push(line.clone());
+            }
+            return;
+        }
+        for m in &moves {
+            if out.len() >= MAX_SOLUTIONS {
+                return;
+            }
+            let child = after(board, m);
+            let works = if first { n > 0 && self.second(&child, n - 1) } else { self.could_mate(&child, n) && self.first(&child, n) };
+            if works {
+                line.push(*m);
+                self.solutions(&child, if first { n - 1 } else { n }, !first, line, out);
+                line.pop();
+            }
+        }
+    }
+}
+
+fn name(player: Player) -> &'static str {
+    match player {
+        Player::White => "White",
+        Player::Black => "Black",
+    }
+}
+
+//the defences to a key and the first side's answers to each that still work. more than one answer is a dual
+fn print_variations(solver: &mut Solver, board: &board::Board, key: &pos::Move, n: u32) {
+    let position = after(board, key);
+    let (defences, _) = outcome(&position);
+    let mut duals = 0;
+    for d in &defences {
+        let child = after(&position, d);
+        let (answers, result) = outcome(&child);
+        let line = analyze::san_line(&position, &[*d], 1);
+        //a selfmate defence that mates straight away needs no answer
+        if result.is_some() || n < 2 {
+            println!("  {}", line);
+            continue;
+        }
+        let answers: Vec::<String> = answers
+            .iter()
+            .filter(|a| solver.second(&after(&child, a), n - 2))
+            .map(|a| format!("2. {}", san::to_san(&child, a)))
+            .collect();
+        if answers.len() > 1 {
+            duals += 1;
+        }
+        println!("  {} {}{}", line, answers.join(" or "), if answers.len() > 1 { "  (dual)" } else { "" });
+    }
+    if duals > 0 {
+        println!("  {} defences with duals", duals);
+    }
+}
+
+//proves or refutes a mate, helpmate or selfmate in n from a position. for a direct mate or a selfmate every
+//key is listed with each defence and the answers to it, so cooks and duals show. a helpmate lists its solutions
+pub fn run(fen: &str, variant: Option::<Variant>, n: u32, stipulation: Stipulation) -> Result::<(), String> {
+    let mut board = board::Board::from_fen(fen);
+    if let Some(v) = variant {
+        board.set_variant(v);
+    }
+    if n == 0 {
+        return Err("the number of moves has to be at least 1".to_owned());
+    }
+    let (moves, result) = outcome(&board);
+    if result.is_some() {
+        return Err("the game is already over".to_owned());
+    }
+    let mut solver = Solver::new(&board, stipulation);
+    let start = Instant::now();
+    let first = board.active_player();
+    match stipulation {
+        Stipulation::Mate => println!("{} to mate in {}", name(first), n),
+        Stipulation::Helpmate => println!("{} helps {} mate in {}", name(first), name(first.invert()), n),
+        Stipulation::Selfmate => println!("{} forces {} to mate in {}", name(first), name(first.invert()), n),
+    }
+
+    if stipulation == Stipulation::Helpmate {
+        let mut solutions = Vec::<Vec::<pos::Move>>::new();
+        solver.solutions(&board, n, true, &mut vec![], &mut solutions);
+        for line in &solutions {
+            println!("  {}", analyze::san_line(&board, line, 1));
+        }
+        match solutions.len() {
+            0 => println!("no solution"),
+            1 => println!("one solution"),
+            MAX_SOLUTIONS => println!("cooked, {} solutions or more", MAX_SOLUTIONS),
+            count => println!("cooked, {} solutions", count),
+        }
+    } else {
+        let keys: Vec::<pos::Move> = moves.into_iter().filter(|m| solver.second(&after(&board, m), n - 1)).collect();
+        for key in &keys {
+            let length = solver.shortest(&after(&board, key), n);
+            let short = if length < n { format!(", in {}", length) } else { String::new() };
+            println!("key 1. {}{}", san::to_san(&board, key), short);
+            print_variations(&mut solver, &board, key, length);
+        }
+        match keys.len() {
+            0 => println!("no solution, refuted"),
+            1 => println!("sound, one key"),
+            count => println!("cooked, {} keys", count),
+        }
+    }
+    println!("{} nodes in {:.2}s", solver.nodes, start.elapsed().as_secs_f64());
+    Ok(())
+}
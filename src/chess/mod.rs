@@ -1,10 +1,56 @@
 
 use termion::{color};
 
+mod analyze;
 mod board;
+mod book;
+mod clock;
+mod datagen;
+mod epd;
+mod eval;
+mod mate;
+mod ordering;
+mod perft;
+mod nnue;
+mod pgn;
 mod piece;
 mod pos;
+mod puzzle;
+mod san;
+mod search;
+mod sprt;
+mod syzygy;
+mod tablebase;
+mod time;
+mod tournament;
+mod tt;
+mod tune;
+mod uci;
+mod variant;
 mod validator;
+mod xboard;
+mod zobrist;
+
+pub use analyze::run as analyze;
+pub use board::chess960_fen;
+pub use book::{run_build as book_build, run_probe as book_probe};
+pub use clock::TimeControl;
+pub use datagen::{run as datagen, DatagenSettings};
+pub use epd::run as epd_suite;
+pub use mate::{run as solve_mate, Stipulation};
+pub use nnue::load as load_network;
+pub use perft::{run as perft, run_suite as perft_suite};
+pub use piece::Player;
+pub use puzzle::run as puzzles;
+pub use search::{bench, bench_pruning, bench_smp};
+pub use sprt::{run as sprt, SprtSettings};
+pub use syzygy::run_probe as syzygy_probe;
+pub use tablebase::{init_kpk, run_generate as tablebase_generate, run_probe as tablebase_probe};
+pub use tournament::{load_openings, run as play_match, MatchSettings};
+pub use tune::run as tune;
+pub use uci::run as uci;
+pub use variant::Variant;
+pub use xboard::run as xboard;
 
 #[derive(Clone,Copy)]
 pub enum GameEval {
@@ -13,11 +59,26 @@ pub enum GameEval {
 	Eval(f64),
 }
 
+//how a finished game ended
+#[derive(Clone,Copy,PartialEq)]
+pub enum GameResult {
+	Win(piece::Player, variant::Win),
+	Draw,
+}
+
 #[derive(Clone)]
 pub struct Game{
 	board: board::Board,
 	eval: Option<GameEval>,
-	legal_moves: Option<std::vec::Vec<pos::Move>>
+	legal_moves: Option<std::vec::Vec<pos::Move>>,
+	//where the game started and every move since, to save it as pgn
+	start_fen: String,
+	record: Vec::<pgn::PgnMove>,
+	//the position before every move played, so the search can spot repetitions
+	history: Vec::<u64>,
+	clock: Option<clock::Clock>,
+	//set when a game ends in a way the board alone can't tell, like on time, with the pgn Termination for it
+	ended: Option<(GameResult, &'static str)>,
 }
 
 fn get_input() -> String {
@@ -64,6 +125,7 @@ fn parse_simple_input(input: &str, player: piece::Player) -> Result::<pos::MoveC
 			"r" => Some(piece::Piece::Rook(player)),
 			"b" => Some(piece::Piece::Bishop(player)),
 			"n" => Some(piece::Piece::Knight(player)),
+			"k" => Some(piece::Piece::King(player)),
 			_ => None,
 		}
 	};
@@ -75,13 +137,53 @@ fn parse_simple_input(input: &str, player: piece::Player) -> Result::<pos::MoveC
 	})
 }
 
+//matches coordinate input like e2e4, e7e8q, e1h1 for castling or N@f3 for a drop against the legal moves, promoting to a queen if no piece is given
+fn find_move(legal_moves: &[pos::Move], input: &str, player: piece::Player) -> Result::<pos::Move, String> {
+	//crazyhouse drops, like N@f3. a pawn can leave its letter off
+	if let Some((p, square)) = input.trim().split_once('@') {
+		let square = pos::Square::from_string(&square.to_ascii_lowercase())?;
+		let p = p.chars().next().unwrap_or('P').to_ascii_uppercase();
+		return legal_moves.iter()
+			.find(|m| m.move_type == pos::MoveType::Drop && m.new_pos == square && m.piece.to_char_ascii().to_ascii_uppercase() == p)
+			.copied()
+			.ok_or_else(|| "Invalid Move!".to_owned());
+	}
+	let user_move = parse_simple_input(input, player)?;
+	legal_moves.iter().find(|m| {
+		//castling can be given as the king taking its own rook, or as where the king ends up
+		//when that's more than one square away so it can't be a normal king move
+		if let pos::MoveType::Castle(rook_pos) = m.move_type {
+			m.old_pos == user_move.old_pos && (
+				user_move.new_pos == rook_pos ||
+				(user_move.new_pos == m.new_pos && (m.new_pos.file - m.old_pos.file).abs() >= 2)
+			)
+		}
+		else if let Some(p) = m.promotion() {
+			m.old_pos == user_move.old_pos &&
+			m.new_pos == user_move.new_pos &&
+			p == user_move.promote_to.unwrap_or(piece::Piece::Queen(player))
+		}
+		else{
+			m.old_pos == user_move.old_pos &&
+			m.new_pos == user_move.new_pos 
+		}
+	})
+	.copied()
+	.ok_or_else(|| "Invalid Move!".to_owned())
+}
+
 impl Game{
 	pub fn new() -> Self{
 		let board = board::Board::new();
 		Self {
 			board,
 			eval: None,
-			legal_moves: None
+			legal_moves: None,
+			start_fen: pgn::START_FEN.to_owned(),
+			record: vec![],
+			history: vec![],
+			clock: None,
+			ended: None,
 		}
 	}
 	pub fn from_fen(fen: &str) -> Self {
@@ -89,12 +191,145 @@ impl Game{
 		Self {
 			board,
 			eval: None,
-			legal_moves: None
+			legal_moves: None,
+			start_fen: fen.trim().to_owned(),
+			record: vec![],
+			history: vec![],
+			clock: None,
+			ended: None,
+		}
+	}
+	//one of the 960 fischer random start positions, by number
+	pub fn chess960(index: usize) -> Self {
+		Self::from_fen(&board::chess960_fen(index))
+	}
+	//plays by other rules from here on, a fen with pockets is already crazyhouse
+	pub fn set_variant(&mut self, variant: variant::Variant) {
+		self.board.set_variant(variant);
+		self.eval = None;
+		self.legal_moves = None;
+	}
+	//plays on a clock from the next move on
+	pub fn set_clock(&mut self, control: clock::TimeControl) {
+		self.clock = Some(clock::Clock::new(control));
+	}
+	//none while the game is still going
+	pub fn result(&mut self) -> Option::<GameResult> {
+		if let Some((result, _)) = self.ended {
+			return Some(result);
+		}
+		if let Some(result) = self.board.variant().game_over(&self.board) {
+			return Some(result);
 		}
+		if !validator::get_possible_moves(&mut self.board).is_empty() {
+			return None;
+		}
+		Some(self.board.variant().out_of_moves(&self.board))
+	}
+	pub fn history(&self) -> &[u64] {
+		&self.history
 	}
 	pub fn eval(&mut self) -> GameEval {
 		self.eval.unwrap_or(GameEval::Eval(0.0))
 	}
+	//searches to a fixed depth, remembers the eval and returns the best move if there is one
+	pub fn best_move(&mut self, depth: i32) -> Option::<pos::Move> {
+		let mut searcher = search::Searcher::new(search::SearchOptions::default());
+		searcher.set_history(&self.history);
+		let result = searcher.search(&self.board, depth);
+		let player = self.board.active_player();
+		self.eval = Some(if result.best_move.is_none() && result.score == 0 {
+			GameEval::Draw
+		} else if result.score >= search::MATE_BOUND {
+			GameEval::Checkmate(player)
+		} else if result.score <= -search::MATE_BOUND {
+			GameEval::Checkmate(player.invert())
+		} else {
+			//pawns, from white's point of view
+			GameEval::Eval(result.score as f64 * player.sign() as f64 / 100.0)
+		});
+		result.best_move
+	}
+	//the exact result with best play, if the position is in one of the endgame tables
+	pub fn endgame(&self, tables: &tablebase::Tables) -> Option::<tablebase::Outcome> {
+		tables.probe(&self.board)
+	}
+	//plays a move given in coordinate notation, if it is legal
+	pub fn play_move(&mut self, input: &str) -> Result::<(), String> {
+		let legal_moves = validator::get_possible_moves(&mut self.board);
+		let m = find_move(&legal_moves, input, self.board.active_player())?;
+		self.record_move(&m, None);
+		Ok(())
+	}
+	//makes the move and writes it down for the pgn
+	fn record_move(&mut self, m: &pos::Move, comment: Option::<String>) {
+		let san = san::to_san(&self.board, m);
+		self.record.push(pgn::PgnMove { san, comment });
+		self.history.push(zobrist::hash(&self.board));
+		self.board.make_move(m);
+	}
+	//player ran out of time. they only lose if the other side could still mate them somehow
+	fn flag(&mut self, player: piece::Player) {
+		let winner = player.invert();
+		let result = if self.board.has_mating_material(winner) {
+			GameResult::Win(winner, variant::Win::Timeout)
+		} else {
+			GameResult::Draw
+		};
+		self.end(result, "time forfeit");
+	}
+	//ends the game whatever the board says, termination is the reason the pgn gives
+	fn end(&mut self, result: GameResult, termination: &'static str) {
+		self.ended = Some((result, termination));
+	}
+	//the game so far as pgn, with the clock times after each move if it was played on one.
+	//tags given here replace the made up ones, like White and Black
+	pub fn pgn(&mut self, given: &[(&str, String)]) -> String {
+		let result = match self.result() {
+			Some(GameResult::Win(piece::Player::White, _)) => "1-0",
+			Some(GameResult::Win(piece::Player::Black, _)) => "0-1",
+			Some(GameResult::Draw) => "1/2-1/2",
+			None => "*",
+		};
+		let mut tags: Vec::<(String, String)> = [
+			("Event", "Casual game"),
+			("Site", "?"),
+			("Date", "????.??.??"),
+			("Round", "-"),
+			("White", "?"),
+			("Black", "?"),
+			("Result", result),
+		].iter().map(|(n, v)| (n.to_string(), v.to_string())).collect();
+		if self.board.variant() != variant::Variant::Standard {
+			tags.push(("Variant".to_owned(), self.board.variant().name().to_owned()));
+		}
+		if self.start_fen != pgn::START_FEN {
+			tags.push(("SetUp".to_owned(), "1".to_owned()));
+			tags.push(("FEN".to_owned(), self.start_fen.clone()));
+		}
+		if let Some(clock) = &self.clock {
+			tags.push(("TimeControl".to_owned(), clock.control().to_string()));
+		}
+		if let Some((_, termination)) = self.ended {
+			tags.push(("Termination".to_owned(), termination.to_owned()));
+		}
+		for (name, value) in given {
+			match tags.iter_mut().find(|(n, _)| n == name) {
+				Some(tag) => tag.1 = value.clone(),
+				None => tags.push((name.to_string(), value.clone())),
+			}
+		}
+		pgn::write(&pgn::PgnGame { tags, moves: self.record.clone(), result: result.to_owned() })
+	}
+	fn print_clock(&self, player: piece::Player) {
+		if let Some(clock) = &self.clock {
+			let name = match player {
+				piece::Player::White => "White",
+				piece::Player::Black => "Black",
+			};
+			println!("{} {}", name, clock::format_clock(clock.remaining(player)));
+		}
+	}
 	pub fn gen_moves(&mut self) {
 		self.legal_moves = Some(validator::get_possible_moves(&mut self.board));
 	}
@@ -119,63 +354,70 @@ impl Game{
 		}
 	}
 
+	//the moves there are and the board from the side to move, before asking for a move
+	fn show(&mut self) {
+		self.gen_moves();
+		println!("-----------------------------");
+		self.print_possible_moves();
+		println!("-----------------------------");
+		let player = self.board.active_player();
+		//the clock of whoever sits across the board above it, your own below
+		self.print_clock(player.invert());
+		self.board.print(player);
+		self.print_clock(player);
+		if self.board.variant() == variant::Variant::ThreeCheck {
+			println!(
+				"checks given, White {} Black {}",
+				self.board.checks_given(piece::Player::White),
+				self.board.checks_given(piece::Player::Black),
+			);
+		}
+	}
 	pub fn play(&mut self) {
+		let first = self.board.active_player();
+		if let Some(clock) = &mut self.clock {
+			clock.start(first);
+		}
 		loop {
-			let all_legal_moves = validator::get_possible_moves(&mut self.board);
-			if all_legal_moves.len() == 0 {            
-				if let (Some(_), check_vec) = self.board.in_check(self.board.active_player()) {
-					if check_vec.len() > 0 { 
-						self.board.print(self.board.active_player().invert()); 
-						println!(
-							"{}Checkmate! {} wins!", 
-							color::Fg(color::Red), 
-							match self.board.active_player() {
-								piece::Player::White => "Black",
-								piece::Player::Black => "White",
-							}
-						);
-						print!("{}", color::Fg(color::Reset));
-						return;
-					} else {
-						self.board.print(self.board.active_player().invert()); 
-						println!(
-							"{}Draw!", 
-							color::Fg(color::Red),
-						);
-					}
+			if let Some(result) = self.result() {
+				self.board.print(self.board.active_player().invert());
+				match result {
+					GameResult::Win(winner, win) => println!(
+						"{}{}! {} wins!",
+						color::Fg(color::Red),
+						win,
+						match winner {
+							piece::Player::White => "White",
+							piece::Player::Black => "Black",
+						}
+					),
+					GameResult::Draw => println!("{}Draw!", color::Fg(color::Red)),
 				}
+				print!("{}", color::Fg(color::Reset));
+				return;
 			}
-			self.gen_moves();
-			println!("-----------------------------");
-			self.print_possible_moves();
-			println!("-----------------------------");
-			self.board.print(self.board.active_player());
+			let all_legal_moves = validator::get_possible_moves(&mut self.board);
+			self.show();
+			let player = self.board.active_player();
 			let user_input = get_input();
-			let user_move = match parse_simple_input(&user_input, self.board.active_player()) {
-				Ok(n) => n,
+			//the flag can fall while typing, so the clock is looked at before the move counts
+			if self.clock.as_ref().is_some_and(|c| c.flagged(player)) {
+				self.flag(player);
+				continue;
+			}
+			match find_move(&all_legal_moves, &user_input, player) {
+				Ok(valid_move) => {
+					let left = self.clock.as_mut().map(|c| c.press(player).then(|| c.remaining(player)));
+					match left {
+						Some(None) => self.flag(player),
+						Some(Some(left)) => self.record_move(&valid_move, Some(format!("[%clk {}]", clock::format_clock(left)))),
+						None => self.record_move(&valid_move, None),
+					}
+				}
 				Err(msg) => {
 					println!("{}{}", color::Fg(color::Red), msg);
 					print!("{}", color::Fg(color::Reset));
-					continue;
 				}
-			};
-			if let Some(valid_move) = all_legal_moves.iter().find(|m| {
-				if let pos::MoveType::Promotion(p) = m.move_type {
-					m.old_pos == user_move.old_pos &&
-					m.new_pos == user_move.new_pos &&
-					p == user_move.promote_to.unwrap_or(piece::Piece::Queen(self.board.active_player()))
-				}
-				else{
-					m.old_pos == user_move.old_pos &&
-					m.new_pos == user_move.new_pos 
-				}
-			})  {
-				self.board.make_move(valid_move);
-			}
-			else {
-
-				println!("{}Invalid Move!", color::Fg(color::Red));
-				print!("{}", color::Fg(color::Reset));
 			}
 		}
 	}
@@ -0,0 +1,294 @@
+use crate::chess::piece::{Piece, Player};
+use crate::chess::variant::Variant;
+use crate::chess::{board, pos};
+
+use std::sync::{Arc, RwLock};
+
+// a halfkp network: every piece but the kings is a feature seen from each side's king, the two accumulators
+// go through a clipped relu into one output. the weight file is all little endian:
+//   8 bytes   "CHESSNN1"
+//   u32       the hidden size, which has to be HIDDEN
+//   i16       feature weights, HIDDEN of them for each of the INPUTS features in order
+//   i16       HIDDEN feature biases
+//   i8        2 * HIDDEN output weights, the side to move's accumulator first
+//   i32       output bias
+// features are (king square * 10 + piece) * 64 + square. squares go from a1 = 0 to h8 = 63 and are flipped top
+// to bottom from black's side, pieces are pawn, knight, bishop, rook, queen with our own before theirs,
+// so 0 is our pawn and 9 their queen. the output is
+//   (bias + sum of clamp(accumulator, 0, QA) * output weight) * SCALE / (QA * QB)
+// in centipawns for the side to move. the accumulator is i16, so the biases plus the weights of any 30 features,
+// the most pieces there can be besides the kings, have to stay inside i16 for every king square or the load fails
+const MAGIC: &[u8; 8] = b"CHESSNN1";
+pub const HIDDEN: usize = 256;
+const INPUTS: usize = 64 * 10 * 64;
+// the accumulator is clipped to this before the output layer, so it fits the int8 weights' products
+const QA: i32 = 127;
+// how much the output weights were scaled up before being rounded to int8
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+const MAX_FEATURES: usize = 30;
+
+// the network boards set up from now on will use. setting EvalFile again swaps it out
+static NETWORK: RwLock::<Option::<Arc::<Network>>> = RwLock::new(None);
+
+pub struct Network {
+    feature_weights: Vec::<i16>,
+    feature_biases: Vec::<i16>,
+    output_weights: Vec::<i8>,
+    output_bias: i32,
+}
+
+//the sums of the weights of every feature that is on, one for each side's point of view.
+//boards keep one up to date move by move while a network is loaded, along with the network it is for
+#[derive(Clone)]
+pub struct Accumulator {
+    network: Arc::<Network>,
+    values: [[i16; HIDDEN]; 2],
+}
+
+fn side(player: Player) -> usize {
+    match player {
+        Player::White => 0,
+        Player::Black => 1,
+    }
+}
+
+fn king_square(board: &board::Board, player: Player) -> Option::<pos::Square> {
+    board.get_pieces(player).iter().find(|s| s.piece_type == Piece::King(player)).map(|s| s.pos)
+}
+
+//none for kings and empty squares, kings are only there as where the features are seen from
+fn feature(perspective: Player, king: pos::Square, piece: Piece, square: pos::Square) -> Option::<usize> {
+    let kind = match piece {
+        Piece::Pawn(_) => 0,
+        Piece::Knight(_) => 1,
+        Piece::Bishop(_) => 2,
+        Piece::Rook(_) => 3,
+        Piece::Queen(_) => 4,
+        Piece::King(_) | Piece::Empty => return None,
+    };
+    let theirs = (piece.owner() != Some(perspective)) as usize;
+    let index = |s: pos::Square| {
+        let i = (s.rank as usize - 1) * 8 + s.file as usize - 1;
+        if perspective == Player::Black { i ^ 56 } else { i }
+    };
+    Some((index(king) * 10 + kind * 2 + theirs) * 64 + index(square))
+}
+
+//whether the accumulator stays inside i16 whatever the position, going by the largest weights either way
+fn fits_i16(feature_weights: &[i16], feature_biases: &[i16]) -> bool {
+    let mut column = Vec::<i32>::with_capacity(10 * 64);
+    for king in 0..64 {
+        for (h, &bias) in feature_biases.iter().enumerate() {
+            column.clear();
+            column.extend((0..10 * 64).map(|f| feature_weights[(king * 10 * 64 + f) * HIDDEN + h] as i32));
+            column.sort_unstable();
+            let lowest: i32 = column.iter().take(MAX_FEATURES).filter(|&&w| w < 0).sum();
+            let highest: i32 = column.iter().rev().take(MAX_FEATURES).filter(|&&w| w > 0).sum();
+            if bias as i32 + lowest < i16::MIN as i32 || bias as i32 + highest > i16::MAX as i32 {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+//reads a weight file in the format at the top, replacing any network loaded before
+pub fn load(path: &str) -> Result::<(), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("couldn't read {}: {}", path, e))?;
+    let expected = MAGIC.len() + 4 + (INPUTS * HIDDEN + HIDDEN) * 2 + 2 * HIDDEN + 4;
+    if !bytes.starts_with(MAGIC) || bytes.len() < MAGIC.len() + 4 {
+        return Err(format!("{} isn't a network", path));
+    }
+    let hidden = u32::from_le_bytes(bytes[8..12].try_into().expect("4 bytes")) as usize;
+    if hidden != HIDDEN || bytes.len() != expected {
+        return Err(format!("{} has {} hidden neurons and {} bytes, {} and {} are needed", path, hidden, bytes.len(), HIDDEN, expected));
+    }
+    let i16s = |from: usize, count: usize| -> Vec::<i16> {
+        bytes[from..from + count * 2].chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect()
+    };
+    let mut at = 12;
+    let feature_weights = i16s(at, INPUTS * HIDDEN);
+    at += INPUTS * HIDDEN * 2;
+    let feature_biases = i16s(at, HIDDEN);
+    at += HIDDEN * 2;
+    let output_weights = bytes[at..at + 2 * HIDDEN].iter().map(|&b| b as i8).collect();
+    at += 2 * HIDDEN;
+    let output_bias = i32::from_le_bytes(bytes[at..at + 4].try_into().expect("4 bytes"));
+    if !fits_i16(&feature_weights, &feature_biases) {
+        return Err(format!("{} has weights too big for the accumulator, {} of them can add up past an i16", path, MAX_FEATURES));
+    }
+    let network = Network { feature_weights, feature_biases, output_weights, output_bias };
+    *NETWORK.write().unwrap() = Some(Arc::new(network));
+    Ok(())
+}
+
+//back to the classical evaluation for positions set up from now on
+pub fn unload() {
+    *NETWORK.write().unwrap() = None;
+}
+
+pub fn network() -> Option::<Arc::<Network>> {
+    NETWORK.read().unwrap().clone()
+}
+
+//the network's score for the side to move, none when the board has no network or isn't standard chess
+pub fn evaluate(board: &board::Board) -> Option::<i32> {
+    board.accumulator().filter(|_| board.variant() == Variant::Standard).map(|a| a.output(board.active_player()))
+}
+
+impl Network {
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * HIDDEN..][..HIDDEN]
+    }
+}
+
+//the adds and subtracts wrap rather than panic. load makes sure real positions never get that far,
+//and wrapping both ways still undoes itself if a made up one does
+impl Accumulator {
+    pub fn new(network: Arc::<Network>, board: &board::Board) -> Self {
+        let mut accumulator = Self { network, values: [[0; HIDDEN]; 2] };
+        for perspective in [Player::White, Player::Black] {
+            accumulator.refresh(board, perspective);
+        }
+        accumulator
+    }
+
+    //starts one side's point of view over from the pieces on the board, needed whenever that side's king moves
+    pub fn refresh(&mut self, board: &board::Board, perspective: Player) {
+        let network = &self.network;
+        let values = &mut self.values[side(perspective)];
+        values.copy_from_slice(&network.feature_biases);
+        let king = match king_square(board, perspective) {
+            Some(k) => k,
+            None => return,
+        };
+        for player in [Player::White, Player::Black] {
+            for state in board.get_pieces(player) {
+                if let Some(f) = feature(perspective, king, state.piece_type, state.pos) {
+                    for (v, &w) in values.iter_mut().zip(network.weights(f)) {
+                        *v = v.wrapping_add(w);
+                    }
+                }
+            }
+        }
+    }
+
+    //takes the pieces that left squares out and puts the ones that arrived in, kings are skipped
+    pub fn update(
+        &mut self,
+        board: &board::Board,
+        perspective: Player,
+        removed: &[(Piece, pos::Square)],
+        added: &[(Piece, pos::Square)],
+    ) {
+        let king = match king_square(board, perspective) {
+            Some(k) => k,
+            None => return,
+        };
+        let network = &self.network;
+        let values = &mut self.values[side(perspective)];
+        for &(piece, square) in removed {
+            if let Some(f) = feature(perspective, king, piece, square) {
+                for (v, &w) in values.iter_mut().zip(network.weights(f)) {
+                    *v = v.wrapping_sub(w);
+                }
+            }
+        }
+        for &(piece, square) in added {
+            if let Some(f) = feature(perspective, king, piece, square) {
+                for (v, &w) in values.iter_mut().zip(network.weights(f)) {
+                    *v = v.wrapping_add(w);
+                }
+            }
+        }
+    }
+
+    pub fn output(&self, player: Player) -> i32 {
+        let network = &self.network;
+        let us = &self.values[side(player)];
+        let them = &self.values[side(player.invert())];
+        let (our_weights, their_weights) = network.output_weights.split_at(HIDDEN);
+        let layer = |values: &[i16; HIDDEN], weights: &[i8]| -> i32 {
+            values.iter().zip(weights).map(|(&v, &w)| (v as i32).clamp(0, QA) * w as i32).sum()
+        };
+        let sum = network.output_bias as i64 + layer(us, our_weights) as i64 + layer(them, their_weights) as i64;
+        (sum * SCALE as i64 / (QA * QB) as i64) as i32
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::validator;
+
+    fn write_network(path: &std::path::Path, weight: impl Fn(usize) -> i16, bias: i16) {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(HIDDEN as u32).to_le_bytes());
+        for i in 0..INPUTS * HIDDEN {
+            bytes.extend_from_slice(&weight(i).to_le_bytes());
+        }
+        for _ in 0..HIDDEN {
+            bytes.extend_from_slice(&bias.to_le_bytes());
+        }
+        bytes.extend((0..2 * HIDDEN).map(|i| (i % 7) as u8));
+        bytes.extend_from_slice(&0i32.to_le_bytes());
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    //loading fails before anything is replaced, so this doesn't get in the way of the test below
+    #[test]
+    fn truncated_networks_are_refused() {
+        let path = std::env::temp_dir().join(format!("nnue-truncated-{}.bin", std::process::id()));
+        for len in [4, 8, 10, 12, 100] {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&(HIDDEN as u32).to_le_bytes());
+            bytes.resize(len, 0);
+            std::fs::write(&path, bytes).unwrap();
+            assert!(load(path.to_str().unwrap()).is_err(), "{} bytes", len);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    //the network is global, so loading, swapping and unloading all happen in the one test
+    #[test]
+    fn networks_can_be_swapped_and_updates_match_refreshes() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("nnue-first-{}.bin", std::process::id()));
+        let second = dir.join(format!("nnue-second-{}.bin", std::process::id()));
+        let too_big = dir.join(format!("nnue-too-big-{}.bin", std::process::id()));
+        write_network(&first, |i| (i * 7919 % 101) as i16 - 50, 10);
+        write_network(&second, |i| (i * 104729 % 61) as i16 - 30, -5);
+        write_network(&too_big, |_| 1200, 0);
+
+        load(first.to_str().unwrap()).unwrap();
+        let mut board = board::Board::from_fen("r3k2r/pPppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let before = evaluate(&board).unwrap();
+        //every kind of move, checked against starting the sums over
+        for ply in 0..40 {
+            let moves = validator::get_possible_moves(&mut board.clone());
+            if moves.is_empty() {
+                break;
+            }
+            board.make_move(&moves[(ply * 31) % moves.len()]);
+            let fresh = Accumulator::new(network().unwrap(), &board);
+            assert_eq!(board.accumulator().unwrap().values, fresh.values, "after ply {}", ply);
+        }
+
+        //a second EvalFile replaces the first for boards set up afterwards
+        load(second.to_str().unwrap()).unwrap();
+        let start = "r3k2r/pPppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let swapped = board::Board::from_fen(start);
+        assert!(Arc::ptr_eq(&swapped.accumulator().unwrap().network, &network().unwrap()));
+        assert_ne!(evaluate(&swapped), Some(before));
+
+        assert!(load(too_big.to_str().unwrap()).is_err());
+        unload();
+        assert_eq!(evaluate(&board::Board::from_fen(start)), None);
+
+        for path in [first, second, too_big] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::chess::{eval, piece, pos};
+
+pub const MAX_PLY: usize = 128;
+
+// move scores, higher gets searched first
+const TT_MOVE_SCORE: i32 = 4_000_000;
+const GOOD_CAPTURE_SCORE: i32 = 3_000_000;
+const PROMOTION_SCORE: i32 = 2_900_000;
+const FIRST_KILLER_SCORE: i32 = 2_000_000;
+const SECOND_KILLER_SCORE: i32 = 1_900_000;
+const COUNTERMOVE_SCORE: i32 = 1_800_000;
+// history scores stay within +-HISTORY_MAX, below the killers
+const HISTORY_MAX: i32 = 16_384;
+
+//piece that gets taken by a move, if any
+pub fn captured_piece(m: &pos::Move) -> Option::<piece::Piece> {
+    match m.move_type {
+        pos::MoveType::Capture(p) | pos::MoveType::CapturePromotion(p, _) => Some(p),
+        pos::MoveType::EnPassant => Some(piece::Piece::Pawn(m.piece.owner()?.invert())),
+        _ => None,
+    }
+}
+
+pub fn is_quiet(m: &pos::Move) -> bool {
+    matches!(m.move_type, pos::MoveType::Move | pos::MoveType::Castle(_) | pos::MoveType::Drop)
+}
+
+//most valuable victim, least valuable attacker
+pub fn mvv_lva(m: &pos::Move) -> i32 {
+    match captured_piece(m) {
+        Some(victim) => eval::piece_value(victim) * 16 - eval::piece_value(m.piece) / 10,
+        None => 0,
+    }
+}
+
+fn player_index(p: piece::Player) -> usize {
+    match p {
+        piece::Player::White => 0,
+        piece::Player::Black => 1,
+    }
+}
+
+//killers, history and countermoves learned while searching, reused between iterations
+#[derive(Clone)]
+pub struct OrderingTables {
+    killers: [[Option::<pos::Move>; 2]; MAX_PLY],
+    // butterfly history, [player][from][to]
+    history: Box::<[[[i32; 64]; 64]; 2]>,
+    // best reply to the last move, [piece that moved][where it went]
+    countermoves: [[Option::<pos::Move>; 64]; 12],
+}
+
+impl OrderingTables {
+    pub fn new() -> Self {
+        Self {
+            killers: [[None; 2]; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2]),
+            countermoves: [[None; 64]; 12],
+        }
+    }
+
+    fn history(&self, m: &pos::Move) -> i32 {
+        let player = player_index(m.piece.owner().expect("Board Mangled"));
+        self.history[player][m.old_pos.index()][m.new_pos.index()]
+    }
+
+    fn countermove(&self, prev: Option::<&pos::Move>) -> Option::<pos::Move> {
+        let prev = prev?;
+        self.countermoves[prev.piece.index()][prev.new_pos.index()]
+    }
+
+    pub fn score(&self, m: &pos::Move, tt_move: Option::<&pos::Move>, ply: usize, prev: Option::<&pos::Move>) -> i32 {
+        if tt_move == Some(m) {
+            return TT_MOVE_SCORE;
+        }
+        if captured_piece(m).is_some() {
+            return GOOD_CAPTURE_SCORE + mvv_lva(m) + m.promotion().map_or(0, eval::piece_value);
+        }
+        if let Some(p) = m.promotion() {
+            return PROMOTION_SCORE + eval::piece_value(p);
+        }
+        let killers = &self.killers[ply.min(MAX_PLY - 1)];
+        if killers[0] == Some(*m) {
+            FIRST_KILLER_SCORE
+        } else if killers[1] == Some(*m) {
+            SECOND_KILLER_SCORE
+        } else if self.countermove(prev) == Some(*m) {
+            COUNTERMOVE_SCORE
+        } else {
+            self.history(m)
+        }
+    }
+
+    //sorts moves best first
+    pub fn order(&self, moves: &mut [pos::Move], tt_move: Option::<&pos::Move>, ply: usize, prev: Option::<&pos::Move>) {
+        moves.sort_by_cached_key(|m| -self.score(m, tt_move, ply, prev));
+    }
+
+    fn add_history(&mut self, m: &pos::Move, bonus: i32) {
+        let player = player_index(m.piece.owner().expect("Board Mangled"));
+        let entry = &mut self.history[player][m.old_pos.index()][m.new_pos.index()];
+        //gravity, so that old results fade and the score cant run away
+        *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+    }
+
+    //a quiet move caused a beta cutoff, reward it and punish the quiets tried before it
+    pub fn update_quiet(
+        &mut self,
+        m: &pos::Move,
+        depth: i32,
+        ply: usize,
+        prev: Option::<&pos::Move>,
+        tried: &[pos::Move],
+    ) {
+        let killers = &mut self.killers[ply.min(MAX_PLY - 1)];
+        if killers[0] != Some(*m) {
+            killers[1] = killers[0];
+            killers[0] = Some(*m);
+        }
+        let bonus = (depth * depth).min(HISTORY_MAX / 4);
+        self.add_history(m, bonus);
+        for other in tried.iter().filter(|o| *o != m && is_quiet(o)) {
+            self.add_history(other, -bonus);
+        }
+        if let Some(prev) = prev {
+            self.countermoves[prev.piece.index()][prev.new_pos.index()] = Some(*m);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use crate::chess::{board, pos, validator, zobrist};
+use crate::chess::variant::Variant;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+//subtree sizes by position and depth, so transpositions only get counted out once
+pub struct PerftTable {
+    // key, depth, nodes. depth 0 means the slot is empty, nothing that shallow gets stored
+    entries: Vec::<Mutex::<(u64, u32, u64)>>,
+}
+
+impl PerftTable {
+    pub fn new(size_mb: usize) -> Self {
+        let count = (size_mb * 1024 * 1024 / std::mem::size_of::<Mutex::<(u64, u32, u64)>>())
+            .max(1)
+            .next_power_of_two() / 2;
+        Self {
+            entries: (0..count.max(1)).map(|_| Mutex::new((0, 0, 0))).collect(),
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Mutex::<(u64, u32, u64)> {
+        &self.entries[(key as usize) & (self.entries.len() - 1)]
+    }
+
+    fn probe(&self, key: u64, depth: u32) -> Option::<u64> {
+        let (k, d, nodes) = *self.slot(key).lock().unwrap();
+        if k == key && d == depth {
+            Some(nodes)
+        } else {
+            None
+        }
+    }
+
+    fn store(&self, key: u64, depth: u32, nodes: u64) {
+        *self.slot(key).lock().unwrap() = (key, depth, nodes);
+    }
+}
+
+//depth first leaf count, the last ply is just the number of legal moves
+pub fn perft(board: &mut board::Board, depth: u32, table: Option::<&PerftTable>) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = validator::get_possible_moves(board);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let key = zobrist::hash(board);
+    if let Some(nodes) = table.and_then(|t| t.probe(key, depth)) {
+        return nodes;
+    }
+    let mut nodes = 0;
+    for m in &moves {
+        let mut child = board.clone();
+        child.make_move(m);
+        nodes += perft(&mut child, depth - 1, table);
+    }
+    if let Some(t) = table {
+        t.store(key, depth, nodes);
+    }
+    nodes
+}
+
+//leaf count under every root move. the root moves are handed out to the threads one at a time
+pub fn divide(board: &board::Board, depth: u32, threads: usize, table: Option::<&PerftTable>) -> Vec::<(pos::Move, u64)> {
+    let moves = validator::get_possible_moves(&mut board.clone());
+    let counts = Mutex::new(vec![0u64; moves.len()]);
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, moves.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= moves.len() {
+                    break;
+                }
+                let mut child = board.clone();
+                child.make_move(&moves[i]);
+                let nodes = perft(&mut child, depth.saturating_sub(1), table);
+                counts.lock().unwrap()[i] = nodes;
+            });
+        }
+    });
+    moves.into_iter().zip(counts.into_inner().unwrap()).collect()
+}
+
+pub fn run(fen: &str, variant: Option::<Variant>, depth: u32, threads: usize, hash_mb: usize) -> u64 {
+    let mut board = board::Board::from_fen(fen);
+    if let Some(v) = variant {
+        board.set_variant(v);
+    }
+    let table = (hash_mb > 0).then(|| PerftTable::new(hash_mb));
+    let start = std::time::Instant::now();
+    let counts = divide(&board, depth.max(1), threads, table.as_ref());
+    let elapsed = start.elapsed().as_secs_f64();
+    for (m, nodes) in &counts {
+        println!("{}: {}", m, nodes);
+    }
+    let total: u64 = counts.iter().map(|(_, n)| n).sum();
+    println!();
+    println!("depth {}: {} nodes in {:.2}s ({:.0} nps, {} threads)", depth, total, elapsed, total as f64 / elapsed.max(1e-9), threads);
+    total
+}
+
+// known leaf counts, the first six are the chessprogramming wiki positions, then edge cases, then chess960
+const SUITE: &[(&str, u32, u64)] = &[
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 5, 4865609),
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 6, 119060324),
+    ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 4, 4085603),
+    ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 5, 193690690),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 6, 11030083),
+    ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 5, 15833292),
+    ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 4, 2103487),
+    ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", 4, 3894594),
+    ("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 6, 1134888),
+    ("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1", 6, 1015133),
+    ("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 6, 1440467),
+    ("5k2/8/8/8/8/8/8/4K2R w K - 0 1", 6, 661072),
+    ("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", 6, 803711),
+    ("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1", 4, 1274206),
+    ("r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1", 4, 1720476),
+    ("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1", 6, 3821001),
+    ("8/8/1P2K3/8/2n5/1q6/8/5k2 b - - 0 1", 5, 1004658),
+    ("4k3/1P6/8/8/8/8/K7/8 w - - 0 1", 6, 217342),
+    ("8/P1k5/K7/8/8/8/8/8 w - - 0 1", 6, 92683),
+    ("K1k5/8/P7/8/8/8/8/8 w - - 0 1", 6, 2217),
+    ("8/k1P5/8/1K6/8/8/8/8 w - - 0 1", 7, 567584),
+    ("8/8/2k5/5q2/5n2/8/5K2/8 b - - 0 1", 4, 23527),
+    // chess960, castling written shredder style with the rooks' files
+    ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", 4, 326672),
+    ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", 5, 8146062),
+    ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", 4, 667366),
+    ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", 5, 6417013),
+    ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", 4, 382958),
+    ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", 4, 1171749),
+];
+
+// same again for other rule sets, the fen alone doesnt always say which
+const VARIANT_SUITE: &[(Variant, &str, u32, u64)] = &[
+    // crazyhouse, the pockets in brackets and ~ after a piece that was promoted
+    (Variant::Crazyhouse, "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1", 2, 75353),
+    (Variant::Crazyhouse, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1", 5, 4888832),
+    (Variant::Crazyhouse, "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1", 4, 2083382),
+    (Variant::Crazyhouse, "4k3/1Q~6/8/8/4b3/8/Kpp5/8/ b - - 0 1", 2, 360),
+    // three-check, one check left for each side so the next check ends it
+    (Variant::ThreeCheck, "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 1+1 0 1", 3, 97848),
+    (Variant::ThreeCheck, "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 1+1 0 1", 4, 4081798),
+    // king of the hill, d4 and e4 end the game for white
+    (Variant::KingOfTheHill, "8/8/8/8/8/3K4/8/7k w - - 0 1", 2, 18),
+    (Variant::KingOfTheHill, "8/8/8/8/8/3K4/8/7k w - - 0 1", 3, 136),
+    // atomic, the last three castle chess960 style next to the enemy king
+    (Variant::Atomic, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 4, 197326),
+    (Variant::Atomic, "rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1", 3, 45237),
+    (Variant::Atomic, "rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1", 3, 23353),
+    (Variant::Atomic, "8/8/8/8/8/8/2k5/rR4KR w KQ - 0 1", 3, 4364),
+    (Variant::Atomic, "r3k1rR/5K2/8/8/8/8/8/8 b kq - 0 1", 3, 6753),
+    (Variant::Atomic, "Rr2k1rR/3K4/3p4/8/8/8/7P/8 w kq - 0 1", 3, 10631),
+    // antichess, then two pawn races where whoever loses their pawn first wins
+    (Variant::Antichess, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1", 4, 153299),
+    (Variant::Antichess, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1", 5, 2732672),
+    (Variant::Antichess, "8/1p6/8/8/8/8/P7/8 w - - 0 1", 6, 0),
+    (Variant::Antichess, "8/2p5/8/8/8/8/P7/8 w - - 0 1", 12, 2557),
+    // horde, white's first rank pawns can double step but dont leave an en passant square behind
+    (Variant::Horde, "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1", 5, 265223),
+    (Variant::Horde, "4k3/pp4q1/3P2p1/8/P3PP2/PPP2r2/PPP5/PPPP4 b - - 0 1", 4, 56539),
+    (Variant::Horde, "k7/5p2/4p2P/3p2P1/2p2P2/1p2P2P/p2P2P1/2P2P2 w - - 0 1", 4, 33781),
+    // racing kings, the second with black's own pieces standing on the squares it would catch up on
+    (Variant::RacingKings, "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1", 4, 296242),
+    (Variant::RacingKings, "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1", 5, 9472927),
+    (Variant::RacingKings, "4brn1/2K2k2/8/8/8/8/8/8 w - - 0 1", 4, 3151),
+];
+
+//checks move generation against every known count up to max_nodes, returns false if any are off
+pub fn run_suite(max_nodes: u64, threads: usize, hash_mb: usize) -> bool {
+    let table = (hash_mb > 0).then(|| PerftTable::new(hash_mb));
+    let mut all_passed = true;
+    let positions = SUITE.iter()
+        .map(|&(fen, depth, nodes)| (Variant::Standard, fen, depth, nodes))
+        .chain(VARIANT_SUITE.iter().copied());
+    for (variant, fen, depth, expected) in positions.filter(|(_, _, _, n)| *n <= max_nodes) {
+        let start = std::time::Instant::now();
+        let mut board = board::Board::from_fen(fen);
+        board.set_variant(variant);
+        let nodes: u64 = divide(&board, depth, threads, table.as_ref())
+            .iter()
+            .map(|(_, n)| n)
+            .sum();
+        let passed = nodes == expected;
+        all_passed &= passed;
+        println!(
+            "{} depth {} {:>10} {:>10} {:.2}s  {}{}",
+            if passed { "ok  " } else { "FAIL" },
+            depth,
+            nodes,
+            expected,
+            start.elapsed().as_secs_f64(),
+            if variant == Variant::Standard { String::new() } else { format!("{} ", variant.name()) },
+            fen
+        );
+    }
+    all_passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //leaf counts from depth 1 up, for a position played under these rules
+    fn assert_counts(variant: Variant, fen: &str, counts: &[u64]) {
+        let mut board = board::Board::from_fen(fen);
+        board.set_variant(variant);
+        for (depth, &expected) in (1..).zip(counts) {
+            assert_eq!(perft(&mut board, depth, None), expected, "{} depth {} {}", variant.name(), depth, fen);
+        }
+    }
+
+    #[test]
+    fn standard() {
+        let positions: &[(&str, &[u64])] = &[
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197281]),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039, 97862]),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238]),
+            ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467]),
+            //en passant that gives check, and one that would leave the king in check from the bishop
+            ("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", &[15, 126, 1928, 13931]),
+            ("8/5bk1/8/2Pp4/8/1K6/8/8 w - d6 0 1", &[8, 104, 736]),
+        ];
+        for (fen, counts) in positions {
+            assert_counts(Variant::Standard, fen, counts);
+        }
+    }
+
+    #[test]
+    fn chess960() {
+        let positions: &[(&str, &[u64])] = &[
+            ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &[21, 528, 12189]),
+            ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", &[21, 807, 18002]),
+            ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", &[20, 479, 10471]),
+            ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", &[22, 593, 13440]),
+            ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", &[28, 1120, 31058]),
+        ];
+        for (fen, counts) in positions {
+            assert_counts(Variant::Standard, fen, counts);
+        }
+    }
+
+    #[test]
+    fn crazyhouse() {
+        let positions: &[(&str, &[u64])] = &[
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1", &[20, 400, 8902, 197281]),
+            //every kind of piece in both pockets
+            ("2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1", &[301, 75353]),
+            ("r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1", &[42, 1347, 58057]),
+            //the queen on b7 was a pawn, so taking it only pockets a pawn
+            ("4k3/1Q~6/8/8/4b3/8/Kpp5/8/ b - - 0 1", &[20, 360, 5445]),
+        ];
+        for (fen, counts) in positions {
+            assert_counts(Variant::Crazyhouse, fen, counts);
+        }
+    }
+
+    #[test]
+    fn atomic() {
+        let positions: &[(&str, &[u64])] = &[
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902]),
+            ("rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1", &[40, 1238, 45237]),
+            ("rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1", &[28, 833, 23353]),
+            //chess960 style castling with the kings close together
+            ("8/8/8/8/8/8/2k5/rR4KR w KQ - 0 1", &[18, 180, 4364]),
+            ("r3k1rR/5K2/8/8/8/8/8/8 b kq - 0 1", &[25, 282, 6753]),
+            ("Rr2k1rR/3K4/3p4/8/8/8/7P/8 w kq - 0 1", &[21, 465, 10631]),
+        ];
+        for (fen, counts) in positions {
+            assert_counts(Variant::Atomic, fen, counts);
+        }
+    }
+
+    #[test]
+    fn antichess() {
+        assert_counts(Variant::Antichess, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1", &[20, 400, 8067]);
+        //pawn races, whoever loses their pawn first wins and the game stops there
+        assert_counts(Variant::Antichess, "8/1p6/8/8/8/8/P7/8 w - - 0 1", &[2, 4, 4, 3, 1, 0]);
+        let mut board = board::Board::from_fen("8/2p5/8/8/8/8/P7/8 w - - 0 1");
+        board.set_variant(Variant::Antichess);
+        assert_eq!(perft(&mut board, 12, None), 2557);
+    }
+}
@@ -0,0 +1,148 @@
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Clone)]
+pub struct PgnMove {
+    pub san: String,
+    //the {} comment right after the move, if there is one
+    pub comment: Option::<String>,
+}
+
+pub struct PgnGame {
+    pub tags: Vec::<(String, String)>,
+    pub moves: Vec::<PgnMove>,
+    //1-0, 0-1, 1/2-1/2 or *
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option::<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn start_fen(&self) -> &str {
+        self.tag("FEN").unwrap_or(START_FEN)
+    }
+}
+
+fn parse_tag(tag: &str) -> Option::<(String, String)> {
+    let (name, value) = tag.trim().split_once(char::is_whitespace)?;
+    let value = value.trim().trim_matches('"').replace("\\\"", "\"");
+    Some((name.to_owned(), value))
+}
+
+//reads every game in a pgn file. variations and nags are skipped, only the main line is kept
+pub fn parse(text: &str) -> Vec::<PgnGame> {
+    let mut games = Vec::<PgnGame>::new();
+    let mut game = PgnGame { tags: vec![], moves: vec![], result: "*".to_owned() };
+    let mut chars = text.chars().peekable();
+    let mut variation_depth = 0;
+
+    let finish = |game: &mut PgnGame, games: &mut Vec::<PgnGame>| {
+        let done = std::mem::replace(game, PgnGame { tags: vec![], moves: vec![], result: "*".to_owned() });
+        if !done.tags.is_empty() || !done.moves.is_empty() {
+            games.push(done);
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                if variation_depth == 0 {
+                    if let Some(m) = game.moves.last_mut() {
+                        m.comment = Some(comment.trim().to_owned());
+                    }
+                }
+            }
+            ';' => {
+                chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+            }
+            '(' => variation_depth += 1,
+            ')' => variation_depth -= 1,
+            '[' if variation_depth == 0 => {
+                //tags after movetext means the next game started without a result
+                if !game.moves.is_empty() {
+                    finish(&mut game, &mut games);
+                }
+                let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if let Some(tag) = parse_tag(&tag) {
+                    game.tags.push(tag);
+                }
+            }
+            c if c.is_whitespace() => (),
+            _ => {
+                let mut token = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || "{}();[".contains(next) {
+                        break;
+                    }
+                    token.push(next);
+                    chars.next();
+                }
+                if variation_depth > 0 || token.starts_with('$') {
+                    continue;
+                }
+                match token.as_str() {
+                    "1-0" | "0-1" | "1/2-1/2" | "*" => {
+                        game.result = token;
+                        finish(&mut game, &mut games);
+                    }
+                    _ => {
+                        //move numbers, like 12. or 12... or stuck to the move as 12.e4
+                        let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                        if !san.is_empty() {
+                            game.moves.push(PgnMove { san: san.to_owned(), comment: None });
+                        }
+                    }
+                }
+            }
+        }
+    }
+    finish(&mut game, &mut games);
+    games
+}
+
+//writes a game out, move numbers follow the start fen and the movetext is wrapped under 80 columns
+pub fn write(game: &PgnGame) -> String {
+    let mut res = String::new();
+    for (name, value) in &game.tags {
+        res.push_str(&format!("[{} \"{}\"]\n", name, value.replace('"', "\\\"")));
+    }
+    res.push('\n');
+    let mut fields = game.start_fen().split_whitespace().skip(1);
+    let mut black = fields.next() == Some("b");
+    let mut number = fields.nth(3).and_then(|n| n.parse::<u32>().ok()).unwrap_or(1);
+    let mut tokens = Vec::<String>::new();
+    for (i, m) in game.moves.iter().enumerate() {
+        //black's moves need their number again after a comment, or as the first move
+        if !black {
+            tokens.push(format!("{}.", number));
+        } else if i == 0 || game.moves[i - 1].comment.is_some() {
+            tokens.push(format!("{}...", number));
+        }
+        tokens.push(m.san.clone());
+        if let Some(comment) = &m.comment {
+            tokens.push(format!("{{{}}}", comment));
+        }
+        if black {
+            number += 1;
+        }
+        black = !black;
+    }
+    tokens.push(game.result.clone());
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() >= 80 {
+            res.push_str(&line);
+            res.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    res.push_str(&line);
+    res.push_str("\n\n");
+    res
+}
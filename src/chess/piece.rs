@@ -33,7 +33,7 @@ impl Player {
 }
 
 impl Piece{
-    pub fn to_char(&self)-> char {
+    pub fn to_char(self)-> char {
         match self {
             Piece::Pawn(p) => {
                 match p {
@@ -83,4 +83,36 @@ impl Piece{
                 => Some(*p),
         }
     }
+    //fen style letter, uppercase for white
+    pub fn to_char_ascii(self) -> char {
+        let c = match self {
+            Self::Empty => ' ',
+            Self::Pawn(_) => 'p',
+            Self::Knight(_) => 'n',
+            Self::Bishop(_) => 'b',
+            Self::Rook(_) => 'r',
+            Self::Queen(_) => 'q',
+            Self::King(_) => 'k',
+        };
+        match self.owner() {
+            Some(Player::White) => c.to_ascii_uppercase(),
+            _ => c,
+        }
+    }
+    //index into per piece lookup tables, white pieces are 0..6 and black are 6..12
+    pub fn index(self) -> usize {
+        let kind = match self {
+            Self::Empty => panic!("Board Mangled"),
+            Self::Pawn(_) => 0,
+            Self::Knight(_) => 1,
+            Self::Bishop(_) => 2,
+            Self::Rook(_) => 3,
+            Self::Queen(_) => 4,
+            Self::King(_) => 5,
+        };
+        match self.owner() {
+            Some(Player::White) => kind,
+            _ => kind + 6,
+        }
+    }
 }
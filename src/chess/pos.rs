@@ -83,16 +83,18 @@ impl std::ops::AddAssign::<Self> for Square {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum MoveType {
     Capture(piece::Piece),
     Castle(Square),
     EnPassant, //needed to keep track of the piece captured on a diff square
     Move,
     Promotion(piece::Piece),
+    CapturePromotion(piece::Piece, piece::Piece), //piece captured, piece promoted to
+    Drop, //crazyhouse, the piece comes out of the pocket onto new_pos. old_pos is the same square
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Move {
     pub piece: piece::Piece, 
     pub move_type: MoveType,
@@ -104,4 +106,60 @@ pub struct MoveCandidate {
     pub old_pos: Square,
     pub new_pos: Square,
     pub promote_to: Option::<piece::Piece>,
-}
\ No newline at end of file
+}
+
+impl Square {
+    //0..64 index, a1 is 0 and h8 is 63. used for lookup tables
+    pub fn index(&self) -> usize {
+        (self.rank as usize - 1) * 8 + (self.file as usize - 1)
+    }
+}
+
+impl Move {
+    //what a pawn turns into, if this is a promotion
+    pub fn promotion(&self) -> Option::<piece::Piece> {
+        match self.move_type {
+            MoveType::Promotion(p) | MoveType::CapturePromotion(_, p) => Some(p),
+            _ => None,
+        }
+    }
+    pub fn is_capture(&self) -> bool {
+        matches!(self.move_type, MoveType::Capture(_) | MoveType::CapturePromotion(..) | MoveType::EnPassant)
+    }
+}
+
+impl std::fmt::Display for Square {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}{}", (b'a' + (self.file - 1) as u8) as char, self.rank)
+    }
+}
+
+impl Move {
+    //uci coordinates. with chess960 on every castle is written as the king taking its own rook
+    pub fn to_uci(self, chess960: bool) -> String {
+        match self.move_type {
+            MoveType::Castle(rook_pos) if chess960 => format!("{}{}", self.old_pos, rook_pos),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Move {
+    //coordinate notation, e2e4, e7e8q or N@f3 for a drop. castling is the king's two step, unless it's a chess960 castle
+    //where that could be mistaken for a normal king move, then it's the king taking its rook
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.move_type == MoveType::Drop {
+            return write!(f, "{}@{}", self.piece.to_char_ascii().to_ascii_uppercase(), self.new_pos);
+        }
+        if let MoveType::Castle(rook_pos) = self.move_type {
+            if self.old_pos.file != 5 || (rook_pos.file != 1 && rook_pos.file != 8) {
+                return write!(f, "{}{}", self.old_pos, rook_pos);
+            }
+        }
+        write!(f, "{}{}", self.old_pos, self.new_pos)?;
+        if let Some(p) = self.promotion() {
+            write!(f, "{}", p.to_char_ascii().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use crate::chess::{find_move, get_input, san, validator, Game, GameResult, Player};
+
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use termion::color;
+
+const START_RATING: f64 = 1500.0;
+// how far the rating moves after one puzzle, at most
+const K_FACTOR: f64 = 32.0;
+// puzzles within this much of the rating are picked first
+const RATING_WINDOW: i32 = 200;
+
+//a line of the lichess puzzle csv: PuzzleId,FEN,Moves,Rating,... the fen is before the opponent's move,
+//the first of the moves, then the moves alternate between the solver and the replies
+struct Puzzle {
+    id: String,
+    fen: String,
+    moves: Vec::<String>,
+    rating: i32,
+}
+
+fn parse_line(line: &str) -> Option::<Puzzle> {
+    let fields: Vec::<&str> = line.split(',').collect();
+    let moves: Vec::<String> = fields.get(2)?.split_whitespace().map(|m| m.to_owned()).collect();
+    if moves.len() < 2 {
+        return None;
+    }
+    Some(Puzzle {
+        id: fields[0].to_owned(),
+        fen: fields[1].to_owned(),
+        moves,
+        rating: fields.get(3)?.trim().parse().ok()?,
+    })
+}
+
+//the history has a line per puzzle tried: id, solved, failed or skipped, the puzzle's rating and ours after it
+fn read_history(path: &str) -> (f64, HashSet::<String>) {
+    let text = std::fs::read_to_string(path).unwrap_or_default();
+    let mut rating = START_RATING;
+    let mut tried = HashSet::<String>::new();
+    for line in text.lines() {
+        let fields: Vec::<&str> = line.split_whitespace().collect();
+        if let [id, _, _, after] = fields[..] {
+            tried.insert(id.to_owned());
+            rating = after.parse().unwrap_or(rating);
+        }
+    }
+    (rating, tried)
+}
+
+//the first puzzle not tried yet near the rating, or just the first one not tried when none are near
+fn next_puzzle(path: &str, rating: f64, tried: &HashSet::<String>) -> Result::<Option::<Puzzle>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("couldn't read {}: {}", path, e))?;
+    let mut fallback = None;
+    for line in std::io::BufReader::new(file).lines() {
+        let line = line.map_err(|e| format!("couldn't read {}: {}", path, e))?;
+        let puzzle = match parse_line(&line) {
+            Some(p) if !tried.contains(&p.id) => p,
+            _ => continue,
+        };
+        if (puzzle.rating - rating as i32).abs() <= RATING_WINDOW {
+            return Ok(Some(puzzle));
+        }
+        if fallback.is_none() {
+            fallback = Some(puzzle);
+        }
+    }
+    Ok(fallback)
+}
+
+fn red(msg: &str) {
+    println!("{}{}{}", color::Fg(color::Red), msg, color::Fg(color::Reset));
+}
+
+//plays through one puzzle on the board the game ui shows. some(solved), or none when the user quits.
+//a mate is a solution whether or not it's the one in the file
+fn solve(puzzle: &Puzzle) -> Result::<Option::<bool>, String> {
+    let mut game = Game::from_fen(&puzzle.fen);
+    game.play_move(&puzzle.moves[0]).map_err(|e| format!("puzzle {}: {}", puzzle.id, e))?;
+    let solver = game.board.active_player();
+    println!(
+        "puzzle {}, rated {}: {} to move. hint shows the piece to move, quit stops",
+        puzzle.id,
+        puzzle.rating,
+        match solver {
+            Player::White => "White",
+            Player::Black => "Black",
+        }
+    );
+    for (i, expected) in puzzle.moves.iter().enumerate().skip(1).step_by(2) {
+        let legal_moves = validator::get_possible_moves(&mut game.board);
+        let expected = find_move(&legal_moves, expected, solver).map_err(|e| format!("puzzle {}: {}", puzzle.id, e))?;
+        let answer = loop {
+            game.show();
+            let input = get_input();
+            match input.trim() {
+                //end of input, nobody is there to answer
+                _ if input.is_empty() => return Ok(None),
+                "quit" => return Ok(None),
+                "hint" => red(&format!("move the piece on {}", expected.old_pos)),
+                text => match find_move(&legal_moves, text, solver) {
+                    Ok(m) => break m,
+                    Err(msg) => red(&msg),
+                },
+            }
+        };
+        let san = san::to_san(&game.board, &answer);
+        game.record_move(&answer, None);
+        if answer != expected {
+            if let Some(GameResult::Win(winner, _)) = game.result() {
+                if winner == solver {
+                    println!("{} is mate too, solved!", san);
+                    return Ok(Some(true));
+                }
+            }
+            red(&format!("{} is wrong, the answer was {}", san, puzzle.moves[i]));
+            return Ok(Some(false));
+        }
+        println!("{} is right", san);
+        if let Some(reply) = puzzle.moves.get(i + 1) {
+            let reply = find_move(&validator::get_possible_moves(&mut game.board), reply, solver.invert())
+                .map_err(|e| format!("puzzle {}: {}", puzzle.id, e))?;
+            println!("they answer {}", san::to_san(&game.board, &reply));
+            game.record_move(&reply, None);
+        }
+    }
+    println!("solved!");
+    Ok(Some(true))
+}
+
+fn append(path: &str, line: &str) -> Result::<(), String> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut f| f.write_all(line.as_bytes()))
+        .map_err(|e| format!("couldn't write {}: {}", path, e))
+}
+
+//puzzles one after another from a lichess style csv, each one moving an elo rating kept in the history file
+pub fn run(csv: &str, history: &str) -> Result::<(), String> {
+    loop {
+        let (rating, tried) = read_history(history);
+        println!("your rating {:.0}, {} puzzles tried", rating, tried.len());
+        let puzzle = match next_puzzle(csv, rating, &tried)? {
+            Some(p) => p,
+            None => {
+                println!("no puzzles left in {}", csv);
+                return Ok(());
+            }
+        };
+        let solved = match solve(&puzzle) {
+            Ok(Some(s)) => s,
+            Ok(None) => return Ok(()),
+            //a broken puzzle is written down as skipped so it doesn't come up again
+            Err(msg) => {
+                println!("{}, skipped", msg);
+                append(history, &format!("{} skipped {} {:.1}\n", puzzle.id, puzzle.rating, rating))?;
+                continue;
+            }
+        };
+        let expected = 1.0 / (1.0 + 10f64.powf((puzzle.rating as f64 - rating) / 400.0));
+        let after = rating + K_FACTOR * (if solved { 1.0 } else { 0.0 } - expected);
+        println!("rating {:.0} ({:+.0})", after, after - rating);
+        let line = format!("{} {} {} {:.1}\n", puzzle.id, if solved { "solved" } else { "failed" }, puzzle.rating, after);
+        append(history, &line)?;
+    }
+}
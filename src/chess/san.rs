@@ -0,0 +1,85 @@
+use crate::chess::{board, piece, pos, validator};
+
+//standard algebraic notation without the check suffix, legal_moves is used to tell apart pieces that can reach the same square
+fn san_body(legal_moves: &[pos::Move], m: &pos::Move) -> String {
+    if m.move_type == pos::MoveType::Drop {
+        return m.to_string();
+    }
+    if let pos::MoveType::Castle(rook_pos) = m.move_type {
+        return if rook_pos.file > m.old_pos.file { "O-O".to_owned() } else { "O-O-O".to_owned() };
+    }
+    let capture = matches!(
+        m.move_type,
+        pos::MoveType::Capture(_) | pos::MoveType::CapturePromotion(..) | pos::MoveType::EnPassant
+    );
+    let mut res = String::new();
+    if let piece::Piece::Pawn(_) = m.piece {
+        if capture {
+            res.push((b'a' + (m.old_pos.file - 1) as u8) as char);
+        }
+    } else {
+        res.push(m.piece.to_char_ascii().to_ascii_uppercase());
+        let rivals: Vec::<&pos::Move> = legal_moves
+            .iter()
+            .filter(|o| o.piece == m.piece && o.new_pos == m.new_pos && o.old_pos != m.old_pos)
+            .collect();
+        if !rivals.is_empty() {
+            let file = (b'a' + (m.old_pos.file - 1) as u8) as char;
+            let rank = (b'0' + m.old_pos.rank as u8) as char;
+            if rivals.iter().all(|o| o.old_pos.file != m.old_pos.file) {
+                res.push(file);
+            } else if rivals.iter().all(|o| o.old_pos.rank != m.old_pos.rank) {
+                res.push(rank);
+            } else {
+                res.push(file);
+                res.push(rank);
+            }
+        }
+    }
+    if capture {
+        res.push('x');
+    }
+    res.push_str(&m.new_pos.to_string());
+    if let Some(p) = m.promotion() {
+        res.push('=');
+        res.push(p.to_char_ascii().to_ascii_uppercase());
+    }
+    res
+}
+
+//a move in standard algebraic notation, like Nbd7, exd6, e8=Q+ or N@f3
+pub fn to_san(board: &board::Board, m: &pos::Move) -> String {
+    let legal_moves = validator::get_possible_moves(&mut board.clone());
+    let mut res = san_body(&legal_moves, m);
+    let mut after = board.clone();
+    after.make_move(m);
+    if after.is_in_check(after.active_player()) {
+        if validator::get_possible_moves(&mut after).is_empty() {
+            res.push('#');
+        } else {
+            res.push('+');
+        }
+    }
+    res
+}
+
+//leaves out the parts people and other programs are sloppy with
+fn normalize(san: &str) -> String {
+    san.trim_end_matches(['+', '#', '!', '?'])
+        .trim_end_matches("e.p.")
+        .replace('0', "O")
+        .chars()
+        .filter(|c| !matches!(c, 'x' | '=' | ':'))
+        .collect()
+}
+
+//finds the legal move a san string means, coordinate notation is accepted too
+pub fn parse_san(board: &board::Board, san: &str) -> Result::<pos::Move, String> {
+    let legal_moves = validator::get_possible_moves(&mut board.clone());
+    let wanted = normalize(san.trim());
+    legal_moves
+        .iter()
+        .find(|m| normalize(&san_body(&legal_moves, m)) == wanted || m.to_string() == san.trim())
+        .copied()
+        .ok_or_else(|| format!("illegal move {}", san))
+}
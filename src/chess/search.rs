@@ -0,0 +1,808 @@
+use crate::chess::{board, eval, ordering, pos, syzygy, time, tt, validator, zobrist};
+use crate::chess::variant::Variant;
+use crate::chess::{GameResult, Player};
+
+use ordering::MAX_PLY;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+pub const MATE: i32 = 30_000;
+pub const INFINITY: i32 = 32_000;
+// any score past this is a mate, how far past says how many plies away it is
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+// tablebase wins score below any mate, and like mates they are worth less the further away they are
+pub const TB_WIN: i32 = MATE_BOUND - 1 - MAX_PLY as i32;
+pub const TB_BOUND: i32 = TB_WIN - MAX_PLY as i32;
+// deepest iteration a search without a depth limit will go to
+pub const MAX_DEPTH: i32 = 64;
+
+// how far past alpha the static eval has to be before quiet moves are skipped, per ply of depth left
+const FUTILITY_MARGIN: i32 = 150;
+// how far past beta the static eval has to be before the node is cut, per ply of depth left
+const REVERSE_FUTILITY_MARGIN: i32 = 120;
+const REVERSE_FUTILITY_DEPTH: i32 = 6;
+const NULL_MOVE_DEPTH: i32 = 3;
+const LMR_DEPTH: i32 = 3;
+// moves searched at full depth before late move reductions start
+const LMR_MOVES: usize = 3;
+const ASPIRATION_WINDOW: i32 = 50;
+const ASPIRATION_DEPTH: i32 = 4;
+// plies without a capture or pawn move before the game is a draw
+const FIFTY_MOVES: u32 = 100;
+
+//every selectivity trick can be turned off, to measure what it buys with the bench
+#[derive(Clone, Copy)]
+pub struct SearchOptions {
+    pub move_ordering: bool,
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub futility: bool,
+    pub reverse_futility: bool,
+    pub aspiration_windows: bool,
+    pub check_extensions: bool,
+    pub hash_mb: usize,
+    // lazy smp, 1 is a plain single threaded search that always gives the same result
+    pub threads: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            move_ordering: true,
+            null_move: true,
+            late_move_reductions: true,
+            futility: true,
+            reverse_futility: true,
+            aspiration_windows: true,
+            check_extensions: true,
+            hash_mb: 16,
+            threads: 1,
+        }
+    }
+}
+
+impl SearchOptions {
+    //plain alpha beta with move ordering, nothing pruned, reduced or extended
+    pub fn no_pruning() -> Self {
+        Self {
+            null_move: false,
+            late_move_reductions: false,
+            futility: false,
+            reverse_futility: false,
+            aspiration_windows: false,
+            check_extensions: false,
+            ..Self::default()
+        }
+    }
+}
+
+pub struct SearchResult {
+    pub best_move: Option::<pos::Move>,
+    pub score: i32, //centipawns for the player to move
+    pub depth: i32,
+    pub nodes: u64,
+    pub tb_hits: u64,
+    pub pv: Vec::<pos::Move>,
+}
+
+//later moves in a well ordered list are less likely to be good, so they get searched shallower
+fn late_move_reduction(depth: i32, move_number: usize) -> i32 {
+    (0.75 + (depth as f64).ln() * (move_number as f64).ln() / 2.25) as i32
+}
+
+// mate and tablebase scores are stored relative to the node so they stay correct wherever the position is found again
+//a finished game from the side to move's point of view, sooner wins score higher
+fn result_score(result: GameResult, player: Player, ply: usize) -> i32 {
+    match result {
+        GameResult::Win(winner, _) if winner == player => MATE - ply as i32,
+        GameResult::Win(..) => -MATE + ply as i32,
+        GameResult::Draw => 0,
+    }
+}
+
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= TB_BOUND {
+        score + ply as i32
+    } else if score <= -TB_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= TB_BOUND {
+        score - ply as i32
+    } else if score <= -TB_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+//iterative deepening alpha beta searcher, keeps its hash table and move ordering tables between searches.
+//with more than one thread, helper searchers run the same root alongside it and share its hash table
+pub struct Searcher {
+    options: SearchOptions,
+    tt: Arc::<tt::TranspositionTable>,
+    tables: ordering::OrderingTables,
+    nodes: u64,
+    time: Option::<time::TimeManager>,
+    // helpers stop when this gets set, instead of watching the clock
+    abort: Option::<Arc::<AtomicBool>>,
+    // set once the time manager says to abort, everything unwinds without trusting the scores
+    stopped: bool,
+    root_depth: i32,
+    helpers: Vec::<Searcher>,
+    tablebases: Option::<Arc::<syzygy::Tablebases>>,
+    // when the root is in the tablebases, only the moves that keep its result get searched
+    root_moves: Option::<Vec::<pos::Move>>,
+    // moves already shown as earlier lines of a multipv search, left out at the root
+    excluded: Vec::<pos::Move>,
+    tb_hits: u64,
+    // the game's positions before the root, and the ones from the root down to the node being searched
+    history: Vec::<u64>,
+    path: Vec::<u64>,
+}
+
+// how many nodes go by between looking at the clock
+const TIME_CHECK_NODES: u64 = 1024;
+
+impl Searcher {
+    pub fn new(options: SearchOptions) -> Self {
+        let tt = Arc::new(tt::TranspositionTable::new(options.hash_mb));
+        let helpers = (1..options.threads.max(1))
+            .map(|_| Self::with_table(SearchOptions { threads: 1, ..options }, tt.clone(), vec![]))
+            .collect();
+        Self::with_table(options, tt, helpers)
+    }
+
+    fn with_table(options: SearchOptions, tt: Arc::<tt::TranspositionTable>, helpers: Vec::<Searcher>) -> Self {
+        Self {
+            options,
+            tt,
+            tables: ordering::OrderingTables::new(),
+            nodes: 0,
+            time: None,
+            abort: None,
+            stopped: false,
+            root_depth: 0,
+            helpers,
+            tablebases: None,
+            root_moves: None,
+            excluded: vec![],
+            tb_hits: 0,
+            history: vec![],
+            path: vec![],
+        }
+    }
+
+    pub fn set_tablebases(&mut self, tablebases: Option::<Arc::<syzygy::Tablebases>>) {
+        for helper in self.helpers.iter_mut() {
+            helper.set_tablebases(tablebases.clone());
+        }
+        self.tablebases = tablebases;
+    }
+
+    //the positions the game went through before the one being searched, oldest first
+    pub fn set_history(&mut self, history: &[u64]) {
+        for helper in self.helpers.iter_mut() {
+            helper.set_history(history);
+        }
+        self.history = history.to_vec();
+    }
+
+    pub fn search(&mut self, board: &board::Board, max_depth: i32) -> SearchResult {
+        self.iterate(board, max_depth, None, &mut |_| ())
+    }
+
+    //searches until the time manager runs out or max depth is reached, reporting every finished iteration.
+    //an aborted iteration is thrown away, so the result is always from the last one that finished
+    pub fn search_timed(
+        &mut self,
+        board: &board::Board,
+        max_depth: i32,
+        time: time::TimeManager,
+        report: &mut dyn FnMut(&SearchResult),
+    ) -> SearchResult {
+        self.iterate(board, max_depth, Some(time), report)
+    }
+
+    //the best few moves, each with its own line. every depth searches the root once per line with the moves of the
+    //lines before it left out. helpers aren't used, each line needs the whole root to itself
+    pub fn search_multipv(
+        &mut self,
+        board: &board::Board,
+        max_depth: i32,
+        lines: usize,
+        time: time::TimeManager,
+        report: &mut dyn FnMut(&[SearchResult]),
+    ) -> Vec::<SearchResult> {
+        let lines = lines.clamp(1, validator::get_possible_moves(&mut board.clone()).len().max(1));
+        self.root_moves = self.tablebases.as_ref().and_then(|tb| tb.root_moves(board)).map(|(_, moves)| moves);
+        self.nodes = 0;
+        self.tb_hits = 0;
+        self.stopped = false;
+        self.time = Some(time);
+        let mut results = Vec::<SearchResult>::new();
+        'deepening: for depth in 1..=max_depth.max(1) {
+            self.root_depth = depth;
+            let mut found = Vec::<SearchResult>::new();
+            for line in 0..lines {
+                self.excluded = found.iter().filter_map(|r| r.best_move).collect();
+                let guess = results.get(line).map_or(0, |r| r.score);
+                let mut pv = Vec::new();
+                let score = self.search_root(board, depth, guess, &mut pv);
+                if self.stopped {
+                    break 'deepening;
+                }
+                //tablebases can leave fewer root moves than lines
+                if pv.is_empty() {
+                    break;
+                }
+                found.push(SearchResult {
+                    best_move: pv.first().copied(),
+                    score,
+                    depth,
+                    nodes: self.nodes,
+                    tb_hits: self.tb_hits,
+                    pv,
+                });
+            }
+            found.sort_by_key(|r| -r.score);
+            if let (Some(time), Some(best), Some(last)) = (self.time.as_mut(), found.first(), results.first()) {
+                time.update(best.best_move != last.best_move, last.score - best.score);
+            }
+            results = found;
+            report(&results);
+            if self.time.as_ref().is_some_and(|t| !t.should_start_iteration()) {
+                break;
+            }
+        }
+        self.excluded.clear();
+        self.time = None;
+        results
+    }
+
+    fn iterate(
+        &mut self,
+        board: &board::Board,
+        max_depth: i32,
+        time: Option::<time::TimeManager>,
+        report: &mut dyn FnMut(&SearchResult),
+    ) -> SearchResult {
+        let root_moves = self.tablebases.as_ref().and_then(|tb| tb.root_moves(board)).map(|(_, moves)| moves);
+        for helper in self.helpers.iter_mut() {
+            helper.root_moves = root_moves.clone();
+        }
+        self.root_moves = root_moves;
+        if self.helpers.is_empty() {
+            return self.iterate_main(board, max_depth, time, report);
+        }
+        //lazy smp, the helpers fill the shared hash table while this thread does the real search
+        let mut helpers = std::mem::take(&mut self.helpers);
+        let abort = Arc::new(AtomicBool::new(false));
+        let result = std::thread::scope(|scope| {
+            let handles: Vec::<_> = helpers.iter_mut()
+                .enumerate()
+                .map(|(i, helper)| {
+                    let abort = abort.clone();
+                    scope.spawn(move || helper.help(board, i + 1, abort))
+                })
+                .collect();
+            let mut result = self.iterate_main(board, max_depth, time, report);
+            abort.store(true, Ordering::Relaxed);
+            for handle in handles {
+                result.nodes += handle.join().expect("search thread panicked");
+            }
+            result
+        });
+        self.helpers = helpers;
+        result
+    }
+
+    //helpers search the same position as the main thread, half of them a ply deeper so they arent all in lockstep.
+    //their results are never used directly, only through what they leave in the hash table
+    fn help(&mut self, board: &board::Board, id: usize, abort: Arc::<AtomicBool>) -> u64 {
+        self.nodes = 0;
+        self.tb_hits = 0;
+        self.stopped = false;
+        self.abort = Some(abort);
+        let mut score = 0;
+        for depth in (1 + (id % 2) as i32)..=MAX_DEPTH {
+            self.root_depth = depth;
+            score = self.search_root(board, depth, score, &mut Vec::new());
+            if self.stopped {
+                break;
+            }
+        }
+        self.abort = None;
+        self.nodes
+    }
+
+    fn search_root(&mut self, board: &board::Board, depth: i32, guess: i32, pv: &mut Vec::<pos::Move>) -> i32 {
+        let mut root = board.clone();
+        if self.options.aspiration_windows && depth >= ASPIRATION_DEPTH {
+            self.aspiration(&mut root, depth, guess, pv)
+        } else {
+            self.alpha_beta(&mut root, depth, -INFINITY, INFINITY, 0, None, pv)
+        }
+    }
+
+    fn iterate_main(
+        &mut self,
+        board: &board::Board,
+        max_depth: i32,
+        time: Option::<time::TimeManager>,
+        report: &mut dyn FnMut(&SearchResult),
+    ) -> SearchResult {
+        self.nodes = 0;
+        self.tb_hits = 0;
+        self.stopped = false;
+        self.time = time;
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            tb_hits: 0,
+            pv: vec![],
+        };
+        for depth in 1..=max_depth.max(1) {
+            self.root_depth = depth;
+            let mut pv = Vec::new();
+            let score = self.search_root(board, depth, result.score, &mut pv);
+            //the first iteration is never aborted, so there is always a move to play
+            if self.stopped {
+                break;
+            }
+            let best_move = pv.first().copied();
+            if let Some(time) = self.time.as_mut() {
+                if depth > 1 {
+                    time.update(best_move != result.best_move, result.score - score);
+                }
+            }
+            result = SearchResult {
+                best_move,
+                score,
+                depth,
+                nodes: self.nodes,
+                tb_hits: self.tb_hits,
+                pv,
+            };
+            report(&result);
+            //no point looking deeper once a forced mate is found
+            if score.abs() >= MATE_BOUND {
+                break;
+            }
+            if self.time.as_ref().is_some_and(|t| !t.should_start_iteration()) {
+                break;
+            }
+        }
+        self.time = None;
+        result.nodes = self.nodes;
+        result
+    }
+
+    //checks the clock every so often, never during the main thread's first iteration
+    fn check_time(&mut self) -> bool {
+        if !self.stopped && self.nodes.is_multiple_of(TIME_CHECK_NODES) {
+            if let Some(abort) = &self.abort {
+                self.stopped = abort.load(Ordering::Relaxed);
+            } else if let Some(time) = &self.time {
+                self.stopped = self.root_depth > 1 && time.should_abort();
+            }
+        }
+        self.stopped
+    }
+
+    //whether the position came up before with the same side to move since the last capture or pawn move.
+    //once is enough, if repeating it is best then it can be repeated again
+    fn repeated(&self, halfmove_clock: u32) -> bool {
+        let mut keys = self.history.iter().chain(&self.path).rev();
+        let key = keys.next();
+        keys.skip(1).step_by(2).take(halfmove_clock as usize / 2).any(|k| Some(k) == key)
+    }
+
+    //search a small window around the last iteration's score, widening it whenever the score falls outside
+    fn aspiration(&mut self, root: &mut board::Board, depth: i32, guess: i32, pv: &mut Vec::<pos::Move>) -> i32 {
+        let mut delta = ASPIRATION_WINDOW;
+        let mut alpha = (guess - delta).max(-INFINITY);
+        let mut beta = (guess + delta).min(INFINITY);
+        loop {
+            let score = self.alpha_beta(root, depth, alpha, beta, 0, None, pv);
+            if self.stopped {
+                return score;
+            }
+            if score <= alpha && alpha > -INFINITY {
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta && beta < INFINITY {
+                beta = (score + delta).min(INFINITY);
+            } else {
+                return score;
+            }
+            delta *= 2;
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn alpha_beta(
+        &mut self,
+        board: &mut board::Board,
+        depth: i32,
+        mut alpha: i32,
+        beta: i32,
+        ply: usize,
+        prev: Option::<pos::Move>,
+        pv: &mut Vec::<pos::Move>,
+    ) -> i32 {
+        pv.clear();
+        //ended by the variant's rules on the last move, wins scored like mates
+        if let Some(result) = board.variant().game_over(board) {
+            return result_score(result, board.active_player(), ply);
+        }
+        let key = zobrist::hash(board);
+        self.path.truncate(ply);
+        self.path.push(key);
+        //draws by repetition or the fifty move rule, unless the last move mated
+        if ply > 0
+            && (self.repeated(board.halfmove_clock())
+                || (board.halfmove_clock() >= FIFTY_MOVES
+                    && (!board.is_in_check(board.active_player()) || !validator::get_possible_moves(board).is_empty())))
+        {
+            return 0;
+        }
+        if depth <= 0 {
+            return self.quiescence(board, alpha, beta, ply);
+        }
+        self.nodes += 1;
+        if self.check_time() {
+            return 0;
+        }
+        if ply >= MAX_PLY - 1 {
+            return eval::evaluate(board);
+        }
+
+        let player = board.active_player();
+        let in_check = board.is_in_check(player);
+        //dont let a check run into the horizon
+        let depth = if in_check && self.options.check_extensions { depth + 1 } else { depth };
+        let is_pv = beta - alpha > 1;
+
+        let mut tt_move = None;
+        if let Some(entry) = self.tt.probe(key) {
+            tt_move = entry.best_move;
+            if ply > 0 && !is_pv && entry.depth >= depth {
+                let score = score_from_tt(entry.score, ply);
+                match entry.bound {
+                    tt::Bound::Exact => return score,
+                    tt::Bound::Lower if score >= beta => return score,
+                    tt::Bound::Upper if score <= alpha => return score,
+                    _ => (),
+                }
+            }
+        }
+
+        //right after a capture or pawn move the position may be in the tablebases.
+        //a win is only a lower bound and a loss an upper one, since the search could still find a mate
+        if ply > 0 && board.halfmove_clock() == 0 {
+            if let Some(wdl) = self.tablebases.as_ref().and_then(|tb| tb.probe_wdl(board)) {
+                self.tb_hits += 1;
+                let (score, bound) = match wdl {
+                    syzygy::Wdl::Win => (TB_WIN - ply as i32, tt::Bound::Lower),
+                    syzygy::Wdl::Loss => (-TB_WIN + ply as i32, tt::Bound::Upper),
+                    //draws by the fifty move rule, just a hair off zero so a real win or loss is still preferred
+                    wdl => (wdl as i32, tt::Bound::Exact),
+                };
+                if bound == tt::Bound::Exact
+                    || (bound == tt::Bound::Lower && score >= beta)
+                    || (bound == tt::Bound::Upper && score <= alpha)
+                {
+                    self.tt.store(tt::Entry {
+                        key,
+                        depth: (depth + 6).min(MAX_DEPTH),
+                        score: score_to_tt(score, ply),
+                        bound,
+                        best_move: None,
+                    });
+                    return score;
+                }
+            }
+        }
+
+        let static_eval = if in_check { -INFINITY } else { eval::evaluate(board) };
+
+        //so far ahead that even giving up some eval per ply left would still beat beta
+        if self.options.reverse_futility
+            && !is_pv
+            && !in_check
+            && depth <= REVERSE_FUTILITY_DEPTH
+            && beta.abs() < MATE_BOUND
+            && static_eval - REVERSE_FUTILITY_MARGIN * depth >= beta
+        {
+            return static_eval;
+        }
+
+        //if passing still beats beta a real move will too, except in pawn endings where passing would be a zugzwang escape,
+        //or antichess where it nearly always would be.
+        //prev is none after a null move, so two nulls are never played in a row
+        if self.options.null_move
+            && !is_pv
+            && !in_check
+            && prev.is_some()
+            && depth >= NULL_MOVE_DEPTH
+            && static_eval >= beta
+            && board.has_non_pawn_material(player)
+            && board.variant() != Variant::Antichess
+        {
+            let reduction = 2 + depth / 4;
+            let mut child = board.clone();
+            child.make_null_move();
+            let score = -self.alpha_beta(&mut child, depth - 1 - reduction, -beta, -beta + 1, ply + 1, None, &mut Vec::new());
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta {
+                //null move mates arent real mates
+                return if score >= MATE_BOUND { beta } else { score };
+            }
+        }
+
+        let mut moves = validator::get_possible_moves(board);
+        if ply == 0 {
+            if let Some(root_moves) = &self.root_moves {
+                moves.retain(|m| root_moves.contains(m));
+            }
+            moves.retain(|m| !self.excluded.contains(m));
+        }
+        if moves.is_empty() {
+            return result_score(board.variant().out_of_moves(board), player, ply);
+        }
+        if self.options.move_ordering {
+            self.tables.order(&mut moves, tt_move.as_ref(), ply, prev.as_ref());
+        }
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        for (i, m) in moves.iter().enumerate() {
+            let quiet = ordering::is_quiet(m);
+            let mut child = board.clone();
+            child.make_move(m);
+            let gives_check = child.is_in_check(child.active_player());
+
+            //near the leaves a quiet move wont make up a big deficit
+            if self.options.futility
+                && i > 0
+                && !is_pv
+                && !in_check
+                && !gives_check
+                && quiet
+                && depth <= 2
+                && alpha.abs() < MATE_BOUND
+                && static_eval + FUTILITY_MARGIN * depth <= alpha
+            {
+                continue;
+            }
+
+            //principal variation search, the first move gets a full window and the rest only have to prove they are worse
+            let mut child_pv = Vec::new();
+            let score = if i == 0 {
+                -self.alpha_beta(&mut child, depth - 1, -beta, -alpha, ply + 1, Some(*m), &mut child_pv)
+            } else {
+                let reduction = if self.options.late_move_reductions
+                    && depth >= LMR_DEPTH
+                    && i >= LMR_MOVES
+                    && quiet
+                    && !in_check
+                    && !gives_check
+                {
+                    late_move_reduction(depth, i).min(depth - 2)
+                } else {
+                    0
+                };
+                let mut score = -self.alpha_beta(&mut child, depth - 1 - reduction, -alpha - 1, -alpha, ply + 1, Some(*m), &mut child_pv);
+                if score > alpha && reduction > 0 {
+                    score = -self.alpha_beta(&mut child, depth - 1, -alpha - 1, -alpha, ply + 1, Some(*m), &mut child_pv);
+                }
+                if score > alpha && score < beta {
+                    score = -self.alpha_beta(&mut child, depth - 1, -beta, -alpha, ply + 1, Some(*m), &mut child_pv);
+                }
+                score
+            };
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(*m);
+                if score > alpha {
+                    alpha = score;
+                    pv.clear();
+                    pv.push(*m);
+                    pv.append(&mut child_pv);
+                }
+            }
+            if alpha >= beta {
+                if self.options.move_ordering && quiet {
+                    self.tables.update_quiet(m, depth, ply, prev.as_ref(), &moves[..i]);
+                }
+                break;
+            }
+        }
+
+        let bound = if best_score >= beta {
+            tt::Bound::Lower
+        } else if best_score > original_alpha {
+            tt::Bound::Exact
+        } else {
+            tt::Bound::Upper
+        };
+        //a root with moves left out doesn't have its real score
+        if ply > 0 || self.excluded.is_empty() {
+            self.tt.store(tt::Entry {
+                key,
+                depth,
+                score: score_to_tt(best_score, ply),
+                bound,
+                best_move,
+            });
+        }
+        best_score
+    }
+
+    //only look at captures and promotions, so we dont stop searching in the middle of a trade
+    fn quiescence(&mut self, board: &mut board::Board, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.nodes += 1;
+        if self.check_time() {
+            return 0;
+        }
+        if let Some(result) = board.variant().game_over(board) {
+            return result_score(result, board.active_player(), ply);
+        }
+        let stand_pat = eval::evaluate(board);
+        //taking is compulsory in antichess, so standing pat only works when nothing can be taken
+        let forced = board.variant() == Variant::Antichess && {
+            let moves = validator::get_possible_moves(board);
+            if moves.is_empty() {
+                return MATE - ply as i32;
+            }
+            moves[0].is_capture()
+        };
+        if ply >= MAX_PLY - 1 || (!forced && stand_pat >= beta) {
+            return stand_pat;
+        }
+        if !forced {
+            alpha = alpha.max(stand_pat);
+        }
+
+        let mut captures: Vec::<pos::Move> = validator::get_possible_moves(board)
+            .into_iter()
+            .filter(|m| !ordering::is_quiet(m))
+            .collect();
+        //always sorted, without mvv lva the capture sequences blow up in busy positions
+        captures.sort_by_cached_key(|m| -ordering::mvv_lva(m));
+        for m in &captures {
+            let mut child = board.clone();
+            child.make_move(m);
+            let score = -self.quiescence(&mut child, -beta, -alpha, ply + 1);
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+}
+
+// the six perft positions from the chessprogramming wiki
+const BENCH_POSITIONS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+];
+
+//runs a fixed depth search over the bench positions, prints each one and returns the total node count
+fn bench_run(name: &str, options: SearchOptions, depth: i32) -> u64 {
+    let start = std::time::Instant::now();
+    let mut total = 0u64;
+    println!("{} (depth {})", name, depth);
+    for fen in BENCH_POSITIONS {
+        let mut searcher = Searcher::new(options);
+        let result = searcher.search(&board::Board::from_fen(fen), depth);
+        total += result.nodes;
+        let pv: Vec::<String> = result.pv.iter().map(|m| m.to_string()).collect();
+        println!("  {}", fen);
+        println!(
+            "  {:>10} nodes  depth {} score {:>6}  pv {}",
+            result.nodes,
+            result.depth,
+            result.score,
+            pv.join(" ")
+        );
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    println!("  {} nodes in {:.2}s ({:.0} nps)", total, elapsed, total as f64 / elapsed.max(1e-9));
+    total
+}
+
+//fixed depth search over a few positions, with and without move ordering, to see how many nodes it saves
+pub fn bench(depth: i32) {
+    let unordered = bench_run("unordered", SearchOptions {
+        move_ordering: false,
+        ..SearchOptions::no_pruning()
+    }, depth);
+    let ordered = bench_run("ordered", SearchOptions::no_pruning(), depth);
+    println!(
+        "move ordering searched {:.1}% of the unordered nodes",
+        ordered as f64 * 100.0 / unordered.max(1) as f64
+    );
+}
+
+//same as bench, but turning the pruning, reductions and extensions on one at a time
+pub fn bench_pruning(depth: i32) {
+    let base = SearchOptions::no_pruning();
+    let configs = [
+        ("no pruning", base),
+        ("null move", SearchOptions { null_move: true, ..base }),
+        ("late move reductions", SearchOptions { late_move_reductions: true, ..base }),
+        ("futility", SearchOptions { futility: true, ..base }),
+        ("reverse futility", SearchOptions { reverse_futility: true, ..base }),
+        ("aspiration windows", SearchOptions { aspiration_windows: true, ..base }),
+        ("check extensions", SearchOptions { check_extensions: true, ..base }),
+        ("everything", SearchOptions::default()),
+    ];
+    let totals: Vec::<(&str, u64)> = configs.iter()
+        .map(|&(name, options)| (name, bench_run(name, options, depth)))
+        .collect();
+    let baseline = totals[0].1.max(1);
+    for (name, total) in totals {
+        println!("{:>22}: {:>10} nodes ({:.1}%)", name, total, total as f64 * 100.0 / baseline as f64);
+    }
+}
+
+//time to depth over the bench positions, one thread against however many were asked for
+pub fn bench_smp(depth: i32, threads: usize) {
+    let start = std::time::Instant::now();
+    bench_run("1 thread", SearchOptions::default(), depth);
+    let single = start.elapsed().as_secs_f64();
+    let start = std::time::Instant::now();
+    bench_run(&format!("{} threads", threads), SearchOptions { threads, ..SearchOptions::default() }, depth);
+    let multi = start.elapsed().as_secs_f64();
+    println!("time to depth {} speedup with {} threads: {:.2}x", depth, threads, single / multi.max(1e-9));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::Game;
+
+    #[test]
+    fn repeating_a_position_is_a_draw() {
+        //a queen down, but the knight can go back to where it was two moves ago
+        let mut game = Game::from_fen("7k/8/8/8/8/8/q7/6NK w - - 0 1");
+        for m in ["g1f3", "h8h7", "f3g1", "h7h8"] {
+            game.play_move(m).unwrap();
+        }
+        let mut searcher = Searcher::new(SearchOptions::default());
+        assert!(searcher.search(&game.board, 1).score < -500);
+        searcher.set_history(game.history());
+        let result = searcher.search(&game.board, 1);
+        assert_eq!(result.score, 0);
+        assert_eq!(result.best_move.map(|m| m.to_string()), Some("g1f3".to_owned()));
+    }
+
+    #[test]
+    fn fifty_moves_are_a_draw_unless_the_last_one_mates() {
+        let mut searcher = Searcher::new(SearchOptions::default());
+        assert_eq!(searcher.search(&board::Board::from_fen("k7/8/8/8/8/8/8/1Q4K1 w - - 99 100"), 3).score, 0);
+        assert!(searcher.search(&board::Board::from_fen("k7/8/1K6/8/8/8/8/7Q w - - 99 100"), 2).score >= MATE_BOUND);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::chess::tournament::{adjudicate, elo};
+use crate::chess::variant::Variant;
+use crate::chess::{pgn, san, Game, GameResult, Player};
+
+use std::collections::{BTreeMap, HashMap};
+
+pub struct SprtSettings {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    // the engine being tested, white in the first game when not given
+    pub engine: Option::<String>,
+}
+
+//the score fraction an elo difference means
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+//mean and variance of scores that each came up this many times
+fn mean_variance(counts: &[(f64, u32)]) -> (f64, f64, u32) {
+    let n: u32 = counts.iter().map(|(_, c)| c).sum();
+    if n == 0 {
+        return (0.5, 0.0, 0);
+    }
+    let mean = counts.iter().map(|&(s, c)| s * c as f64).sum::<f64>() / n as f64;
+    let variance = counts.iter().map(|&(s, c)| (s - mean).powi(2) * c as f64).sum::<f64>() / n as f64;
+    (mean, variance, n)
+}
+
+//log likelihood ratio of elo1 against elo0, with the normal approximation fishtest and cutechess use.
+//the samples are single games for the trinomial model and game pairs for the pentanomial one
+fn llr(counts: &[(f64, u32)], elo0: f64, elo1: f64) -> f64 {
+    let (mean, variance, n) = mean_variance(counts);
+    if variance == 0.0 {
+        return 0.0;
+    }
+    let (s0, s1) = (expected_score(elo0), expected_score(elo1));
+    (s1 - s0) * (2.0 * mean - s0 - s1) * n as f64 / (2.0 * variance)
+}
+
+//the llr below which H0 is accepted and above which H1 is, from the false positive and false negative rates
+fn bounds(alpha: f64, beta: f64) -> (f64, f64) {
+    ((beta / (1.0 - alpha)).ln(), ((1.0 - beta) / alpha).ln())
+}
+
+//elo and the 95% error margin around it, none with no games or when one side scored every point
+fn elo_margin(counts: &[(f64, u32)]) -> Option::<(f64, f64)> {
+    let (mean, variance, n) = mean_variance(counts);
+    if n == 0 || mean == 0.0 || mean == 1.0 {
+        return None;
+    }
+    if variance == 0.0 {
+        return Some((elo(mean), 0.0));
+    }
+    let margin = 1.96 * (variance / n as f64).sqrt();
+    let low = elo(mean - margin);
+    let high = elo(mean + margin);
+    Some((elo(mean), (high - low) / 2.0))
+}
+
+//the score for white of a pgn game, replayed with our own rules so mates, stalemates, repetitions and
+//the fifty move rule are judged the same way for every game. the result tag is only used when the board
+//doesn't say, like for time forfeits. none for unfinished games
+fn replay(game: &pgn::PgnGame) -> Result::<(Option::<f64>, bool), String> {
+    let mut board_game = Game::from_fen(game.start_fen());
+    if let Some(variant) = game.tag("Variant").and_then(Variant::from_name) {
+        if variant != Variant::Standard {
+            board_game.set_variant(variant);
+        }
+    }
+    let mut seen = HashMap::<u64, u32>::new();
+    let mut ended = None;
+    for m in &game.moves {
+        adjudicate(&mut board_game, &mut seen);
+        if let Some(result) = board_game.result() {
+            ended = Some(result);
+            break;
+        }
+        let m = san::parse_san(&board_game.board, &m.san)?;
+        board_game.board.make_move(&m);
+    }
+    if ended.is_none() {
+        adjudicate(&mut board_game, &mut seen);
+        ended = board_game.result();
+    }
+    let tagged = match game.result.as_str() {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        _ => None,
+    };
+    let judged = ended.map(|result| match result {
+        GameResult::Win(Player::White, _) => 1.0,
+        GameResult::Win(Player::Black, _) => 0.0,
+        GameResult::Draw => 0.5,
+    });
+    let overruled = judged.is_some() && tagged.is_some() && judged != tagged;
+    Ok((judged.or(tagged), overruled))
+}
+
+//reads a match from a pgn file and says whether the engine is better by elo1 or not better than elo0 yet
+pub fn run(path: &str, settings: &SprtSettings) -> Result::<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path, e))?;
+    let games = pgn::parse(&text);
+    let engine = match &settings.engine {
+        Some(e) => e.clone(),
+        None => games.first().and_then(|g| g.tag("White")).ok_or("no games")?.to_owned(),
+    };
+    let mut singles = BTreeMap::<u32, u32>::new();
+    //pair number to the scores of its games, games are paired by round like the match command plays them
+    let mut pairs = BTreeMap::<usize, Vec::<f64>>::new();
+    let mut overruled = 0;
+    let mut skipped = 0;
+    for (i, game) in games.iter().enumerate() {
+        let plays_white = game.tag("White") == Some(engine.as_str());
+        if !plays_white && game.tag("Black") != Some(engine.as_str()) {
+            skipped += 1;
+            continue;
+        }
+        let (white_score, changed) = match replay(game) {
+            Ok(r) => r,
+            Err(msg) => {
+                println!("game {}: {}, skipped", i + 1, msg);
+                skipped += 1;
+                continue;
+            }
+        };
+        let score = match white_score {
+            Some(s) if plays_white => s,
+            Some(s) => 1.0 - s,
+            None => {
+                skipped += 1;
+                continue;
+            }
+        };
+        if changed {
+            overruled += 1;
+        }
+        *singles.entry((score * 2.0) as u32).or_insert(0) += 1;
+        let round = game.tag("Round").and_then(|r| r.parse::<usize>().ok()).map_or(i, |r| r.saturating_sub(1));
+        pairs.entry(round / 2).or_default().push(score);
+    }
+
+    let trinomial: Vec::<(f64, u32)> = singles.iter().map(|(&half_points, &n)| (half_points as f64 / 2.0, n)).collect();
+    //0, 0.5, 1, 1.5 or 2 points from a pair, scored per game
+    let mut pentanomial_counts = [0u32; 5];
+    for scores in pairs.values().filter(|s| s.len() == 2) {
+        pentanomial_counts[((scores[0] + scores[1]) * 2.0) as usize] += 1;
+    }
+    let pentanomial: Vec::<(f64, u32)> =
+        pentanomial_counts.iter().enumerate().map(|(i, &n)| (i as f64 / 4.0, n)).collect();
+    let count = |half_points: u32| singles.get(&half_points).copied().unwrap_or(0);
+
+    let played: u32 = singles.values().sum();
+    println!(
+        "{}: {} games, wins {} draws {} losses {}",
+        engine,
+        played,
+        count(2),
+        count(1),
+        count(0)
+    );
+    println!("pairs {:?}", pentanomial_counts);
+    if skipped > 0 {
+        println!("{} games skipped, unfinished, unreadable or without {}", skipped, engine);
+    }
+    if overruled > 0 {
+        println!("{} results replaced by what the board says", overruled);
+    }
+    let (lower, upper) = bounds(settings.alpha, settings.beta);
+    println!(
+        "elo0 {} elo1 {} alpha {} beta {}, bounds {:.2} {:.2}",
+        settings.elo0, settings.elo1, settings.alpha, settings.beta, lower, upper
+    );
+    for (model, counts) in [("trinomial", &trinomial), ("pentanomial", &pentanomial)] {
+        let diff = match elo_margin(counts) {
+            Some((diff, margin)) => format!("{:+.1} +/- {:.1}", diff, margin),
+            None => "undefined".to_owned(),
+        };
+        let ratio = llr(counts, settings.elo0, settings.elo1);
+        let verdict = if ratio >= upper {
+            "H1 accepted"
+        } else if ratio <= lower {
+            "H0 accepted"
+        } else {
+            "continue"
+        };
+        println!("{:<12} elo {}, llr {:.2}, {}", model, diff, ratio, verdict);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(value: f64, expected: f64) {
+        assert!((value - expected).abs() < 1e-3, "{} instead of {}", value, expected);
+    }
+
+    //the reference numbers are the old fishtest LLR_alt, (s1 - s0) * (2 * mean - s0 - s1) / (2 * variance) per sample
+    #[test]
+    fn llr_matches_the_normal_approximation() {
+        let trinomial = [(0.0, 800), (0.5, 2000), (1.0, 1000)];
+        assert_near(llr(&trinomial, 0.0, 5.0), 5.276093);
+        let trinomial = [(0.0, 1000), (0.5, 2000), (1.0, 800)];
+        assert_near(llr(&trinomial, 0.0, 5.0), -6.947081);
+        let pentanomial = [(0.0, 100), (0.25, 800), (0.5, 2000), (0.75, 900), (1.0, 200)];
+        assert_near(llr(&pentanomial, 0.0, 5.0), 9.699369);
+        //nothing to go on yet
+        assert_eq!(llr(&[(0.5, 10)], 0.0, 5.0), 0.0);
+        assert_eq!(llr(&[], 0.0, 5.0), 0.0);
+    }
+
+    #[test]
+    fn bounds_match_fishtest() {
+        let (lower, upper) = bounds(0.05, 0.05);
+        assert_near(lower, -2.944439);
+        assert_near(upper, 2.944439);
+        let (lower, upper) = bounds(0.05, 0.1);
+        assert_near(lower, -2.251292);
+        assert_near(upper, 2.890372);
+    }
+}
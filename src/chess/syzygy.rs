@@ -0,0 +1,1237 @@
+use crate::chess::{board, piece, pos, validator};
+
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+//syzygy endgame tablebases, read from the .rtbw (win/draw/loss) and .rtbz (distance to zeroing) files.
+//the file layout and the position indexing follow the probing code that comes with the generator.
+//only the start of a file, up to the compressed data, is kept in memory. the data is read a block at a time,
+//so even 6 and 7 piece tables that are gigabytes each dont have to fit
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+const MAX_PIECES: usize = 7;
+
+// per table flags
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum Wdl {
+    Loss = -2,
+    //lost, but the fifty move rule saves it
+    BlessedLoss = -1,
+    Draw = 0,
+    //won, but not before the fifty move rule runs out
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(v: i32) -> Self {
+        match v {
+            -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            1 => Wdl::CursedWin,
+            2 => Wdl::Win,
+            _ => Wdl::Draw,
+        }
+    }
+
+    fn value(self) -> i32 {
+        self as i32
+    }
+
+    fn invert(self) -> Self {
+        Self::from_value(-self.value())
+    }
+}
+
+//distance to zeroing, in plies, of the move that got to a position where the capture or pawn move is made
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+fn file_of(s: usize) -> usize {
+    s & 7
+}
+
+fn rank_of(s: usize) -> usize {
+    s >> 3
+}
+
+//how far above the a1-h8 diagonal a square is, negative below it
+fn off_diagonal(s: usize) -> i32 {
+    rank_of(s) as i32 - file_of(s) as i32
+}
+
+//lookup tables for turning piece squares into a table index
+struct Encoding {
+    // squares below the a1-h8 diagonal, 0..28
+    map_b1h1h7: [u64; 64],
+    // squares in the a1-d1-d4 triangle, 0..10, the diagonal ones last
+    map_a1d1d4: [u64; 64],
+    // the 462 ways to put two kings down with the first in the triangle
+    map_kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; MAX_PIECES],
+    // a2-h7 to 0..48, highest for the pawn closest to the edge and lowest on the board
+    map_pawns: [u64; 64],
+    lead_pawn_idx: [[u64; 64]; MAX_PIECES],
+    lead_pawns_size: [[u64; 4]; MAX_PIECES],
+}
+
+fn encoding() -> &'static Encoding {
+    static ENCODING: OnceLock::<Encoding> = OnceLock::new();
+    ENCODING.get_or_init(|| {
+        let mut e = Encoding {
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; MAX_PIECES],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; MAX_PIECES],
+            lead_pawns_size: [[0; 4]; MAX_PIECES],
+        };
+        let mut code = 0;
+        for s in 0..64 {
+            if off_diagonal(s) < 0 {
+                e.map_b1h1h7[s] = code;
+                code += 1;
+            }
+        }
+
+        let mut diagonal = Vec::<usize>::new();
+        code = 0;
+        for s in 0..=27 {
+            if off_diagonal(s) < 0 && file_of(s) <= 3 {
+                e.map_a1d1d4[s] = code;
+                code += 1;
+            } else if off_diagonal(s) == 0 && file_of(s) <= 3 {
+                diagonal.push(s);
+            }
+        }
+        for s in diagonal {
+            e.map_a1d1d4[s] = code;
+            code += 1;
+        }
+
+        //when the first king is on the diagonal, the second one is kept on or below it
+        let mut both_on_diagonal = Vec::<(usize, usize)>::new();
+        code = 0;
+        for idx in 0..10 {
+            for s1 in 0..=27 {
+                // b1 is 0, and so is every square outside the triangle
+                if e.map_a1d1d4[s1] != idx as u64 || (idx == 0 && s1 != 1) {
+                    continue;
+                }
+                for s2 in 0..64 {
+                    let touching = (rank_of(s1) as i32 - rank_of(s2) as i32).abs() <= 1
+                        && (file_of(s1) as i32 - file_of(s2) as i32).abs() <= 1;
+                    if touching || (off_diagonal(s1) == 0 && off_diagonal(s2) > 0) {
+                        continue;
+                    }
+                    if off_diagonal(s1) == 0 && off_diagonal(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        e.map_kk[idx][s2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            e.map_kk[idx][s2] = code;
+            code += 1;
+        }
+
+        e.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                e.binomial[k][n] = if k > 0 { e.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { e.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // squares not handed out yet, counting down to 0 at the last one
+        let mut available = 48;
+        for lead_pawns in 1..MAX_PIECES - 1 {
+            for f in 0..4 {
+                let mut idx = 0;
+                for r in 1..7 {
+                    let s = r * 8 + f;
+                    if lead_pawns == 1 {
+                        e.map_pawns[s] = available - 1;
+                        e.map_pawns[s ^ 7] = available - 2;
+                        available -= 2;
+                    }
+                    e.lead_pawn_idx[lead_pawns][s] = idx;
+                    idx += e.binomial[lead_pawns - 1][e.map_pawns[s] as usize];
+                }
+                e.lead_pawns_size[lead_pawns][f] = idx;
+            }
+        }
+        e
+    })
+}
+
+fn u16_le(data: &[u8], at: usize) -> u64 {
+    u16::from_le_bytes([data[at], data[at + 1]]) as u64
+}
+
+fn u32_le(data: &[u8], at: usize) -> u64 {
+    u32::from_le_bytes(data[at..at + 4].try_into().expect("table too short")) as u64
+}
+
+fn u32_be(data: &[u8], at: usize) -> u64 {
+    u32::from_be_bytes(data[at..at + 4].try_into().expect("table too short")) as u64
+}
+
+fn u64_be(data: &[u8], at: usize) -> u64 {
+    u64::from_be_bytes(data[at..at + 8].try_into().expect("table too short"))
+}
+
+//the start of a table file, read in as far as the setup gets while it is parsed
+struct Head {
+    file: std::fs::File,
+    bytes: Vec::<u8>,
+}
+
+impl Head {
+    //the bytes read so far, making sure there are at least end of them
+    fn need(&mut self, end: usize) -> Result::<&[u8], String> {
+        if self.bytes.len() < end {
+            let more = (end - self.bytes.len()).max(1 << 16) as u64;
+            (&mut self.file).take(more).read_to_end(&mut self.bytes).map_err(|e| e.to_string())?;
+            if self.bytes.len() < end {
+                return Err("table too short".to_owned());
+            }
+        }
+        Ok(&self.bytes)
+    }
+}
+
+//one compressed sub table, there is one per side to move and per leading pawn file
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    // piece codes in the order they are indexed, 1..6 white pawn to king and 9..14 for black
+    pieces: [u8; MAX_PIECES],
+    group_len: [usize; MAX_PIECES + 1],
+    group_idx: [u64; MAX_PIECES + 1],
+    block_size: u64,
+    span: u64,
+    num_blocks: u64,
+    sparse_index_size: u64,
+    block_length_size: u64,
+    min_sym_len: u8,
+    // offsets into the file
+    lowest_sym: usize,
+    btree: usize,
+    sparse_index: usize,
+    block_length: usize,
+    data: usize,
+    base64: Vec::<u64>,
+    symlen: Vec::<u8>,
+    // where each wdl result starts in the dtz value map
+    map_idx: [u64; 4],
+}
+
+impl PairsData {
+    //left and right child of a symbol, packed into 3 bytes
+    fn children(&self, file: &[u8], sym: usize) -> (usize, usize) {
+        let at = self.btree + sym * 3;
+        let (b0, b1, b2) = (file[at] as usize, file[at + 1] as usize, file[at + 2] as usize);
+        (((b1 & 0xF) << 8) | b0, (b2 << 4) | (b1 >> 4))
+    }
+
+    fn set_groups(&mut self, table: &Table, order: [usize; 2], f: usize) {
+        let e = encoding();
+        let mut n = 0;
+        let mut first_len: i32 = if table.has_pawns { 0 } else if table.has_unique_pieces { 3 } else { 2 };
+        self.group_len[0] = 1;
+        for i in 1..table.piece_count {
+            first_len -= 1;
+            if first_len > 0 || self.pieces[i] == self.pieces[i - 1] {
+                self.group_len[n] += 1;
+            } else {
+                n += 1;
+                self.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        self.group_len[n] = 0;
+
+        //groups are combined as g1 * N(g2) * N(g3) + g2 * N(g3) + g3, in an order each table picks
+        let both_pawns = table.has_pawns && table.pawn_count[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - self.group_len[0] - if both_pawns { self.group_len[1] } else { 0 };
+        let mut idx = 1u64;
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                self.group_idx[0] = idx;
+                idx *= if table.has_pawns {
+                    e.lead_pawns_size[self.group_len[0]][f]
+                } else if table.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                self.group_idx[1] = idx;
+                idx *= e.binomial[self.group_len[1]][48 - self.group_len[0]];
+            } else {
+                self.group_idx[next] = idx;
+                idx *= e.binomial[self.group_len[next]][free_squares];
+                free_squares -= self.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        self.group_idx[n] = idx;
+    }
+
+    //reads the huffman and pairing setup, returns where the next part of the file starts
+    fn set_sizes(&mut self, head: &mut Head, mut at: usize) -> Result::<usize, String> {
+        let file = head.need(at + 2)?;
+        self.flags = file[at];
+        at += 1;
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            //the one value every position has
+            self.min_sym_len = file[at];
+            return Ok(at + 1);
+        }
+        let file = head.need(at + 9)?;
+        let groups = self.group_len.iter().position(|&l| l == 0).unwrap_or(MAX_PIECES);
+        let table_size = self.group_idx[groups];
+        self.block_size = 1 << file[at];
+        self.span = 1 << file[at + 1];
+        self.sparse_index_size = table_size.div_ceil(self.span);
+        let padding = file[at + 2] as u64;
+        self.num_blocks = u32_le(file, at + 3);
+        self.block_length_size = self.num_blocks + padding;
+        let max_sym_len = file[at + 7];
+        self.min_sym_len = file[at + 8];
+        at += 9;
+        self.lowest_sym = at;
+
+        //canonical huffman, longer codes have lower values. base64[i] is the lowest code of length min + i, left aligned
+        let lengths = (max_sym_len as usize + 1).saturating_sub(self.min_sym_len as usize);
+        let file = head.need(at + lengths * 2 + 2)?;
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths.saturating_sub(1)).rev() {
+            self.base64[i] = (self.base64[i + 1]
+                .wrapping_add(u16_le(file, self.lowest_sym + 2 * i))
+                .wrapping_sub(u16_le(file, self.lowest_sym + 2 * (i + 1))))
+                / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base = base.checked_shl(64 - i as u32 - self.min_sym_len as u32).unwrap_or(0);
+        }
+        at += lengths * 2;
+
+        let symbols = u16_le(file, at) as usize;
+        at += 2;
+        self.btree = at;
+        let file = head.need(at + symbols * 3 + 1)?;
+        //how many values each symbol stands for, minus one. pairs expand into their two children
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            let mut stack = vec![(sym, false)];
+            while let Some((s, children_done)) = stack.pop() {
+                let (left, right) = self.children(file, s);
+                if right == 0xFFF {
+                    visited[s] = true;
+                    continue;
+                }
+                if children_done {
+                    self.symlen[s] = self.symlen[left].wrapping_add(self.symlen[right]).wrapping_add(1);
+                    visited[s] = true;
+                } else if !visited[s] {
+                    stack.push((s, true));
+                    for child in [left, right] {
+                        if !visited[child] {
+                            stack.push((child, false));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(at + symbols * 3 + (symbols & 1))
+    }
+
+    //the compressed block, with a few bytes of padding the decoder can read ahead into
+    fn read_block(&self, data: &Mutex::<std::fs::File>, block: u64) -> Option::<Vec::<u8>> {
+        let len = self.block_size + 8;
+        let mut buf = Vec::<u8>::with_capacity(len as usize);
+        let mut file = data.lock().unwrap();
+        file.seek(SeekFrom::Start(self.data as u64 + block * self.block_size)).ok()?;
+        (&mut *file).take(len).read_to_end(&mut buf).ok()?;
+        buf.resize(len as usize, 0);
+        Some(buf)
+    }
+
+    //the value stored for one index. file is the table's head, the data block comes off the disk
+    fn decompress(&self, file: &[u8], data: &Mutex::<std::fs::File>, idx: u64) -> Option::<i32> {
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(self.min_sym_len as i32);
+        }
+        //the sparse index points somewhere near the block, the block lengths get the rest of the way
+        let k = (idx / self.span) as usize;
+        let entry = self.sparse_index + k * 6;
+        let mut block = u32_le(file, entry) as i64;
+        let mut offset = u16_le(file, entry + 4) as i64;
+        offset += (idx % self.span) as i64 - (self.span / 2) as i64;
+        let block_length = |b: i64| u16_le(file, self.block_length + 2 * b as usize) as i64;
+        while offset < 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        if block < 0 || block as u64 >= self.num_blocks {
+            return None;
+        }
+        let bytes = self.read_block(data, block as u64)?;
+        let mut buf = u64_be(&bytes, 0);
+        let mut at = 8;
+        let mut buf_size = 64;
+        let min = self.min_sym_len as usize;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buf < self.base64[len] {
+                len += 1;
+                if len >= self.base64.len() {
+                    return None;
+                }
+            }
+            sym = ((buf - self.base64[len]) >> (64 - len - min)) as usize;
+            sym += u16_le(file, self.lowest_sym + 2 * len) as usize;
+            let count = *self.symlen.get(sym)? as i64 + 1;
+            if offset < count {
+                break;
+            }
+            offset -= count;
+            buf <<= len + min;
+            buf_size -= len + min;
+            if buf_size <= 32 {
+                if at + 4 > bytes.len() {
+                    return None;
+                }
+                buf_size += 32;
+                buf |= u32_be(&bytes, at) << (64 - buf_size);
+                at += 4;
+            }
+        }
+        //walk down the pairs to the single value the offset lands on
+        while self.symlen[sym] != 0 {
+            let (left, right) = self.children(file, sym);
+            if offset < self.symlen[left] as i64 + 1 {
+                sym = left;
+            } else {
+                offset -= self.symlen[left] as i64 + 1;
+                sym = right;
+            }
+        }
+        Some(self.children(file, sym).0 as i32)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Wdl,
+    Dtz,
+}
+
+struct Table {
+    // everything up to the compressed data, and the file to read that from
+    head: Vec::<u8>,
+    data: Mutex::<std::fs::File>,
+    kind: Kind,
+    // both sides have the same pieces, like KRvKR
+    symmetric: bool,
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    // leading side's pawns, then the other side's
+    pawn_count: [usize; 2],
+    // [side to move][leading pawn file]
+    items: Vec::<Vec::<PairsData>>,
+    // dtz value map
+    map: usize,
+}
+
+//piece letters for one side as the file names have them, strongest first
+fn side_signature(board: &board::Board, player: piece::Player) -> String {
+    let mut pieces: Vec::<piece::Piece> = board.get_pieces(player).iter().map(|p| p.piece_type).collect();
+    pieces.sort_by_key(|p| 5 - p.index() % 6);
+    pieces.iter().map(|p| p.to_char_ascii().to_ascii_uppercase()).collect()
+}
+
+fn piece_code(p: piece::Piece) -> u8 {
+    let kind = (p.index() % 6) as u8 + 1;
+    match p.owner() {
+        Some(piece::Player::White) => kind,
+        _ => kind + 8,
+    }
+}
+
+impl Table {
+    //pieces per side from a name like KRPvKR, the first side is called white
+    fn open(name: &str, path: &Path, kind: Kind) -> Result::<Self, String> {
+        let magic = match kind {
+            Kind::Wdl => WDL_MAGIC,
+            Kind::Dtz => DTZ_MAGIC,
+        };
+        let file = std::fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut head = Head { file: file.try_clone().map_err(|e| e.to_string())?, bytes: vec![] };
+        if head.need(6).map(|h| h[0..4] != magic).unwrap_or(true) {
+            return Err(format!("{} is not a syzygy table", name));
+        }
+        let (white, black) = name.split_once('v').ok_or_else(|| format!("bad table name {}", name))?;
+        let count = |side: &str, c: char| side.chars().filter(|&x| x == c).count();
+        let pawns = [count(white, 'P'), count(black, 'P')];
+        let has_unique_pieces = [white, black]
+            .iter()
+            .any(|side| "QRBNP".chars().any(|c| count(side, c) == 1));
+        //the side with fewer pawns leads, it compresses better
+        let white_leads = pawns[1] == 0 || (pawns[0] > 0 && pawns[1] >= pawns[0]);
+        let mut table = Self {
+            head: vec![],
+            data: Mutex::new(file),
+            kind,
+            symmetric: white == black,
+            piece_count: white.len() + black.len(),
+            has_pawns: pawns[0] + pawns[1] > 0,
+            has_unique_pieces,
+            pawn_count: if white_leads { pawns } else { [pawns[1], pawns[0]] },
+            items: vec![],
+            map: 0,
+        };
+        if table.piece_count > MAX_PIECES {
+            return Err(format!("{} has too many pieces", name));
+        }
+        table.init(&mut head).map_err(|e| format!("{}: {}", name, e))?;
+        Ok(table)
+    }
+
+    fn init(&mut self, head: &mut Head) -> Result::<(), String> {
+        let mut at = 4;
+        let files = if self.has_pawns { 4 } else { 1 };
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let file = head.need(at + 1 + files * (2 + self.piece_count) + 1)?;
+        let split = file[at] & 1 != 0;
+        at += 1;
+        let sides = if self.kind == Kind::Wdl && split { 2 } else { 1 };
+        let mut items = vec![vec![PairsData::default(); files]; sides];
+
+        for f in 0..files {
+            let order = [
+                [(file[at] & 0xF) as usize, if both_pawns { (file[at + 1] & 0xF) as usize } else { 0xF }],
+                [(file[at] >> 4) as usize, if both_pawns { (file[at + 1] >> 4) as usize } else { 0xF }],
+            ];
+            at += 1 + both_pawns as usize;
+            for k in 0..self.piece_count {
+                for (i, side) in items.iter_mut().enumerate() {
+                    side[f].pieces[k] = if i == 1 { file[at] >> 4 } else { file[at] & 0xF };
+                }
+                at += 1;
+            }
+            for (i, side) in items.iter_mut().enumerate() {
+                side[f].set_groups(self, order[i], f);
+            }
+        }
+        at += at & 1;
+
+        for f in 0..files {
+            for side in items.iter_mut() {
+                at = side[f].set_sizes(head, at)?;
+            }
+        }
+
+        if self.kind == Kind::Dtz {
+            self.map = at;
+            for d in items[0].iter_mut() {
+                if d.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & FLAG_WIDE != 0 {
+                    at += at & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = ((at - self.map) / 2 + 1) as u64;
+                        at += 2 * u16_le(head.need(at + 2)?, at) as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = (at - self.map + 1) as u64;
+                        at += head.need(at + 1)?[at] as usize + 1;
+                    }
+                }
+            }
+            at += at & 1;
+        }
+
+        for f in 0..files {
+            for side in items.iter_mut() {
+                side[f].sparse_index = at;
+                at += side[f].sparse_index_size as usize * 6;
+            }
+        }
+        for f in 0..files {
+            for side in items.iter_mut() {
+                side[f].block_length = at;
+                at += side[f].block_length_size as usize * 2;
+            }
+        }
+        //the indexes are kept, the data after them isnt
+        head.need(at)?;
+        head.bytes.truncate(at);
+        for f in 0..files {
+            for side in items.iter_mut() {
+                at = (at + 0x3F) & !0x3F;
+                side[f].data = at;
+                at += (side[f].num_blocks * side[f].block_size) as usize;
+            }
+        }
+        self.head = std::mem::take(&mut head.bytes);
+        self.items = items;
+        Ok(())
+    }
+
+    fn get(&self, stm: usize, f: usize) -> &PairsData {
+        &self.items[stm % self.items.len()][if self.has_pawns { f } else { 0 }]
+    }
+
+    //the raw value for a position. flipped is set when black has this table's white pieces.
+    //none means the table is for the other side to move, only dtz tables do that
+    fn probe(&self, board: &board::Board, flipped: bool, wdl: Wdl) -> Option::<Option::<i32>> {
+        let e = encoding();
+        let black_to_move = board.active_player() == piece::Player::Black;
+        let flip = flipped || (self.symmetric && black_to_move);
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = (flip ^ black_to_move) as usize;
+
+        let mut all: Vec::<(usize, u8)> = [piece::Player::White, piece::Player::Black]
+            .iter()
+            .flat_map(|&p| board.get_pieces(p).iter())
+            .map(|p| (p.pos.index(), piece_code(p.piece_type)))
+            .collect();
+        all.sort_by_key(|(s, _)| *s);
+
+        let mut squares = Vec::<usize>::with_capacity(self.piece_count);
+        let mut pieces = Vec::<u8>::with_capacity(self.piece_count);
+        let mut lead_pawns = 0;
+        let mut tb_file = 0;
+        let pawns_cmp = |a: &usize, b: &usize| e.map_pawns[*a].cmp(&e.map_pawns[*b]);
+        if self.has_pawns {
+            let lead = self.get(0, 0).pieces[0] ^ flip_color;
+            for &(s, code) in &all {
+                if code == lead {
+                    squares.push(s ^ flip_squares);
+                    pieces.push(lead ^ flip_color);
+                }
+            }
+            lead_pawns = squares.len();
+            let max = (0..lead_pawns).max_by(|&a, &b| pawns_cmp(&squares[a], &squares[b]))?;
+            squares.swap(0, max);
+            let f = file_of(squares[0]);
+            tb_file = f.min(7 - f);
+        }
+
+        let d = self.get(stm, tb_file);
+        if self.kind == Kind::Dtz
+            && (d.flags & FLAG_STM) as usize != stm
+            && (self.has_pawns || !self.symmetric)
+        {
+            return Some(None);
+        }
+
+        let lead_code = if self.has_pawns { self.get(0, 0).pieces[0] ^ flip_color } else { 0 };
+        for &(s, code) in &all {
+            if self.has_pawns && code == lead_code {
+                continue;
+            }
+            squares.push(s ^ flip_squares);
+            pieces.push(code ^ flip_color);
+        }
+        if squares.len() != self.piece_count {
+            return None;
+        }
+
+        //put the pieces in the order the table has them
+        for i in lead_pawns..squares.len().saturating_sub(1) {
+            for j in i + 1..squares.len() {
+                if d.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        //mirror so the leading piece is on files a-d
+        if file_of(squares[0]) > 3 {
+            squares.iter_mut().for_each(|s| *s ^= 7);
+        }
+
+        let mut idx;
+        if self.has_pawns {
+            idx = e.lead_pawn_idx[lead_pawns][squares[0]];
+            squares[1..lead_pawns].sort_by(pawns_cmp);
+            for (i, s) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+                idx += e.binomial[i][e.map_pawns[*s] as usize];
+            }
+        } else {
+            //without pawns the leading piece also goes below rank 5 and below the diagonal
+            if rank_of(squares[0]) > 3 {
+                squares.iter_mut().for_each(|s| *s ^= 56);
+            }
+            for i in 0..d.group_len[0] {
+                if off_diagonal(squares[i]) == 0 {
+                    continue;
+                }
+                if off_diagonal(squares[i]) > 0 {
+                    squares[i..].iter_mut().for_each(|s| *s = ((*s >> 3) | (*s << 3)) & 63);
+                }
+                break;
+            }
+
+            if self.has_unique_pieces {
+                let (s0, s1, s2) = (squares[0], squares[1], squares[2]);
+                let adjust1 = (s1 > s0) as usize;
+                let adjust2 = (s2 > s0) as usize + (s2 > s1) as usize;
+                idx = if off_diagonal(s0) != 0 {
+                    (e.map_a1d1d4[s0] * 63 + (s1 - adjust1) as u64) * 62 + (s2 - adjust2) as u64
+                } else if off_diagonal(s1) != 0 {
+                    (6 * 63 + rank_of(s0) as u64 * 28 + e.map_b1h1h7[s1]) * 62 + (s2 - adjust2) as u64
+                } else if off_diagonal(s2) != 0 {
+                    6 * 63 * 62 + 4 * 28 * 62
+                        + rank_of(s0) as u64 * 7 * 28
+                        + (rank_of(s1) - adjust1) as u64 * 28
+                        + e.map_b1h1h7[s2]
+                } else {
+                    6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28
+                        + rank_of(s0) as u64 * 7 * 6
+                        + (rank_of(s1) - adjust1) as u64 * 6
+                        + (rank_of(s2) - adjust2) as u64
+                };
+            } else {
+                idx = e.map_kk[e.map_a1d1d4[squares[0]] as usize][squares[1]];
+            }
+        }
+
+        //the rest of the groups, each as a combination of squares not already taken
+        idx *= d.group_idx[0];
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let end = start + d.group_len[next];
+            squares[start..end].sort();
+            let mut n = 0;
+            for i in 0..d.group_len[next] {
+                let s = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&o| s > o).count();
+                n += e.binomial[i + 1][s - adjust - if remaining_pawns { 8 } else { 0 }];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            start = end;
+            next += 1;
+        }
+
+        let value = d.decompress(&self.head, &self.data, idx)?;
+        Some(Some(match self.kind {
+            Kind::Wdl => value - 2,
+            Kind::Dtz => self.map_dtz(tb_file, value, wdl),
+        }))
+    }
+
+    //dtz values can be stored through a map, and in moves instead of plies
+    fn map_dtz(&self, f: usize, mut value: i32, wdl: Wdl) -> i32 {
+        let d = self.get(0, f);
+        let map_index = [1, 3, 0, 2, 0][(wdl.value() + 2) as usize];
+        if d.flags & FLAG_MAPPED != 0 {
+            let at = d.map_idx[map_index] as usize + value as usize;
+            value = if d.flags & FLAG_WIDE != 0 {
+                u16_le(&self.head, self.map + 2 * at) as i32
+            } else {
+                self.head[self.map + at] as i32
+            };
+        }
+        if (wdl == Wdl::Win && d.flags & FLAG_WIN_PLIES == 0)
+            || (wdl == Wdl::Loss && d.flags & FLAG_LOSS_PLIES == 0)
+            || wdl == Wdl::CursedWin
+            || wdl == Wdl::BlessedLoss
+        {
+            value *= 2;
+        }
+        value + 1
+    }
+}
+
+fn is_capture(m: &pos::Move) -> bool {
+    matches!(
+        m.move_type,
+        pos::MoveType::Capture(_) | pos::MoveType::CapturePromotion(..) | pos::MoveType::EnPassant
+    )
+}
+
+fn is_zeroing(m: &pos::Move) -> bool {
+    is_capture(m) || matches!(m.piece, piece::Piece::Pawn(_))
+}
+
+// tables already read, by name and whether it is the dtz one. none if the file was missing or broken
+type LoadedTables = HashMap::<(String, bool), Option::<Arc::<Table>>>;
+
+//the tables in one or more directories. files are only read the first time a position needs them
+pub struct Tablebases {
+    // table name like KRvK, to the wdl and dtz file
+    paths: HashMap::<String, [Option::<PathBuf>; 2]>,
+    loaded: Mutex::<LoadedTables>,
+    pub max_pieces: usize,
+}
+
+impl Tablebases {
+    //directories are separated like in PATH
+    pub fn open(dirs: &str) -> Result::<Self, String> {
+        let mut paths = HashMap::<String, [Option::<PathBuf>; 2]>::new();
+        for dir in dirs.split([':', ';']).filter(|d| !d.is_empty()) {
+            let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir, e))?;
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let kind = match path.extension().and_then(|e| e.to_str()) {
+                    Some("rtbw") => 0,
+                    Some("rtbz") => 1,
+                    _ => continue,
+                };
+                if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                    if name.contains('v') && name.chars().all(|c| "KQRBNPv".contains(c)) {
+                        paths.entry(name.to_owned()).or_default()[kind] = Some(path.clone());
+                    }
+                }
+            }
+        }
+        let max_pieces = paths.keys().map(|n| n.len() - 1).max().unwrap_or(0).min(MAX_PIECES);
+        Ok(Self {
+            paths,
+            loaded: Mutex::new(HashMap::new()),
+            max_pieces,
+        })
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.paths.len()
+    }
+
+    //positions the tables can answer, castling isnt in them
+    pub fn can_probe(&self, board: &board::Board) -> bool {
+        let pieces = board.get_pieces(piece::Player::White).len() + board.get_pieces(piece::Player::Black).len();
+        pieces <= self.max_pieces && !board.has_any_castle_rights() && board.variant().uses_tablebases()
+    }
+
+    fn table(&self, name: &str, kind: Kind) -> Option::<Arc::<Table>> {
+        let dtz = kind == Kind::Dtz;
+        let mut loaded = self.loaded.lock().unwrap();
+        loaded
+            .entry((name.to_owned(), dtz))
+            .or_insert_with(|| {
+                let path = self.paths.get(name)?[dtz as usize].as_ref()?;
+                Table::open(name, path, kind).ok().map(Arc::new)
+            })
+            .clone()
+    }
+
+    //raw table lookup, without looking at captures first
+    fn probe_table(&self, board: &board::Board, kind: Kind, wdl: Wdl) -> Option::<Option::<i32>> {
+        let white = side_signature(board, piece::Player::White);
+        let black = side_signature(board, piece::Player::Black);
+        if white == "K" && black == "K" {
+            return Some(Some(0));
+        }
+        let own = format!("{}v{}", white, black);
+        let (name, flipped) = if self.paths.contains_key(&own) {
+            (own, false)
+        } else {
+            (format!("{}v{}", black, white), true)
+        };
+        self.table(&name, kind)?.probe(board, flipped, wdl)
+    }
+
+    //tables hold the result as if there were no captures, so those get searched first.
+    //the flag is set when the best move is a capture, or a pawn move if zeroing is asked for, which dtz probes need to know
+    fn search(&self, board: &board::Board, zeroing: bool) -> Option::<(Wdl, bool)> {
+        let moves = validator::get_possible_moves(&mut board.clone());
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for m in moves.iter().filter(|m| is_capture(m) || (zeroing && is_zeroing(m))) {
+            searched += 1;
+            let mut child = board.clone();
+            child.make_move(m);
+            let value = self.search(&child, false)?.0.invert();
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+        //when every move was searched the stored value isnt needed, and may be wrong (en passant, stalemate)
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            Wdl::from_value(self.probe_table(board, Kind::Wdl, Wdl::Draw)??)
+        };
+        if best >= value {
+            Some((best, best > Wdl::Draw || no_more_moves))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    pub fn probe_wdl(&self, board: &board::Board) -> Option::<Wdl> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        self.search(board, false).map(|(wdl, _)| wdl)
+    }
+
+    //plies to the next capture or pawn move on the best line, negative when losing and 0 for draws
+    pub fn probe_dtz(&self, board: &board::Board) -> Option::<i32> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        let (wdl, zeroing_best) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        if let Some(dtz) = self.probe_table(board, Kind::Dtz, wdl)? {
+            let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+            return Some((dtz + if cursed { 100 } else { 0 }) * wdl.value().signum());
+        }
+        //the table is for the other side to move, so go one ply deeper
+        let mut min_dtz = i32::MAX;
+        for m in validator::get_possible_moves(&mut board.clone()) {
+            let mut child = board.clone();
+            child.make_move(&m);
+            let zeroing = is_zeroing(&m);
+            let mut dtz = if zeroing {
+                -dtz_before_zeroing(self.search(&child, false)?.0)
+            } else {
+                -self.probe_dtz(&child)?
+            };
+            if dtz == 1 && child.is_in_check(child.active_player()) && validator::get_possible_moves(&mut child).is_empty() {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == wdl.value().signum() {
+                min_dtz = dtz;
+            }
+        }
+        Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+
+    //dtz of every legal move, counted from before the move
+    pub fn root_dtz(&self, board: &board::Board) -> Option::<Vec::<(pos::Move, i32)>> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        let mut res = Vec::<(pos::Move, i32)>::new();
+        for m in validator::get_possible_moves(&mut board.clone()) {
+            let mut child = board.clone();
+            child.make_move(&m);
+            let mut dtz = if child.halfmove_clock() == 0 {
+                dtz_before_zeroing(self.probe_wdl(&child)?.invert())
+            } else {
+                let dtz = -self.probe_dtz(&child)?;
+                dtz + dtz.signum()
+            };
+            //a mating move is as close as it gets
+            if dtz == 2 && child.is_in_check(child.active_player()) && validator::get_possible_moves(&mut child).is_empty() {
+                dtz = 1;
+            }
+            res.push((m, dtz));
+        }
+        Some(res)
+    }
+
+    //the root moves that keep the best result there is, counting the fifty move rule from where the board is.
+    //when winning only the fastest way to the next zeroing move is kept, so the search cant shuffle around forever,
+    //and when losing the slowest
+    pub fn root_moves(&self, board: &board::Board) -> Option::<(Wdl, Vec::<pos::Move>)> {
+        let clock = board.halfmove_clock() as i32;
+        let ranked: Vec::<(pos::Move, Wdl, i32)> = self
+            .root_dtz(board)?
+            .into_iter()
+            .map(|(m, dtz)| {
+                let wdl = if dtz > 0 {
+                    if dtz + clock <= 100 { Wdl::Win } else { Wdl::CursedWin }
+                } else if dtz < 0 {
+                    if -dtz + clock <= 100 { Wdl::Loss } else { Wdl::BlessedLoss }
+                } else {
+                    Wdl::Draw
+                };
+                (m, wdl, dtz)
+            })
+            .collect();
+        let best = ranked.iter().map(|(_, w, _)| *w).fold(Wdl::Loss, |a, b| if b > a { b } else { a });
+        let kept: Vec::<&(pos::Move, Wdl, i32)> = ranked.iter().filter(|(_, w, _)| *w == best).collect();
+        //the lowest dtz is the quickest win, or the most negative and so the longest loss
+        let target = kept.iter().map(|(_, _, d)| *d).min();
+        let moves = kept
+            .iter()
+            .filter(|(_, w, d)| !matches!(w, Wdl::Win | Wdl::Loss) || Some(*d) == target)
+            .map(|(m, _, _)| *m)
+            .collect();
+        Some((best, moves))
+    }
+}
+
+// chess syzygy <dir> [fen]
+pub fn run_probe(dirs: &str, fen: &str) -> Result::<(), String> {
+    let tb = Tablebases::open(dirs)?;
+    println!("{} tables, up to {} pieces", tb.table_count(), tb.max_pieces);
+    let board = board::Board::from_fen(fen);
+    match (tb.probe_wdl(&board), tb.probe_dtz(&board)) {
+        (Some(wdl), Some(dtz)) => println!("wdl {:?} dtz {}", wdl, dtz),
+        _ => return Err("position not in the tables".to_owned()),
+    }
+    if let Some(moves) = tb.root_dtz(&board) {
+        for (m, dtz) in moves {
+            println!("{:>8} dtz {}", crate::chess::san::to_san(&board, &m), dtz);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::tablebase;
+
+    //a small deterministic generator, so a failure can be run again
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % n as u64) as usize
+        }
+    }
+
+    //pieces as fen letters on squares 0..64, none if two share a square, a pawn is on an end rank,
+    //or the side that just moved is in check
+    fn position(pieces: &[(char, usize)], white_to_move: bool, en_passant: &str) -> Option::<board::Board> {
+        let mut grid = [None; 64];
+        for &(c, s) in pieces {
+            let end_rank = !(8..56).contains(&s);
+            if grid[s].is_some() || (c.eq_ignore_ascii_case(&'p') && end_rank) {
+                return None;
+            }
+            grid[s] = Some(c);
+        }
+        let rows: Vec::<String> = (0..8).rev().map(|rank| {
+            let mut row = String::new();
+            let mut empty = 0;
+            for file in 0..8 {
+                match grid[rank * 8 + file] {
+                    Some(c) => {
+                        if empty > 0 {
+                            row.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        row.push(c);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            row
+        }).collect();
+        let fen = format!("{} {} - {} 0 1", rows.join("/"), if white_to_move { "w" } else { "b" }, en_passant);
+        let board = board::Board::from_fen(&fen);
+        (!board.is_in_check(board.active_player().invert())).then_some(board)
+    }
+
+    //random legal positions with these pieces, white or black to move
+    fn sample(rng: &mut Rng, pieces: &[char], count: usize) -> Vec::<board::Board> {
+        let mut res = vec![];
+        while res.len() < count {
+            let placed: Vec::<(char, usize)> = pieces.iter().map(|&c| (c, rng.below(64))).collect();
+            if let Some(board) = position(&placed, rng.below(2) == 0, "-") {
+                res.push(board);
+            }
+        }
+        res
+    }
+
+    fn tablebases() -> Tablebases {
+        let dirs = std::env::var("SYZYGY_PATH").expect("SYZYGY_PATH has to point at the 3 and 4 piece tables");
+        Tablebases::open(&dirs).unwrap()
+    }
+
+    //the retrograde tables to check against, made once for every test that needs them
+    fn retrograde() -> &'static tablebase::Tables {
+        static TABLES: OnceLock::<tablebase::Tables> = OnceLock::new();
+        TABLES.get_or_init(|| {
+            let mut tables = tablebase::Tables::default();
+            for material in [tablebase::Material::Kqk, tablebase::Material::Krk, tablebase::Material::Kpk] {
+                let table = tablebase::Table::generate(material, &tables);
+                tables.add(table);
+            }
+            tables
+        })
+    }
+
+    //writes a table where every position has the same value, which is how the real files store endings like KNvK
+    fn single_value_table(dir: &Path, name: &str, pieces: &[u8], value: u8) {
+        let mut bytes = WDL_MAGIC.to_vec();
+        bytes.push(0);
+        bytes.push(0);
+        bytes.extend_from_slice(pieces);
+        if bytes.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes.extend_from_slice(&[FLAG_SINGLE_VALUE, value]);
+        std::fs::write(dir.join(format!("{}.rtbw", name)), bytes).unwrap();
+    }
+
+    #[test]
+    fn encoding_covers_every_placement() {
+        let e = encoding();
+        assert_eq!(e.map_b1h1h7.iter().max(), Some(&27));
+        assert_eq!(e.map_a1d1d4.iter().max(), Some(&9));
+        let kk = e.map_kk.iter().flat_map(|row| row.iter()).max().unwrap();
+        assert_eq!(*kk, 461);
+        assert_eq!(e.binomial[3][10], 120);
+        //one leading pawn can be on any of the 6 ranks of its file
+        assert_eq!(e.lead_pawns_size[1], [6; 4]);
+    }
+
+    #[test]
+    fn single_value_table_reads_without_the_data() {
+        let dir = std::env::temp_dir().join(format!("syzygy-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // white king, black king, white knight, stored as a draw
+        single_value_table(&dir, "KNvK", &[6, 14, 2], 2);
+        std::fs::write(dir.join("KBvK.rtbw"), b"not a table").unwrap();
+        let tb = Tablebases::open(dir.to_str().unwrap()).unwrap();
+        assert_eq!(tb.max_pieces, 3);
+        for fen in [
+            "8/8/8/4k3/8/2N5/8/K7 w - - 0 1",
+            "8/8/8/4k3/8/2N5/8/K7 b - - 0 1",
+            //black has the knight, so the table is looked up with the colors swapped
+            "k7/8/2n5/8/4K3/8/8/8 w - - 0 1",
+            //the knight can be taken, which goes to KvK
+            "8/8/8/8/8/8/1n6/K6k w - - 0 1",
+        ] {
+            let board = board::Board::from_fen(fen);
+            assert_eq!(tb.probe_wdl(&board), Some(Wdl::Draw), "{}", fen);
+            assert_eq!(tb.probe_dtz(&board), Some(0), "{}", fen);
+        }
+        assert_eq!(tb.probe_wdl(&board::Board::from_fen("8/8/8/4k3/8/2B5/8/K7 w - - 0 1")), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs the 3 and 4 piece syzygy tables in SYZYGY_PATH"]
+    fn matches_retrograde_tables() {
+        let tb = tablebases();
+        let tables = retrograde();
+        let mut rng = Rng(0x5EED);
+        for (pieces, pawns) in [(['K', 'k', 'Q'], false), (['K', 'k', 'R'], false), (['K', 'k', 'P'], true)] {
+            //the extra piece on either side, with either side to move
+            let black_pieces: Vec::<char> = pieces.iter().map(|c| if c.is_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() }).collect();
+            for side in [pieces.to_vec(), black_pieces] {
+                for board in sample(&mut rng, &side, 400) {
+                    let expected = tables.probe(&board).unwrap();
+                    let wdl = tb.probe_wdl(&board).unwrap();
+                    let dtz = tb.probe_dtz(&board).unwrap();
+                    let fen = board.to_fen();
+                    match expected {
+                        tablebase::Outcome::Draw => {
+                            assert_eq!(wdl, Wdl::Draw, "{}", fen);
+                            assert_eq!(dtz, 0, "{}", fen);
+                        }
+                        //without pawns the only zeroing move is the mate, so dtz is the distance to mate,
+                        //or one more where the table rounds it to whole moves
+                        tablebase::Outcome::Win(n) => {
+                            assert_eq!(wdl, Wdl::Win, "{}", fen);
+                            let n = n as i32;
+                            assert!(if pawns { dtz >= 1 && dtz <= n + 1 } else { dtz == n || dtz == n + 1 }, "{} dtz {} mate {}", fen, dtz, n);
+                        }
+                        tablebase::Outcome::Loss(n) => {
+                            assert_eq!(wdl, Wdl::Loss, "{}", fen);
+                            let n = n.max(1) as i32;
+                            assert!(if pawns { dtz <= -1 && dtz >= -n - 1 } else { dtz == -n || dtz == -n - 1 }, "{} dtz {} mate {}", fen, dtz, n);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs the 3 and 4 piece syzygy tables in SYZYGY_PATH"]
+    fn en_passant_is_searched() {
+        let tb = tablebases();
+        let mut rng = Rng(0xE9);
+        let mut checked = 0;
+        while checked < 200 {
+            //a pawn that just went two squares, next to one that can take it
+            let white_to_move = rng.below(2) == 0;
+            let file = rng.below(8);
+            let beside = if file == 0 { 1 } else if file == 7 { 6 } else if rng.below(2) == 0 { file - 1 } else { file + 1 };
+            let (pushed, taker, ep_rank, behind) = if white_to_move { ('p', 'P', 4, 6) } else { ('P', 'p', 3, 1) };
+            let pieces = [('K', rng.below(64)), ('k', rng.below(64)), (pushed, ep_rank * 8 + file), (taker, ep_rank * 8 + beside)];
+            //the squares the pawn went over have to be empty
+            let passed = [(ep_rank + behind) / 2 * 8 + file, behind * 8 + file];
+            if pieces[..2].iter().any(|(_, s)| passed.contains(s)) {
+                continue;
+            }
+            let ep = pos::Square { rank: ((ep_rank + behind) / 2 + 1) as i8, file: file as i8 + 1 }.to_string();
+            let board = match position(&pieces, white_to_move, &ep) {
+                Some(board) => board,
+                None => continue,
+            };
+            //the best of the children, none of which has en passant any more
+            let mut best = Wdl::Loss;
+            for m in validator::get_possible_moves(&mut board.clone()) {
+                let mut child = board.clone();
+                child.make_move(&m);
+                let value = tb.probe_wdl(&child).unwrap().invert();
+                if value > best {
+                    best = value;
+                }
+            }
+            assert_eq!(tb.probe_wdl(&board), Some(best), "{}", board.to_fen());
+            checked += 1;
+        }
+    }
+}
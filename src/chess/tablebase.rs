@@ -0,0 +1,637 @@
+use crate::chess::{board, pos, san, validator};
+use crate::chess::piece::{Piece, Player};
+
+use std::sync::OnceLock;
+
+// distance to mate tables for the endgames small enough to work out here, made by retrograde analysis:
+// start from every mate and walk backwards one move at a time with un-moves.
+// the side with the extra pieces is always white in a table, black positions get flipped over first
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Material {
+    Kqk,
+    Krk,
+    Kpk,
+    Kbnk,
+}
+
+// in the order they have to be made, the pawn ending promotes into the queen and rook ones
+pub const ALL_MATERIALS: [Material; 4] = [Material::Kqk, Material::Krk, Material::Kpk, Material::Kbnk];
+
+// a1 b1 c1 d1 b2 c2 d2 c3 d3 d4, without pawns every position can be turned so the white king is in here
+const TRIANGLE: [usize; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+// stays unresolved forever, for positions that can't be lost whatever the children turn out to be
+const CANNOT_LOSE: u8 = u8::MAX;
+const MAGIC: &[u8; 4] = b"CTB1";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Outcome {
+    // plies to mate, from the point of view of the side to move
+    Win(u32),
+    Loss(u32),
+    Draw,
+}
+
+impl Outcome {
+    //what the position before a move into this one is worth to the side that made it
+    fn before(self) -> Self {
+        match self {
+            Self::Win(n) => Self::Loss(n + 1),
+            Self::Loss(n) => Self::Win(n + 1),
+            Self::Draw => Self::Draw,
+        }
+    }
+
+    //higher is better for the side to move: quick wins, then draws, then slow losses
+    fn rank(self) -> i64 {
+        match self {
+            Self::Win(n) => 1000 - n as i64,
+            Self::Draw => 0,
+            Self::Loss(n) => -1000 + n as i64,
+        }
+    }
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        //moves rather than plies, like mate scores are usually given
+        match self {
+            Self::Win(n) => write!(f, "win, mate in {}", n.div_ceil(2)),
+            Self::Loss(0) => write!(f, "checkmated"),
+            Self::Loss(n) => write!(f, "loss, mated in {}", n / 2),
+            Self::Draw => write!(f, "draw"),
+        }
+    }
+}
+
+fn square(s: usize) -> pos::Square {
+    pos::Square { rank: (s / 8 + 1) as i8, file: (s % 8 + 1) as i8 }
+}
+
+//one of the 8 ways to turn and mirror the board
+fn transform(t: usize, s: usize) -> usize {
+    let (mut file, mut rank) = (s % 8, s / 8);
+    if t & 1 != 0 {
+        file = 7 - file;
+    }
+    if t & 2 != 0 {
+        rank = 7 - rank;
+    }
+    if t & 4 != 0 {
+        std::mem::swap(&mut file, &mut rank);
+    }
+    rank * 8 + file
+}
+
+// white king, black king, then the rest of white's pieces in the order Material::extra gives them
+type Squares = [usize; 4];
+
+impl Material {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Kqk => "KQvK",
+            Self::Krk => "KRvK",
+            Self::Kpk => "KPvK",
+            Self::Kbnk => "KBNvK",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option::<Self> {
+        let name = name.to_ascii_uppercase().replace('V', "");
+        ALL_MATERIALS.into_iter().find(|m| m.name().replace('v', "") == name)
+    }
+
+    //white's pieces besides the king
+    fn extra(self) -> &'static [fn(Player) -> Piece] {
+        match self {
+            Self::Kqk => &[Piece::Queen],
+            Self::Krk => &[Piece::Rook],
+            Self::Kpk => &[Piece::Pawn],
+            Self::Kbnk => &[Piece::Bishop, Piece::Knight],
+        }
+    }
+
+    fn piece_count(self) -> usize {
+        2 + self.extra().len()
+    }
+
+    fn has_pawns(self) -> bool {
+        self == Self::Kpk
+    }
+
+    //pawns only go one way, so those endings can just be mirrored left to right
+    fn transforms(self) -> usize {
+        if self.has_pawns() { 2 } else { 8 }
+    }
+
+    fn king_slots(self) -> usize {
+        if self.has_pawns() { 32 } else { TRIANGLE.len() }
+    }
+
+    fn king_slot(self, s: usize) -> Option::<usize> {
+        if self.has_pawns() {
+            (s % 8 < 4).then_some(s / 8 * 4 + s % 8)
+        } else {
+            TRIANGLE.iter().position(|&t| t == s)
+        }
+    }
+
+    fn king_square(self, slot: usize) -> usize {
+        if self.has_pawns() {
+            slot / 4 * 8 + slot % 4
+        } else {
+            TRIANGLE[slot]
+        }
+    }
+
+    pub fn size(self) -> usize {
+        2 * self.king_slots() * 64usize.pow(self.piece_count() as u32 - 1)
+    }
+
+    fn raw_index(self, squares: &Squares, active: Player) -> Option::<usize> {
+        let slot = self.king_slot(squares[0])?;
+        let side = if active == Player::White { 0 } else { 1 };
+        Some(squares[1..self.piece_count()]
+            .iter()
+            .fold(side * self.king_slots() + slot, |index, &s| index * 64 + s))
+    }
+
+    //the smallest index of all the turned and mirrored copies, so each position is only stored once
+    fn index(self, squares: &Squares, active: Player) -> usize {
+        (0..self.transforms())
+            .filter_map(|t| {
+                let mut turned = *squares;
+                for s in turned.iter_mut().take(self.piece_count()) {
+                    *s = transform(t, *s);
+                }
+                self.raw_index(&turned, active)
+            })
+            .min()
+            .expect("Board Mangled")
+    }
+
+    fn decode(self, mut index: usize) -> (Squares, Player) {
+        let mut squares = [0; 4];
+        for i in (1..self.piece_count()).rev() {
+            squares[i] = index % 64;
+            index /= 64;
+        }
+        squares[0] = self.king_square(index % self.king_slots());
+        let active = if index / self.king_slots() == 0 { Player::White } else { Player::Black };
+        (squares, active)
+    }
+
+    //None if two pieces share a square or a pawn is on the first or last rank
+    fn board(self, squares: &Squares, active: Player) -> Option::<board::Board> {
+        let n = self.piece_count();
+        if (0..n).any(|i| (i + 1..n).any(|j| squares[i] == squares[j])) {
+            return None;
+        }
+        let mut pieces = vec![(Piece::King(Player::White), square(squares[0])), (Piece::King(Player::Black), square(squares[1]))];
+        for (i, kind) in self.extra().iter().enumerate() {
+            let piece = kind(Player::White);
+            if matches!(piece, Piece::Pawn(_)) && !(8..56).contains(&squares[i + 2]) {
+                return None;
+            }
+            pieces.push((piece, square(squares[i + 2])));
+        }
+        Some(board::Board::from_pieces(&pieces, active))
+    }
+
+    //the ending a board is in, with its squares and side to move flipped round so the stronger side is white
+    fn identify(board: &board::Board) -> Option::<(Self, Squares, Player)> {
+        let white = board.get_pieces(Player::White);
+        let black = board.get_pieces(Player::Black);
+        let (strong, weak, flip) = if white.len() >= black.len() { (white, black, false) } else { (black, white, true) };
+        if weak.len() != 1 || !board.variant().uses_tablebases() {
+            return None;
+        }
+        let owner = if flip { Player::Black } else { Player::White };
+        let turn = |s: pos::Square| if flip { s.index() ^ 56 } else { s.index() };
+        let material = ALL_MATERIALS.into_iter().find(|m| {
+            m.piece_count() == strong.len() + 1 && m.extra().iter().all(|kind| strong.iter().any(|p| p.piece_type == kind(owner)))
+        })?;
+        let mut squares = [0; 4];
+        squares[0] = turn(strong.iter().find(|p| p.piece_type == Piece::King(owner))?.pos);
+        squares[1] = turn(weak[0].pos);
+        for (i, kind) in material.extra().iter().enumerate() {
+            squares[i + 2] = turn(strong.iter().find(|p| p.piece_type == kind(owner))?.pos);
+        }
+        let active = if flip { board.active_player().invert() } else { board.active_player() };
+        Some((material, squares, active))
+    }
+
+    //squares a piece could have come from without capturing anything
+    fn unmoves(kind: Piece, from: usize, occupied: &[usize]) -> Vec::<usize> {
+        let empty = |s: usize| !occupied.contains(&s);
+        let step = |s: usize, df: i32, dr: i32| {
+            let (file, rank) = ((s % 8) as i32 + df, (s / 8) as i32 + dr);
+            ((0..8).contains(&file) && (0..8).contains(&rank)).then_some((rank * 8 + file) as usize)
+        };
+        const KING: [(i32, i32); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+        const KNIGHT: [(i32, i32); 8] = [(1, 2), (2, 1), (-1, 2), (-2, 1), (1, -2), (2, -1), (-1, -2), (-2, -1)];
+        let slide = |dirs: &[(i32, i32)]| {
+            let mut res = vec![];
+            for &(df, dr) in dirs {
+                let mut s = from;
+                while let Some(next) = step(s, df, dr).filter(|&n| empty(n)) {
+                    res.push(next);
+                    s = next;
+                }
+            }
+            res
+        };
+        match kind {
+            Piece::King(_) => KING.iter().filter_map(|&(df, dr)| step(from, df, dr)).filter(|&s| empty(s)).collect(),
+            Piece::Knight(_) => KNIGHT.iter().filter_map(|&(df, dr)| step(from, df, dr)).filter(|&s| empty(s)).collect(),
+            Piece::Bishop(_) => slide(&KING[4..]),
+            Piece::Rook(_) => slide(&KING[..4]),
+            Piece::Queen(_) => slide(&KING),
+            //only white pawns are ever in a table. one square back, or two from the fourth rank
+            Piece::Pawn(_) => {
+                let mut res = vec![];
+                if from >= 16 && empty(from - 8) {
+                    res.push(from - 8);
+                    if (24..32).contains(&from) && empty(from - 16) {
+                        res.push(from - 16);
+                    }
+                }
+                res
+            }
+            Piece::Empty => vec![],
+        }
+    }
+
+    //every position that leads here by one move of the other side, that stays in this ending
+    fn predecessors(self, index: usize) -> Vec::<usize> {
+        let (squares, active) = self.decode(index);
+        let mover = active.invert();
+        let n = self.piece_count();
+        let movers: Vec::<usize> = if mover == Player::White { [0].into_iter().chain(2..n).collect() } else { vec![1] };
+        let mut res = vec![];
+        for i in movers {
+            let kind = match i {
+                0 | 1 => Piece::King(mover),
+                _ => self.extra()[i - 2](mover),
+            };
+            for from in Self::unmoves(kind, squares[i], &squares[..n]) {
+                let mut before = squares;
+                before[i] = from;
+                //the side that didn't move can't have been left in check
+                match self.board(&before, mover) {
+                    Some(board) if !board.is_in_check(active) => res.push(self.index(&before, mover)),
+                    _ => (),
+                }
+            }
+        }
+        res.sort_unstable();
+        res.dedup();
+        res
+    }
+}
+
+pub struct Table {
+    pub material: Material,
+    // 0 for draws and positions that can't happen, otherwise plies to mate + 1. odd plies are wins for the side to move
+    dtm: Vec::<u8>,
+}
+
+impl Table {
+    //others has to hold the tables this one converts into, which for now is only KPK needing KQK and KRK
+    pub fn generate(material: Material, others: &Tables) -> Self {
+        let size = material.size();
+        let mut dtm = vec![0u8; size];
+        let mut left = vec![CANNOT_LOSE; size];
+        // positions waiting to be settled, by plies to mate
+        let mut queue: Vec::<Vec::<u32>> = vec![vec![]];
+        let push = |queue: &mut Vec::<Vec::<u32>>, plies: usize, index: usize| {
+            if queue.len() <= plies {
+                queue.resize(plies + 1, vec![]);
+            }
+            queue[plies].push(index as u32);
+        };
+
+        //forwards once through everything: find the mates and count each position's moves
+        for (index, moves_left) in left.iter_mut().enumerate() {
+            let (squares, active) = material.decode(index);
+            if material.index(&squares, active) != index {
+                continue;
+            }
+            let mut board = match material.board(&squares, active) {
+                Some(board) if !board.is_in_check(active.invert()) => board,
+                _ => continue,
+            };
+            let moves = validator::get_possible_moves(&mut board);
+            if moves.is_empty() {
+                if board.is_in_check(active) {
+                    push(&mut queue, 0, index);
+                }
+                continue;
+            }
+            let mut children = vec![];
+            let mut can_lose = true;
+            for m in &moves {
+                let mut child = board.clone();
+                child.make_move(m);
+                match Material::identify(&child) {
+                    Some((child_material, squares, active)) if child_material == material => {
+                        children.push(material.index(&squares, active));
+                    }
+                    //captures and promotions leave the table. anything not in another table is a dead draw here.
+                    //none of these endings can be lost by leaving them, so that case doesn't need its distance kept
+                    _ => match others.probe(&child).unwrap_or(Outcome::Draw).before() {
+                        Outcome::Win(n) => {
+                            push(&mut queue, n as usize, index);
+                            can_lose = false;
+                        }
+                        Outcome::Draw => can_lose = false,
+                        Outcome::Loss(_) => (),
+                    },
+                }
+            }
+            children.sort_unstable();
+            children.dedup();
+            if can_lose {
+                *moves_left = children.len() as u8;
+            }
+        }
+
+        //then backwards from the mates, nearest first, so the first distance a position gets is the right one
+        let mut plies = 0;
+        while plies < queue.len() {
+            for index in std::mem::take(&mut queue[plies]) {
+                let index = index as usize;
+                if dtm[index] != 0 {
+                    continue;
+                }
+                dtm[index] = u8::try_from(plies + 1).expect("mate too far away to store");
+                for before in material.predecessors(index) {
+                    if dtm[before] != 0 {
+                        continue;
+                    }
+                    if plies % 2 == 0 {
+                        //a move into a lost position wins
+                        push(&mut queue, plies + 1, before);
+                    } else if left[before] != CANNOT_LOSE {
+                        //lost once every move leads to a win for the other side
+                        left[before] -= 1;
+                        if left[before] == 0 {
+                            push(&mut queue, plies + 1, before);
+                        }
+                    }
+                }
+            }
+            plies += 1;
+        }
+        Self { material, dtm }
+    }
+
+    fn outcome(&self, index: usize) -> Outcome {
+        match self.dtm[index] {
+            0 => Outcome::Draw,
+            v if v % 2 == 0 => Outcome::Win(v as u32 - 1),
+            v => Outcome::Loss(v as u32 - 1),
+        }
+    }
+
+    pub fn probe(&self, board: &board::Board) -> Option::<Outcome> {
+        let (material, squares, active) = Material::identify(board)?;
+        (material == self.material).then(|| self.outcome(material.index(&squares, active)))
+    }
+
+    //the longest mate with white to move, and how many positions are won, drawn and lost with each side to move
+    pub fn stats(&self) -> (Outcome, [[usize; 3]; 2]) {
+        let mut longest = Outcome::Draw;
+        let mut counts = [[0; 3]; 2];
+        let half = self.dtm.len() / 2;
+        for index in 0..self.dtm.len() {
+            let side = index / half;
+            match self.outcome(index) {
+                Outcome::Win(n) => {
+                    counts[side][0] += 1;
+                    if side == 0 && !matches!(longest, Outcome::Win(m) if m >= n) {
+                        longest = Outcome::Win(n);
+                    }
+                }
+                Outcome::Loss(_) => counts[side][2] += 1,
+                Outcome::Draw => {
+                    //the zeros are also every position that can't happen or is stored under another index
+                    let (squares, active) = self.material.decode(index);
+                    let legal = self.material.index(&squares, active) == index
+                        && self.material.board(&squares, active).is_some_and(|b| !b.is_in_check(active.invert()));
+                    if legal {
+                        counts[side][1] += 1;
+                    }
+                }
+            }
+        }
+        (longest, counts)
+    }
+
+    //the bytes as they are, except runs of zeros are a 0 and then how many, up to 255
+    pub fn save(&self, path: &str) -> Result::<(), String> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(self.material.name().len() as u8);
+        bytes.extend_from_slice(self.material.name().as_bytes());
+        bytes.extend_from_slice(&(self.dtm.len() as u32).to_le_bytes());
+        let mut i = 0;
+        while i < self.dtm.len() {
+            if self.dtm[i] == 0 {
+                let run = self.dtm[i..].iter().take(255).take_while(|&&v| v == 0).count();
+                bytes.extend_from_slice(&[0, run as u8]);
+                i += run;
+            } else {
+                bytes.push(self.dtm[i]);
+                i += 1;
+            }
+        }
+        std::fs::write(path, bytes).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result::<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        let bad = || format!("{}: not an endgame table", path);
+        if bytes.len() < 5 || &bytes[..4] != MAGIC {
+            return Err(bad());
+        }
+        let name_end = 5 + bytes[4] as usize;
+        let name = bytes.get(5..name_end).and_then(|n| std::str::from_utf8(n).ok()).ok_or_else(bad)?;
+        let material = Material::from_name(name).ok_or_else(bad)?;
+        let size = bytes.get(name_end..name_end + 4).ok_or_else(bad)?;
+        let size = u32::from_le_bytes(size.try_into().expect("4 bytes")) as usize;
+        if size != material.size() {
+            return Err(bad());
+        }
+        let mut dtm = Vec::with_capacity(size);
+        let mut data = bytes[name_end + 4..].iter();
+        while let Some(&v) = data.next() {
+            if v == 0 {
+                let run = *data.next().ok_or_else(bad)? as usize;
+                dtm.resize(dtm.len() + run, 0);
+            } else {
+                dtm.push(v);
+            }
+        }
+        if dtm.len() != size {
+            return Err(bad());
+        }
+        Ok(Self { material, dtm })
+    }
+}
+
+#[derive(Default)]
+pub struct Tables {
+    tables: Vec::<Table>,
+}
+
+impl Tables {
+    pub fn file_name(dir: &str, material: Material) -> String {
+        format!("{}/{}.ctb", dir.trim_end_matches('/'), material.name())
+    }
+
+    //whichever tables are in dir, missing ones are left out
+    pub fn open(dir: &str) -> Result::<Self, String> {
+        let mut tables = Self::default();
+        for material in ALL_MATERIALS {
+            let path = Self::file_name(dir, material);
+            if std::path::Path::new(&path).exists() {
+                tables.add(Table::load(&path)?);
+            }
+        }
+        Ok(tables)
+    }
+
+    pub fn add(&mut self, table: Table) {
+        self.tables.retain(|t| t.material != table.material);
+        self.tables.push(table);
+    }
+
+    pub fn get(&self, material: Material) -> Option::<&Table> {
+        self.tables.iter().find(|t| t.material == material)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn probe(&self, board: &board::Board) -> Option::<Outcome> {
+        let (material, _, _) = Material::identify(board)?;
+        self.get(material)?.probe(board)
+    }
+
+    //every legal move with what it leads to, best first
+    pub fn probe_moves(&self, board: &board::Board) -> Option::<Vec::<(pos::Move, Outcome)>> {
+        self.probe(board)?;
+        let mut moves = vec![];
+        for m in validator::get_possible_moves(&mut board.clone()) {
+            let mut child = board.clone();
+            child.make_move(&m);
+            let outcome = match Material::identify(&child) {
+                Some(_) => self.probe(&child)?,
+                None => Outcome::Draw,
+            };
+            moves.push((m, outcome.before()));
+        }
+        moves.sort_by_key(|(_, outcome)| -outcome.rank());
+        Some(moves)
+    }
+}
+
+// one bit per KPK position, set where the side with the pawn wins
+pub struct Bitbase {
+    bits: Vec::<u64>,
+}
+
+impl Bitbase {
+    pub fn from_table(table: &Table) -> Self {
+        let mut bits = vec![0u64; table.dtm.len().div_ceil(64)];
+        let half = table.dtm.len() / 2;
+        for (index, &v) in table.dtm.iter().enumerate() {
+            //white, the pawn's side, wins when it's to move and the plies are odd or when black is and they're even
+            let white_to_move = index < half;
+            if v != 0 && ((v % 2 == 0) == white_to_move) {
+                bits[index / 64] |= 1 << (index % 64);
+            }
+        }
+        Self { bits }
+    }
+
+    //Some(true) if the side with the pawn wins, None if this isn't a king and pawn against king
+    pub fn probe(&self, board: &board::Board) -> Option::<bool> {
+        match Material::identify(board)? {
+            (Material::Kpk, squares, active) => {
+                let index = Material::Kpk.index(&squares, active);
+                Some(self.bits[index / 64] & (1 << (index % 64)) != 0)
+            }
+            _ => None,
+        }
+    }
+}
+
+static KPK: OnceLock::<Bitbase> = OnceLock::new();
+
+//makes the KPK bitbase, which takes a moment. evaluation only uses it once this has been called, so it's done
+//before anything searches and a position never scores differently depending on when it was looked at
+pub fn init_kpk() {
+    KPK.get_or_init(|| {
+        let mut tables = Tables::default();
+        for material in [Material::Kqk, Material::Krk] {
+            tables.add(Table::generate(material, &tables));
+        }
+        Bitbase::from_table(&Table::generate(Material::Kpk, &tables))
+    });
+}
+
+//None when init_kpk hasn't been called
+pub fn kpk() -> Option::<&'static Bitbase> {
+    KPK.get()
+}
+
+// chess tb-gen <dir> [endings]
+pub fn run_generate(dir: &str, names: &[String]) -> Result::<(), String> {
+    let mut wanted = vec![];
+    for name in names {
+        wanted.push(Material::from_name(name).ok_or_else(|| format!("unknown ending {}, try {}", name,
+            ALL_MATERIALS.map(|m| m.name()).join(" ")))?);
+    }
+    if wanted.is_empty() {
+        wanted = ALL_MATERIALS.to_vec();
+    }
+    std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir, e))?;
+    let mut tables = Tables::open(dir)?;
+    for material in ALL_MATERIALS {
+        let needed = wanted.contains(&material)
+            || (material != Material::Kbnk && wanted.contains(&Material::Kpk) && tables.get(material).is_none());
+        if !needed {
+            continue;
+        }
+        let start = std::time::Instant::now();
+        let table = Table::generate(material, &tables);
+        let (longest, counts) = table.stats();
+        let path = Tables::file_name(dir, material);
+        table.save(&path)?;
+        println!("{} in {:.1}s, longest mate {}", material.name(), start.elapsed().as_secs_f64(), longest);
+        for (side, [won, drawn, lost]) in ["white", "black"].iter().zip(counts) {
+            println!("  {} to move: {} won, {} drawn, {} lost", side, won, drawn, lost);
+        }
+        println!("  written to {}", path);
+        tables.add(table);
+    }
+    Ok(())
+}
+
+// chess tb-probe <dir> [fen]
+pub fn run_probe(dir: &str, fen: &str) -> Result::<(), String> {
+    let tables = Tables::open(dir)?;
+    if tables.len() == 0 {
+        return Err(format!("no endgame tables in {}", dir));
+    }
+    let game = crate::chess::Game::from_fen(fen);
+    let outcome = game.endgame(&tables).ok_or_else(|| "position not in the tables".to_owned())?;
+    println!("{}", outcome);
+    let board = board::Board::from_fen(fen);
+    for (m, outcome) in tables.probe_moves(&board).unwrap_or_default() {
+        println!("{:>8} {}", san::to_san(&board, &m), outcome);
+    }
+    Ok(())
+}
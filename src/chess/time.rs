@@ -0,0 +1,101 @@
+use crate::chess::piece;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// time kept back for the gui and the os, per move
+const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+// guess at how many moves are left when the gui doesnt say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// the soft limit can be stretched this much at most when the search is unsure
+const MAX_SOFT_SCALE: f64 = 2.5;
+// a score drop this big between iterations means something is going wrong
+const SCORE_DROP: i32 = 30;
+
+//what the gui asked for with go, times are in milliseconds
+#[derive(Clone, Copy, Default)]
+pub struct Limits {
+    pub wtime: Option::<u64>,
+    pub btime: Option::<u64>,
+    pub winc: Option::<u64>,
+    pub binc: Option::<u64>,
+    pub movestogo: Option::<u32>,
+    pub movetime: Option::<u64>,
+    pub depth: Option::<i32>,
+    pub infinite: bool,
+}
+
+//decides how long a search gets, and when to abort it
+pub struct TimeManager {
+    start: Instant,
+    // dont start another iteration past this, when the search looks settled
+    soft: Option::<Duration>,
+    // abort the search past this, no matter what
+    hard: Option::<Duration>,
+    stop: Arc::<AtomicBool>,
+    // how much the soft limit is stretched right now
+    scale: f64,
+    best_move_changes: f64,
+}
+
+impl TimeManager {
+    pub fn new(limits: &Limits, player: piece::Player, stop: Arc::<AtomicBool>) -> Self {
+        let (time, inc) = match player {
+            piece::Player::White => (limits.wtime, limits.winc),
+            piece::Player::Black => (limits.btime, limits.binc),
+        };
+        let (soft, hard) = if limits.infinite {
+            (None, None)
+        } else if let Some(movetime) = limits.movetime {
+            let t = Duration::from_millis(movetime).saturating_sub(MOVE_OVERHEAD).max(Duration::from_millis(1));
+            (Some(t), Some(t))
+        } else if let Some(time) = time {
+            let left = Duration::from_millis(time).saturating_sub(MOVE_OVERHEAD);
+            let inc = Duration::from_millis(inc.unwrap_or(0));
+            let moves_to_go = limits.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).clamp(1, 50);
+            //never plan on using more than what is left on the clock
+            let hard = (left / 2 + inc / 2).min(left * 4 / 5);
+            let soft = (left / moves_to_go + inc * 3 / 4).min(hard);
+            (Some(soft), Some(hard))
+        } else {
+            (None, None)
+        };
+        Self {
+            start: Instant::now(),
+            soft,
+            hard,
+            stop,
+            scale: 1.0,
+            best_move_changes: 0.0,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    //true once the search has to stop right away, either out of time or told to by the gui
+    pub fn should_abort(&self) -> bool {
+        self.stop.load(Ordering::Relaxed) || self.hard.is_some_and(|hard| self.elapsed() >= hard)
+    }
+
+    //called after every finished iteration, a best move that keeps changing or a falling score buys more time
+    pub fn update(&mut self, best_move_changed: bool, score_drop: i32) {
+        self.best_move_changes = self.best_move_changes / 2.0 + if best_move_changed { 1.0 } else { 0.0 };
+        let instability = 1.0 + self.best_move_changes * 0.5;
+        let falling = if score_drop >= SCORE_DROP { 1.5 } else { 1.0 };
+        self.scale = (instability * falling).min(MAX_SOFT_SCALE);
+    }
+
+    pub fn should_start_iteration(&self) -> bool {
+        if self.should_abort() {
+            return false;
+        }
+        match self.soft {
+            //the next iteration usually takes longer than all the ones before it together
+            Some(soft) => self.elapsed().as_secs_f64() < soft.as_secs_f64() * self.scale * 0.6,
+            None => true,
+        }
+    }
+}
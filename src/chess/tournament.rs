@@ -0,0 +1,484 @@
+use crate::chess::variant::{Variant, Win};
+use crate::chess::board::Board;
+use crate::chess::piece::Piece;
+use crate::chess::{clock, epd, pgn, pos, san, validator, zobrist, Game, GameResult, Player};
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// how long an engine gets to answer uci and isready
+const HANDSHAKE_TIME: Duration = Duration::from_secs(10);
+// how long an engine that ran out of time gets to answer stop before it counts as hung
+const STOP_GRACE: Duration = Duration::from_secs(1);
+// without a clock, going this far over movetime loses on time
+const MOVETIME_GRACE: Duration = Duration::from_secs(1);
+// the fifty move rule, in plies
+const FIFTY_MOVES: u32 = 100;
+
+//a uci engine running as a child process, its output is read on another thread so waits can time out
+struct Engine {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver::<String>,
+    chess960: bool,
+}
+
+impl Engine {
+    fn start(command: &str) -> Result::<Self, String> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or("empty engine command")?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("couldn't start {}: {}", command, e))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, lines) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut engine = Self { name: program.to_owned(), child, stdin, lines, chess960: false };
+        engine.send("uci")?;
+        for line in engine.wait_for("uciok", HANDSHAKE_TIME)? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_owned();
+            }
+        }
+        Ok(engine)
+    }
+
+    fn send(&mut self, line: &str) -> Result::<(), String> {
+        writeln!(self.stdin, "{}", line).map_err(|e| format!("{} went away: {}", self.name, e))
+    }
+
+    //reads lines until one starts with the token, giving back everything read. err if it takes longer than limit
+    fn wait_for(&mut self, token: &str, limit: Duration) -> Result::<Vec::<String>, String> {
+        let deadline = Instant::now() + limit;
+        let mut read = Vec::<String>::new();
+        loop {
+            match self.lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) => {
+                    let done = line.split_whitespace().next() == Some(token);
+                    read.push(line);
+                    if done {
+                        return Ok(read);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return Err(format!("{} didn't send {}", self.name, token)),
+                Err(RecvTimeoutError::Disconnected) => return Err(format!("{} quit", self.name)),
+            }
+        }
+    }
+
+    fn new_game(&mut self, variant: Variant, chess960: bool) -> Result::<(), String> {
+        if variant != Variant::Standard {
+            self.send(&format!("setoption name UCI_Variant value {}", variant.name()))?;
+        }
+        if chess960 != self.chess960 {
+            self.send(&format!("setoption name UCI_Chess960 value {}", chess960))?;
+            self.chess960 = chess960;
+        }
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.wait_for("readyok", HANDSHAKE_TIME).map(|_| ())
+    }
+
+    //asks for a move, and gives it back with a comment like +0.31/12 from the last info line.
+    //none if the engine needed more than limit, it's told to stop then
+    fn think(&mut self, position: &str, go: &str, limit: Duration) -> Result::<Option::<(String, String)>, String> {
+        self.send(position)?;
+        self.send(go)?;
+        let lines = match self.wait_for("bestmove", limit) {
+            Ok(lines) => lines,
+            Err(_) => {
+                self.send("stop")?;
+                self.wait_for("bestmove", STOP_GRACE)?;
+                return Ok(None);
+            }
+        };
+        let mut score = String::new();
+        let mut depth = String::new();
+        for line in &lines {
+            let words: Vec::<&str> = line.split_whitespace().collect();
+            for (i, w) in words.iter().enumerate() {
+                match (*w, words.get(i + 1), words.get(i + 2)) {
+                    ("depth", Some(d), _) => depth = d.to_string(),
+                    ("score", Some(&"cp"), Some(cp)) => {
+                        score = cp.parse::<i32>().map_or(String::new(), |cp| format!("{:+.2}", cp as f64 / 100.0))
+                    }
+                    ("score", Some(&"mate"), Some(m)) => {
+                        score = match m.strip_prefix('-') {
+                            Some(m) => format!("-M{}", m),
+                            None => format!("+M{}", m),
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let best = lines.last().and_then(|l| l.split_whitespace().nth(1)).unwrap_or("0000").to_owned();
+        let comment = if score.is_empty() { String::new() } else { format!("{}/{}", score, depth) };
+        Ok(Some((best, comment)))
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let deadline = Instant::now() + STOP_GRACE;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+//where a game starts: a position and the moves played from it, in coordinate notation
+#[derive(Clone)]
+pub struct Opening {
+    pub fen: String,
+    pub moves: Vec::<String>,
+}
+
+//openings from a pgn file, every move of each game, or from an epd file, one position a line.
+//the variant is needed to read pgn moves by its rules
+pub fn load_openings(path: &str, variant: Variant) -> Result::<Vec::<Opening>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path, e))?;
+    if path.to_ascii_lowercase().ends_with(".pgn") {
+        return pgn::parse(&text).iter().map(|g| {
+            let mut game = Game::from_fen(g.start_fen());
+            if variant != Variant::Standard {
+                game.set_variant(variant);
+            }
+            let mut moves = Vec::<String>::new();
+            for m in &g.moves {
+                let m = san::parse_san(&game.board, &m.san)?;
+                game.board.make_move(&m);
+                moves.push(m.to_string());
+            }
+            Ok(Opening { fen: g.start_fen().to_owned(), moves })
+        }).collect();
+    }
+    Ok(epd::parse(&text).into_iter().map(|e| Opening { fen: e.fen, moves: vec![] }).collect())
+}
+
+pub struct MatchSettings {
+    pub engines: [String; 2],
+    pub games: usize,
+    pub concurrency: usize,
+    pub openings: Vec::<Opening>,
+    pub clock: Option::<clock::TimeControl>,
+    // per move, when there's no clock
+    pub movetime: Duration,
+    pub variant: Variant,
+    pub pgn: String,
+}
+
+//the go command for the player to move, from the clock or the fixed move time
+fn go_command(game: &Game, movetime: Duration) -> (String, Duration) {
+    let clock = match &game.clock {
+        Some(clock) => clock,
+        None => return (format!("go movetime {}", movetime.as_millis()), movetime + MOVETIME_GRACE),
+    };
+    let player = game.board.active_player();
+    let ms = |p: Player| clock.remaining(p).as_millis();
+    let inc = |p: Player| clock.increment(p).as_millis();
+    let mut go = format!(
+        "go wtime {} btime {} winc {} binc {}",
+        ms(Player::White),
+        ms(Player::Black),
+        inc(Player::White),
+        inc(Player::Black)
+    );
+    if let Some(moves) = clock.moves_to_go(player) {
+        go.push_str(&format!(" movestogo {}", moves));
+    }
+    (go, clock.remaining(player))
+}
+
+//draws the board doesn't know about, it has no move history. a mate or stalemate on the same move stands
+pub fn adjudicate(game: &mut Game, seen: &mut HashMap::<u64, u32>) {
+    if game.result().is_some() {
+        return;
+    }
+    let times_seen = seen.entry(zobrist::hash(&game.board)).or_insert(0);
+    *times_seen += 1;
+    let dead = !game.board.has_mating_material(Player::White) && !game.board.has_mating_material(Player::Black);
+    if *times_seen >= 3 || game.board.halfmove_clock() >= FIFTY_MOVES || dead {
+        game.end(GameResult::Draw, "normal");
+    }
+}
+
+//plays one game, white is engines[0]. the opening moves count as played already
+fn play_game(engines: &mut [Engine; 2], opening: &Opening, settings: &MatchSettings) -> Result::<Game, String> {
+    let mut game = Game::from_fen(&opening.fen);
+    if settings.variant != Variant::Standard {
+        game.set_variant(settings.variant);
+    }
+    //castles have to be sent as the king taking its rook then, so the opening moves are written out again
+    let chess960 = chess960_castling(&game.board);
+    let mut moves = Vec::<String>::new();
+    for m in &opening.moves {
+        let legal_moves = validator::get_possible_moves(&mut game.board.clone());
+        let found = crate::chess::find_move(&legal_moves, m, game.board.active_player())
+            .map_err(|e| format!("opening move {}: {}", m, e))?;
+        moves.push(found.to_uci(chess960));
+        game.record_move(&found, None);
+    }
+    for engine in engines.iter_mut() {
+        engine.new_game(settings.variant, chess960)?;
+    }
+    if let Some(control) = &settings.clock {
+        game.set_clock(control.clone());
+    }
+    let first = game.board.active_player();
+    if let Some(clock) = &mut game.clock {
+        clock.start(first);
+    }
+    let mut seen = HashMap::<u64, u32>::new();
+    loop {
+        adjudicate(&mut game, &mut seen);
+        if game.result().is_some() {
+            return Ok(game);
+        }
+        let player = game.board.active_player();
+        let engine = &mut engines[(player == Player::Black) as usize];
+        let position = if opening.fen == settings.variant.start_fen() {
+            format!("position startpos moves {}", moves.join(" "))
+        } else {
+            format!("position fen {} moves {}", opening.fen, moves.join(" "))
+        };
+        let (go, limit) = go_command(&game, settings.movetime);
+        let reply = engine.think(&position, &go, limit)?;
+        let in_time = match &mut game.clock {
+            Some(clock) => clock.press(player),
+            None => reply.is_some(),
+        };
+        let (best, mut comment) = match reply {
+            Some(reply) if in_time => reply,
+            _ => {
+                game.flag(player);
+                return Ok(game);
+            }
+        };
+        let legal_moves = validator::get_possible_moves(&mut game.board.clone());
+        let m = match crate::chess::find_move(&legal_moves, &best, player) {
+            Ok(m) => m,
+            Err(_) => {
+                game.end(GameResult::Win(player.invert(), Win::IllegalMove), "rules infraction");
+                return Ok(game);
+            }
+        };
+        if let Some(clock) = &game.clock {
+            comment = format!("{} [%clk {}]", comment, clock::format_clock(clock.remaining(player))).trim().to_owned();
+        }
+        game.record_move(&m, (!comment.is_empty()).then_some(comment));
+        moves.push(m.to_uci(chess960));
+    }
+}
+
+//whether the engines need UCI_Chess960, a king or a rook that can castle is off its usual square
+fn chess960_castling(board: &Board) -> bool {
+    [(Player::White, 1), (Player::Black, 8)].into_iter().any(|(player, rank)| {
+        [(true, 8), (false, 1)].into_iter().any(|(kingside, corner)| match board.castle_rook(player, kingside) {
+            Some(rook) => rook.file != corner || board.get(pos::Square { rank, file: 5 }) != Piece::King(player),
+            None => false,
+        })
+    })
+}
+
+//the elo difference a score fraction means. kept within +-1200 so a bound at 0% or 100% stays finite
+pub fn elo(score: f64) -> f64 {
+    let score = score.clamp(0.001, 0.999);
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+//elo difference from a match and the 95% error margin, from how spread out the single game scores were.
+//none when one side scored every point, any difference fits that
+pub fn elo_estimate(wins: u32, draws: u32, losses: u32) -> Option::<(f64, f64)> {
+    let n = (wins + draws + losses) as f64;
+    let score = (wins as f64 + draws as f64 / 2.0) / n;
+    if n == 0.0 || score == 0.0 || score == 1.0 {
+        return None;
+    }
+    let variance = (wins as f64 * (1.0 - score).powi(2)
+        + draws as f64 * (0.5 - score).powi(2)
+        + losses as f64 * score.powi(2))
+        / n;
+    if variance == 0.0 {
+        return Some((elo(score), 0.0));
+    }
+    let margin = 1.96 * (variance / n).sqrt();
+    let low = elo(score - margin);
+    let high = elo(score + margin);
+    Some((elo(score), (high - low) / 2.0))
+}
+
+//wins, draws and losses from the first engine's side
+#[derive(Default)]
+struct Tally {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+    finished: usize,
+}
+
+//plays engine against engine, games in pairs from each opening with colours swapped, and adds them all to the pgn
+pub fn run(settings: &MatchSettings) -> Result::<(), String> {
+    let default_opening = Opening { fen: settings.variant.start_fen().to_owned(), moves: vec![] };
+    let next = AtomicUsize::new(0);
+    let tally = Mutex::new(Tally::default());
+    let mut out = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&settings.pgn)
+        .map_err(|e| format!("couldn't open {}: {}", settings.pgn, e))?;
+    let out = Mutex::new(&mut out);
+    let errors = Mutex::new(Vec::<String>::new());
+
+    std::thread::scope(|scope| {
+        for _ in 0..settings.concurrency.clamp(1, settings.games.max(1)) {
+            scope.spawn(|| {
+                let worker = || -> Result::<(), String> {
+                    let first = Engine::start(&settings.engines[0])?;
+                    let mut second = Engine::start(&settings.engines[1])?;
+                    if first.name == second.name {
+                        second.name = format!("{} 2", second.name);
+                    }
+                    let mut pair = [first, second];
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= settings.games {
+                            return Ok(());
+                        }
+                        let opening = match settings.openings.len() {
+                            0 => &default_opening,
+                            n => &settings.openings[index / 2 % n],
+                        };
+                        //the first engine has white in the first game of each pair
+                        let swapped = index % 2 == 1;
+                        if swapped {
+                            pair.swap(0, 1);
+                        }
+                        let played = play_game(&mut pair, opening, settings);
+                        let names = [pair[0].name.clone(), pair[1].name.clone()];
+                        if swapped {
+                            pair.swap(0, 1);
+                        }
+                        let mut game = played?;
+                        let result = game.result();
+                        let text = game.pgn(&[
+                            ("Event", "engine match".to_owned()),
+                            ("Round", (index + 1).to_string()),
+                            ("White", names[0].clone()),
+                            ("Black", names[1].clone()),
+                        ]);
+                        out.lock().expect("pgn lock").write_all(text.as_bytes()).map_err(|e| e.to_string())?;
+                        let mut t = tally.lock().expect("tally lock");
+                        let (first_won, why) = match result {
+                            Some(GameResult::Win(winner, win)) => (Some((winner == Player::White) != swapped), win.to_string()),
+                            _ => (None, "Draw".to_owned()),
+                        };
+                        match first_won {
+                            Some(true) => t.wins += 1,
+                            Some(false) => t.losses += 1,
+                            None => t.draws += 1,
+                        }
+                        t.finished += 1;
+                        println!(
+                            "game {} of {}: {} vs {}, {} ({}), score {}-{}-{}",
+                            t.finished,
+                            settings.games,
+                            names[0],
+                            names[1],
+                            match result {
+                                Some(GameResult::Win(Player::White, _)) => "1-0",
+                                Some(GameResult::Win(Player::Black, _)) => "0-1",
+                                _ => "1/2-1/2",
+                            },
+                            why,
+                            t.wins,
+                            t.draws,
+                            t.losses,
+                        );
+                    }
+                };
+                if let Err(msg) = worker() {
+                    errors.lock().expect("error lock").push(msg);
+                }
+            });
+        }
+    });
+
+    let t = tally.into_inner().expect("tally lock");
+    let played = t.wins + t.draws + t.losses;
+    if played > 0 {
+        println!(
+            "{} games, wins {} draws {} losses {}, score {:.1}%",
+            played,
+            t.wins,
+            t.draws,
+            t.losses,
+            (t.wins as f64 + t.draws as f64 / 2.0) * 100.0 / played as f64
+        );
+        match elo_estimate(t.wins, t.draws, t.losses) {
+            Some((diff, margin)) => println!("elo difference {:+.1} +/- {:.1}", diff, margin),
+            None => println!("elo difference undefined"),
+        }
+    }
+    match errors.into_inner().expect("error lock").first() {
+        Some(msg) => Err(msg.clone()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mates_stand_over_adjudicated_draws() {
+        //mated on the hundredth half move without a capture or pawn move
+        let mut game = Game::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 100 80");
+        adjudicate(&mut game, &mut HashMap::<u64, u32>::new());
+        assert!(matches!(game.result(), Some(GameResult::Win(Player::White, _))));
+        let mut game = Game::from_fen("7k/8/6K1/8/8/8/8/6Q1 w - - 100 80");
+        adjudicate(&mut game, &mut HashMap::<u64, u32>::new());
+        assert!(matches!(game.result(), Some(GameResult::Draw)));
+    }
+
+    #[test]
+    fn chess960_castling_is_spotted() {
+        assert!(!chess960_castling(&Board::from_fen(Variant::Standard.start_fen())));
+        assert!(!chess960_castling(&Board::from_fen(Variant::Horde.start_fen())));
+        assert!(chess960_castling(&Board::from_fen(&crate::chess::chess960_fen(0))));
+        //x-fen KQkq with the rooks in the corners but the king on f
+        assert!(chess960_castling(&Board::from_fen("rnbqbkrn/pppppppp/8/8/8/8/PPPPPPPP/RNBQBKRN w Qq - 0 1")));
+    }
+
+    #[test]
+    fn one_sided_matches_have_no_elo() {
+        assert!(elo_estimate(10, 0, 0).is_none());
+        assert!(elo_estimate(0, 0, 10).is_none());
+        let (diff, margin) = elo_estimate(9, 0, 1).unwrap();
+        assert!(diff.is_finite() && margin.is_finite() && diff > 0.0);
+        assert!(elo(1.0).is_finite() && elo(0.0).is_finite());
+    }
+}
@@ -0,0 +1,55 @@
+use crate::chess::pos;
+
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower, //failed high, real score is at least this
+    Upper, //failed low, real score is at most this
+}
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub key: u64,
+    pub depth: i32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option::<pos::Move>,
+}
+
+//one entry per slot hash table of search results, indexed by the low bits of the zobrist key.
+//every slot has its own lock so search threads can share one table without waiting on each other much
+pub struct TranspositionTable {
+    entries: Vec::<Mutex::<Option::<Entry>>>,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let count = (size_mb * 1024 * 1024 / std::mem::size_of::<Mutex::<Option::<Entry>>>())
+            .max(1)
+            .next_power_of_two() / 2;
+        Self {
+            entries: (0..count.max(1)).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Mutex::<Option::<Entry>> {
+        &self.entries[(key as usize) & (self.entries.len() - 1)]
+    }
+
+    pub fn probe(&self, key: u64) -> Option::<Entry> {
+        self.slot(key).lock().unwrap().filter(|e| e.key == key)
+    }
+
+    pub fn store(&self, entry: Entry) {
+        let mut slot = self.slot(entry.key).lock().unwrap();
+        //a shallow bound shouldnt throw away a deeper result for the same position
+        if let Some(old) = &*slot {
+            if old.key == entry.key && old.depth > entry.depth && entry.bound != Bound::Exact {
+                return;
+            }
+        }
+        *slot = Some(entry);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::chess::piece::{Piece, Player};
+use crate::chess::variant::Variant;
+use crate::chess::{board, epd, eval, ordering, pos, validator};
+
+// the names the tuned numbers get in the file written out, the same as in eval.rs
+const VALUES: [&str; 5] = ["PAWN_VALUE", "KNIGHT_VALUE", "BISHOP_VALUE", "ROOK_VALUE", "QUEEN_VALUE"];
+const TABLES: [&str; 7] = [
+    "PAWN_TABLE",
+    "KNIGHT_TABLE",
+    "BISHOP_TABLE",
+    "ROOK_TABLE",
+    "QUEEN_TABLE",
+    "KING_MIDDLE_TABLE",
+    "KING_END_TABLE",
+];
+const PARAMS: usize = VALUES.len() + TABLES.len() * 64;
+// adam's step size, in centipawns
+const LEARNING_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+// quiescence stops looking deeper than this, a few positions have very long capture sequences
+const MAX_CAPTURES: usize = 16;
+
+//a quiet position as the numbers eval multiplies the parameters by, from white's side, and how the game ended
+struct Position {
+    features: Vec::<(usize, f64)>,
+    result: f64,
+}
+
+fn current_params() -> Vec::<f64> {
+    let values = [eval::PAWN_VALUE, eval::KNIGHT_VALUE, eval::BISHOP_VALUE, eval::ROOK_VALUE, eval::QUEEN_VALUE];
+    let tables = [
+        eval::PAWN_TABLE,
+        eval::KNIGHT_TABLE,
+        eval::BISHOP_TABLE,
+        eval::ROOK_TABLE,
+        eval::QUEEN_TABLE,
+        eval::KING_MIDDLE_TABLE,
+        eval::KING_END_TABLE,
+    ];
+    values.iter().chain(tables.iter().flatten()).map(|&v| v as f64).collect()
+}
+
+//the standard eval is a sum of parameters, so a position is just how many times each one counts.
+//the phase is taken from the eval as it is now, the king tables get split between middle and end by it
+fn features(board: &board::Board) -> Vec::<(usize, f64)> {
+    let phase = eval::phase(board) as f64 / 256.0;
+    let mut features = Vec::<(usize, f64)>::new();
+    for player in [Player::White, Player::Black] {
+        let sign = player.sign() as f64;
+        for state in board.get_pieces(player) {
+            let table = |t: usize| VALUES.len() + t * 64 + eval::table_index(player, state.pos);
+            let kind = match state.piece_type {
+                Piece::Pawn(_) => 0,
+                Piece::Knight(_) => 1,
+                Piece::Bishop(_) => 2,
+                Piece::Rook(_) => 3,
+                Piece::Queen(_) => 4,
+                Piece::King(_) => {
+                    features.push((table(5), sign * (1.0 - phase)));
+                    features.push((table(6), sign * phase));
+                    continue;
+                }
+                Piece::Empty => continue,
+            };
+            features.push((kind, sign));
+            features.push((table(kind), sign));
+        }
+    }
+    features
+}
+
+//quiescence search that also gives back the position at the end of its best line, where nothing is left hanging
+fn quiet(board: &board::Board, mut alpha: i32, beta: i32, ply: usize) -> (i32, board::Board) {
+    let stand_pat = eval::evaluate(board);
+    if stand_pat >= beta || ply >= MAX_CAPTURES {
+        return (stand_pat, board.clone());
+    }
+    alpha = alpha.max(stand_pat);
+    let mut best = (stand_pat, board.clone());
+    let mut captures: Vec::<pos::Move> = validator::get_possible_moves(&mut board.clone())
+        .into_iter()
+        .filter(|m| !ordering::is_quiet(m))
+        .collect();
+    captures.sort_by_cached_key(|m| -ordering::mvv_lva(m));
+    for m in &captures {
+        let mut child = board.clone();
+        child.make_move(m);
+        let (score, leaf) = quiet(&child, -beta, -alpha, ply + 1);
+        if -score > alpha {
+            alpha = -score;
+            best = (-score, leaf);
+            if alpha >= beta {
+                break;
+            }
+        }
+    }
+    best
+}
+
+//the result at the end of a line, either as a pgn result, in brackets like [0.5] or after the last | like
+//datagen writes
+fn parse_result(line: &str) -> Option::<f64> {
+    if let Some((_, result)) = line.rsplit_once('|') {
+        return result.trim().parse().ok();
+    }
+    [("1/2-1/2", 0.5), ("1-0", 1.0), ("0-1", 0.0), ("[1.0]", 1.0), ("[0.5]", 0.5), ("[0.0]", 0.0)]
+        .iter()
+        .find(|(text, _)| line.contains(text))
+        .map(|&(_, result)| result)
+}
+
+//standard chess positions with a legal move, not in check and not king and pawn against king,
+//which the bitbase decides instead of the eval
+fn load(text: &str) -> (Vec::<Position>, usize) {
+    let mut positions = Vec::<Position>::new();
+    let mut skipped = 0;
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        let (epd, result) = match (epd::parse_line(line), parse_result(line)) {
+            (Some(epd), Some(result)) => (epd, result),
+            _ => {
+                skipped += 1;
+                continue;
+            }
+        };
+        let mut board = board::Board::from_fen(&epd.fen);
+        let player = board.active_player();
+        let pieces = board.get_pieces(Player::White).len() + board.get_pieces(Player::Black).len();
+        if board.variant() != Variant::Standard
+            || pieces <= 3
+            || board.is_in_check(player)
+            || validator::get_possible_moves(&mut board).is_empty()
+        {
+            skipped += 1;
+            continue;
+        }
+        let (_, leaf) = quiet(&board, -eval::QUEEN_VALUE * 20, eval::QUEEN_VALUE * 20, 0);
+        positions.push(Position { features: features(&leaf), result });
+    }
+    (positions, skipped)
+}
+
+fn evaluate(position: &Position, params: &[f64]) -> f64 {
+    position.features.iter().map(|&(i, c)| params[i] * c).sum()
+}
+
+//the expected score for white with this eval, k stretches centipawns to fit the results
+fn sigmoid(k: f64, eval: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+fn mse(positions: &[Position], params: &[f64], k: f64) -> f64 {
+    positions.iter().map(|p| (p.result - sigmoid(k, evaluate(p, params))).powi(2)).sum::<f64>() / positions.len() as f64
+}
+
+//the k that fits the untuned eval best, the error is a smooth bowl in k so a ternary search finds it
+fn find_k(positions: &[Position], params: &[f64]) -> f64 {
+    let (mut low, mut high) = (0.0, 4.0);
+    for _ in 0..60 {
+        let a = low + (high - low) / 3.0;
+        let b = high - (high - low) / 3.0;
+        if mse(positions, params, a) < mse(positions, params, b) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+    (low + high) / 2.0
+}
+
+fn gradient(positions: &[Position], params: &[f64], k: f64) -> Vec::<f64> {
+    let mut gradient = vec![0.0; PARAMS];
+    for p in positions {
+        let s = sigmoid(k, evaluate(p, params));
+        let d = (s - p.result) * s * (1.0 - s);
+        for &(i, c) in &p.features {
+            gradient[i] += d * c;
+        }
+    }
+    let scale = 2.0 * k * std::f64::consts::LN_10 / 400.0 / positions.len() as f64;
+    gradient.iter().map(|g| g * scale).collect()
+}
+
+//the tuned numbers as rust, laid out like eval.rs so they can be pasted over the old ones
+fn write_constants(params: &[f64], header: &str) -> String {
+    let mut text = format!("// {}\n", header);
+    for (name, value) in VALUES.iter().zip(params) {
+        text += &format!("pub const {}: i32 = {};\n", name, value.round() as i32);
+    }
+    for (t, name) in TABLES.iter().enumerate() {
+        let table: Vec::<String> = params[VALUES.len() + t * 64..][..64].iter().map(|v| (v.round() as i32).to_string()).collect();
+        let width = table.iter().map(|v| v.len()).max().unwrap_or(1);
+        text += &format!("#[rustfmt::skip]\npub const {}: [i32; 64] = [\n", name);
+        for row in table.chunks(8) {
+            let row: Vec::<String> = row.iter().map(|v| format!("{:>width$}", v, width = width)).collect();
+            text += &format!("    {},\n", row.join(","));
+        }
+        text += "];\n";
+    }
+    text
+}
+
+//texel tuning: fits the material and piece square tables to game results with adam,
+//on the quiet positions quiescence leads to from each labelled position
+pub fn run(path: &str, out: &str, epochs: usize) -> Result::<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path, e))?;
+    let (positions, skipped) = load(&text);
+    if positions.is_empty() {
+        return Err(format!("no positions with results in {}", path));
+    }
+    println!("{} positions, {} skipped", positions.len(), skipped);
+    let mut params = current_params();
+    let k = find_k(&positions, &params);
+    let start = mse(&positions, &params, k);
+    println!("k {:.3}, mse {:.6}", k, start);
+
+    let mut m = vec![0.0; PARAMS];
+    let mut v = vec![0.0; PARAMS];
+    for epoch in 1..=epochs {
+        let g = gradient(&positions, &params, k);
+        for i in 0..PARAMS {
+            m[i] = BETA1 * m[i] + (1.0 - BETA1) * g[i];
+            v[i] = BETA2 * v[i] + (1.0 - BETA2) * g[i] * g[i];
+            let m_hat = m[i] / (1.0 - BETA1.powi(epoch as i32));
+            let v_hat = v[i] / (1.0 - BETA2.powi(epoch as i32));
+            params[i] -= LEARNING_RATE * m_hat / (v_hat.sqrt() + 1e-8);
+        }
+        if epoch % 50 == 0 || epoch == epochs {
+            println!("epoch {}: mse {:.6}", epoch, mse(&positions, &params, k));
+        }
+    }
+
+    let end = mse(&positions, &params, k);
+    let header = format!(
+        "tuned from {} positions in {}, mse {:.6} down from {:.6} with k {:.3}",
+        positions.len(),
+        path,
+        end,
+        start,
+        k
+    );
+    std::fs::write(out, write_constants(&params, &header)).map_err(|e| format!("couldn't write {}: {}", out, e))?;
+    println!("wrote {}", out);
+    Ok(())
+}
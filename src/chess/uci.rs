@@ -0,0 +1,318 @@
+use crate::chess::{book, nnue, search, syzygy, tablebase, time, Game};
+use crate::chess::variant::{self, Variant};
+
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const MAX_THREADS: usize = 256;
+const MAX_HASH_MB: usize = 4096;
+const DEFAULT_BOOK_DEPTH: usize = 16;
+
+//opening book settings, the book is only read once OwnBook is on and a file is set
+struct BookOptions {
+    own_book: bool,
+    file: Option::<String>,
+    keys_file: Option::<String>,
+    depth: usize,
+    selection: book::Selection,
+}
+
+impl BookOptions {
+    fn load(&self) -> Result::<Option::<book::Book>, String> {
+        match (&self.file, self.own_book) {
+            (Some(file), true) => {
+                let keys = book::Keys::load(self.keys_file.as_deref())?;
+                book::Book::open(file, keys).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+//uci score, mates are counted in moves not plies
+pub fn format_score(score: i32) -> String {
+    if score >= search::MATE_BOUND {
+        format!("mate {}", (search::MATE - score + 1) / 2)
+    } else if score <= -search::MATE_BOUND {
+        format!("mate -{}", (search::MATE + score) / 2)
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+// position [startpos | fen <fen>] [moves <move>...]
+//also gives back how many moves were played, for the book depth
+//the variant has to be set before the moves are played, a fen with pockets is crazyhouse whatever it says
+fn parse_position(tokens: &[&str], variant: Variant) -> Result::<(Game, usize), String> {
+    let moves_at = tokens.iter().position(|&t| t == "moves").unwrap_or(tokens.len());
+    let mut game = match tokens.first() {
+        Some(&"startpos") => Game::from_fen(variant.start_fen()),
+        Some(&"fen") => Game::from_fen(&tokens[1..moves_at].join(" ")),
+        _ => return Err("expected startpos or fen".to_owned()),
+    };
+    if variant != Variant::Standard {
+        game.set_variant(variant);
+    }
+    let mut played = 0;
+    for m in tokens.iter().skip(moves_at + 1) {
+        game.play_move(m).map_err(|e| format!("{}: {}", m, e))?;
+        played += 1;
+    }
+    Ok((game, played))
+}
+
+// go [wtime <ms>] [btime <ms>] [winc <ms>] [binc <ms>] [movestogo <n>] [movetime <ms>] [depth <n>] [infinite]
+fn parse_go(tokens: &[&str]) -> time::Limits {
+    let mut limits = time::Limits::default();
+    let mut iter = tokens.iter();
+    while let Some(&token) = iter.next() {
+        let mut value = || iter.next().and_then(|v| v.parse::<u64>().ok());
+        match token {
+            "wtime" => limits.wtime = value(),
+            "btime" => limits.btime = value(),
+            "winc" => limits.winc = value(),
+            "binc" => limits.binc = value(),
+            "movestogo" => limits.movestogo = value().map(|v| v as u32),
+            "movetime" => limits.movetime = value(),
+            "depth" => limits.depth = value().map(|v| v as i32),
+            "infinite" => limits.infinite = true,
+            _ => (),
+        }
+    }
+    limits
+}
+
+//searches on another thread so stop can still be read, hands the searcher back when it is done
+fn start_search(
+    mut searcher: search::Searcher,
+    game: &Game,
+    limits: time::Limits,
+    stop: Arc::<AtomicBool>,
+    chess960: bool,
+) -> JoinHandle::<search::Searcher> {
+    tablebase::init_kpk();
+    searcher.set_history(game.history());
+    let board = game.board.clone();
+    stop.store(false, Ordering::Relaxed);
+    std::thread::spawn(move || {
+        let time = time::TimeManager::new(&limits, board.active_player(), stop.clone());
+        let start = std::time::Instant::now();
+        let result = searcher.search_timed(
+            &board,
+            limits.depth.unwrap_or(search::MAX_DEPTH),
+            time,
+            &mut |r| {
+                let ms = start.elapsed().as_millis().max(1);
+                let pv: Vec::<String> = r.pv.iter().map(|m| m.to_uci(chess960)).collect();
+                println!(
+                    "info depth {} score {} nodes {} time {} nps {} tbhits {} pv {}",
+                    r.depth,
+                    format_score(r.score),
+                    r.nodes,
+                    ms,
+                    r.nodes as u128 * 1000 / ms,
+                    r.tb_hits,
+                    pv.join(" ")
+                );
+            },
+        );
+        //a mate or the last depth can end an infinite search early, the bestmove still waits for stop
+        while limits.infinite && !stop.load(Ordering::Relaxed) {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        match result.best_move {
+            Some(m) => println!("bestmove {}", m.to_uci(chess960)),
+            None => println!("bestmove 0000"),
+        }
+        searcher
+    })
+}
+
+// setoption name <name> value <value>
+fn parse_option(
+    tokens: &[&str],
+    options: &mut search::SearchOptions,
+    book_options: &mut BookOptions,
+    chess960: &mut bool,
+    variant: &mut Variant,
+) -> Result::<(), String> {
+    let value_at = tokens.iter().position(|&t| t == "value").ok_or("expected value")?;
+    let name = tokens[1..value_at].join(" ");
+    let value = tokens[value_at + 1..].join(" ");
+    let number = || value.parse::<usize>().map_err(|_| format!("bad value {}", value));
+    match name.to_ascii_lowercase().as_str() {
+        "threads" => options.threads = number()?.clamp(1, MAX_THREADS),
+        "hash" => options.hash_mb = number()?.clamp(1, MAX_HASH_MB),
+        "ownbook" => book_options.own_book = value == "true",
+        "uci_chess960" => *chess960 = value == "true",
+        "uci_variant" => *variant = Variant::from_name(&value).ok_or_else(|| format!("bad value {}", value))?,
+        "bookfile" => book_options.file = (!value.is_empty() && value != "<empty>").then(|| value.clone()),
+        "polyglotkeys" => book_options.keys_file = (!value.is_empty() && value != "<empty>").then(|| value.clone()),
+        "bookdepth" => book_options.depth = number()?,
+        "bookselection" => book_options.selection = match value.to_ascii_lowercase().as_str() {
+            "best" => book::Selection::Best,
+            "weighted" => book::Selection::Weighted,
+            _ => return Err(format!("bad value {}", value)),
+        },
+        "evalfile" if value.is_empty() || value == "<empty>" => nnue::unload(),
+        "evalfile" => nnue::load(&value)?,
+        _ => return Err(format!("unknown option {}", name)),
+    }
+    Ok(())
+}
+
+//setoption name SyzygyPath value <dirs>, some(none) when the path is being cleared
+fn syzygy_path(tokens: &[&str]) -> Option::<Option::<String>> {
+    let value_at = tokens.iter().position(|&t| t == "value")?;
+    if !tokens[1..value_at].join(" ").eq_ignore_ascii_case("syzygypath") {
+        return None;
+    }
+    let value = tokens[value_at + 1..].join(" ");
+    Some((!value.is_empty() && value != "<empty>").then_some(value))
+}
+
+pub fn run() {
+    let mut options = search::SearchOptions::default();
+    let mut tablebases: Option::<Arc::<syzygy::Tablebases>> = None;
+    let new_searcher = |options: search::SearchOptions, tablebases: &Option::<Arc::<syzygy::Tablebases>>| {
+        let mut searcher = search::Searcher::new(options);
+        searcher.set_tablebases(tablebases.clone());
+        searcher
+    };
+    let mut searcher = Some(new_searcher(options, &tablebases));
+    let mut thread: Option::<JoinHandle::<search::Searcher>> = None;
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut game = Game::from_fen(START_FEN);
+    let mut played = 0;
+    let mut book_options = BookOptions {
+        own_book: false,
+        file: None,
+        keys_file: None,
+        depth: DEFAULT_BOOK_DEPTH,
+        selection: book::Selection::Weighted,
+    };
+    let mut opening_book: Option::<book::Book> = None;
+    //only changes how castling is written, chess960 positions come in as x-fen or shredder-fen either way
+    let mut chess960 = false;
+    let mut variant = Variant::Standard;
+
+    //waits for the search thread to finish and takes the searcher back
+    let join = |thread: &mut Option::<JoinHandle::<search::Searcher>>, searcher: &mut Option::<search::Searcher>| {
+        if let Some(t) = thread.take() {
+            *searcher = Some(t.join().expect("search thread panicked"));
+        }
+    };
+
+    for line in std::io::stdin().lock().lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        let tokens: Vec::<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"uci") => {
+                println!("id name chess");
+                println!("id author RealDummy");
+                println!("option name Threads type spin default {} min 1 max {}", options.threads, MAX_THREADS);
+                println!("option name Hash type spin default {} min 1 max {}", options.hash_mb, MAX_HASH_MB);
+                println!("option name OwnBook type check default false");
+                println!("option name BookFile type string default <empty>");
+                println!("option name PolyglotKeys type string default <empty>");
+                println!("option name BookDepth type spin default {} min 0 max 1000", DEFAULT_BOOK_DEPTH);
+                println!("option name BookSelection type combo default Weighted var Weighted var Best");
+                println!("option name SyzygyPath type string default <empty>");
+                println!("option name EvalFile type string default <empty>");
+                println!("option name UCI_Chess960 type check default false");
+                let vars: Vec::<String> = variant::ALL_VARIANTS.iter().map(|v| format!(" var {}", v.name())).collect();
+                println!("option name UCI_Variant type combo default {}{}", Variant::Standard.name(), vars.concat());
+                println!("uciok");
+            }
+            //the bitbase is made before the first search, isready is where guis expect to wait for that
+            Some(&"isready") => {
+                tablebase::init_kpk();
+                println!("readyok");
+            }
+            Some(&"setoption") => {
+                join(&mut thread, &mut searcher);
+                //the tablebases are kept out of parse_option since opening them reads a directory
+                if let Some(path) = syzygy_path(&tokens[1..]) {
+                    tablebases = match path {
+                        Some(path) => match syzygy::Tablebases::open(&path) {
+                            Ok(tb) => {
+                                println!("info string {} tablebases, up to {} pieces", tb.table_count(), tb.max_pieces);
+                                Some(Arc::new(tb))
+                            }
+                            Err(msg) => {
+                                println!("info string {}", msg);
+                                None
+                            }
+                        },
+                        None => None,
+                    };
+                    searcher = Some(new_searcher(options, &tablebases));
+                    continue;
+                }
+                match parse_option(&tokens[1..], &mut options, &mut book_options, &mut chess960, &mut variant) {
+                    Ok(()) => {
+                        searcher = Some(new_searcher(options, &tablebases));
+                        //a new EvalFile has to reach the position that is already set up too
+                        game.board.refresh_accumulator();
+                    }
+                    Err(msg) => println!("info string {}", msg),
+                }
+                match book_options.load() {
+                    Ok(b) => opening_book = b,
+                    Err(msg) => {
+                        println!("info string {}", msg);
+                        opening_book = None;
+                    }
+                }
+            }
+            Some(&"ucinewgame") => {
+                join(&mut thread, &mut searcher);
+                searcher = Some(new_searcher(options, &tablebases));
+            }
+            Some(&"position") => {
+                join(&mut thread, &mut searcher);
+                match parse_position(&tokens[1..], variant) {
+                    Ok((g, p)) => {
+                        game = g;
+                        played = p;
+                    }
+                    Err(msg) => println!("info string bad position, {}", msg),
+                }
+            }
+            Some(&"go") => {
+                join(&mut thread, &mut searcher);
+                let limits = parse_go(&tokens[1..]);
+                //infinite searches have to wait for stop, so they never come from the book
+                let book_move = opening_book
+                    .as_ref()
+                    .filter(|_| played < book_options.depth && !limits.infinite)
+                    .and_then(|b| b.pick(&game.board, book_options.selection));
+                if let Some(m) = book_move {
+                    println!("info string book move");
+                    println!("bestmove {}", m.to_uci(chess960));
+                    continue;
+                }
+                let s = searcher.take().expect("searcher missing");
+                thread = Some(start_search(s, &game, limits, stop.clone(), chess960));
+            }
+            Some(&"stop") => {
+                stop.store(true, Ordering::Relaxed);
+                join(&mut thread, &mut searcher);
+            }
+            Some(&"quit") => {
+                stop.store(true, Ordering::Relaxed);
+                join(&mut thread, &mut searcher);
+                break;
+            }
+            _ => (),
+        }
+    }
+    stop.store(true, Ordering::Relaxed);
+    join(&mut thread, &mut searcher);
+}
@@ -1,36 +1,36 @@
 use crate::chess::{board, piece, pos};
-use std::vec::Vec;
+use crate::chess::variant::Variant;
 
-const PAWN_START_MOVES: &'static [(i8,i8)] = &[(0,1),(0,2),(1,1),(-1,1),];
+const PAWN_START_MOVES: &[(i8,i8)] = &[(0,1),(0,2),(1,1),(-1,1),];
 
-const PAWN_MOVES: &'static [(i8,i8)] = &[(0,1),(1,1),(-1,1),];
+const PAWN_MOVES: &[(i8,i8)] = &[(0,1),(1,1),(-1,1),];
 
-const PAWN_PROMOTION_MOVES: &'static [(i8,i8)] = &[
+const PAWN_PROMOTION_MOVES: &[(i8,i8)] = &[
     (0,1),(0,1),(0,1),(0,1),
     (1,1),(1,1),(1,1),(1,1),
     (-1,1),(-1,1),(-1,1),(-1,1),
 ];
 
-const KNIGHT_MOVES: &'static [(i8,i8)] = &[
+const KNIGHT_MOVES: &[(i8,i8)] = &[
     ( 1,  2), ( 2,  1),
     ( 2, -1), ( 1, -2),
     (-1, -2), (-2, -1), 
     (-2,  1), (-1,  2)
 ];
-const BISHOP_MOVES: &'static [(i8,i8)] = &[
+const BISHOP_MOVES: &[(i8,i8)] = &[
     ( 1,   1),( 2,   2),( 3,   3),( 4,   4),( 5,   5),( 6,   6),( 7,   7),
     (-1,   1),(-2,   2),(-3,   3),(-4,   4),(-5,   5),(-6,   6),(-7,   7),
     ( 1,  -1),( 2,  -2),( 3,  -3),( 4,  -4),( 5,  -5),( 6,  -6),( 7,  -7),
     (-1,  -1),(-2,  -2),(-3,  -3),(-4,  -4),(-5,  -5),(-6,  -6),(-7,  -7),
 ];
-const ROOK_MOVES: &'static [(i8,i8)] = &[
+const ROOK_MOVES: &[(i8,i8)] = &[
     ( 0,  1), ( 0,  2), ( 0,  3), ( 0,  4), ( 0,  5), ( 0,  6), ( 0,  7),
     ( 0, -1), ( 0, -2), ( 0, -3), ( 0, -4), ( 0, -5), ( 0, -6), ( 0, -7),
     ( 1,  0), ( 2,  0), ( 3,  0), ( 4,  0), ( 5,  0), ( 6,  0), ( 7,  0),
     (-1,  0), (-2,  0), (-3,  0), (-4,  0), (-5,  0), (-6,  0), (-7,  0),
     
 ];
-const QUEEN_MOVES: &'static [(i8,i8)] = &[
+const QUEEN_MOVES: &[(i8,i8)] = &[
     ( 1,  1), ( 2,  2), ( 3,  3), ( 4,  4), ( 5,  5), ( 6,  6), ( 7,  7),
     (-1,  1), (-2,  2), (-3,  3), (-4,  4), (-5,  5), (-6,  6), (-7,  7),
     ( 1, -1), ( 2, -2), ( 3, -3), ( 4, -4), ( 5, -5), ( 6, -6), ( 7, -7),
@@ -40,11 +40,10 @@ const QUEEN_MOVES: &'static [(i8,i8)] = &[
     ( 1,  0), ( 2,  0), ( 3,  0), ( 4,  0), ( 5,  0), ( 6,  0), ( 7,  0),
     (-1,  0), (-2,  0), (-3,  0), (-4,  0), (-5,  0), (-6,  0), (-7,  0),
 ];
-const KING_MOVES: &'static [(i8,i8)] = &[
+const KING_MOVES: &[(i8,i8)] = &[
     (-1,  1), ( 0,  1), ( 1,  1),  
     (-1,  0),           ( 1,  0),
     ( 1, -1), ( 0, -1), (-1, -1),
-    ( 2,  0), (-2,  0),
 ];
 
 fn player_sign(piece: piece::Piece) -> i8 {
@@ -60,8 +59,8 @@ fn on_board(p: &pos::Square) -> bool {
 
 fn move_diff_sign(dy: i8, dx: i8) -> pos::Square {
     pos::Square{
-        rank: dy.signum() as i8, 
-        file: dx.signum() as i8,
+        rank: dy.signum(), 
+        file: dx.signum(),
     }
 }
 
@@ -110,7 +109,7 @@ pub fn legal_move(
                         }
                     }
                     else if (old_pos.rank - new_pos.rank).abs() == 2 {
-                        if let Some(_) = board.get(old_pos + pos::Square{rank: 1 * player_sign(piece_type), file: 0}).owner() {
+                        if board.get(old_pos + pos::Square{rank: player_sign(piece_type), file: 0}).owner().is_some() {
                             return None;
                         }
                         else {
@@ -146,8 +145,16 @@ pub fn legal_move(
                         return None;
                     }
                     else{
+                        let move_type = if new_pos.rank == 8 || new_pos.rank == 1 {
+                            pos::MoveType::CapturePromotion(enemy_piece_type, match promote_to {
+                                Some(p) => p,
+                                None => piece::Piece::Queen(board.active_player()),
+                            })
+                        } else {
+                            pos::MoveType::Capture(enemy_piece_type)
+                        };
                         Some(pos::Move{
-                            move_type: pos::MoveType::Capture(enemy_piece_type),
+                            move_type,
                             old_pos,
                             new_pos,
                             piece: piece_type,
@@ -177,7 +184,7 @@ pub fn legal_move(
             let dir = get_move_dir(old_pos, new_pos);
             let mut sliding_square = old_pos + dir;
             while sliding_square != new_pos {
-                if let Some(_) = board.get(sliding_square).owner(){
+                if board.get(sliding_square).owner().is_some(){
                     return None;
                 }
                 sliding_square += dir;
@@ -199,109 +206,46 @@ pub fn legal_move(
             }
         },
         King(pc) => {
-             //first check if new_pos is under attack!
-             if board.any_piece_attacking(pc.invert(), new_pos) {
+             //first check if new_pos is under attack! unless check is being left for later
+             if check_tuple.0.is_some() && board.any_piece_attacking(pc.invert(), new_pos) {
                 return None;
             }
-            //check if rook/king has moved
-            match (new_pos - old_pos).file {
-                2|-2 => { //king castles
-                    //no castleing in check
-                    if let (Some(_), _) = check_tuple {
-                        if check_tuple.1.len() > 0 {
-                            return None;
-                        }
-                    }
-                    let dir = get_move_dir(old_pos, new_pos);
-                    
-                    //find where rook is
-                    let rook_pos = match dir {
-                        pos::Square{rank:_, file: 1} => {
-                            pos::Square{
-                                rank: old_pos.rank,
-                                file: 8,
-                            }
-                        },
-                        pos::Square{rank:_, file: -1} => {
-                            pos::Square{
-                                rank: old_pos.rank,
-                                file: 1,
-                            }
-                        },
-                        _ => {
-                            println!("how did we get here");
-                            return None;
-                        }
-                    };
-                    //make sure castleing rights are preserved
-                    if !board.has_castle_rights(
-                        board.active_player(),
-                        rook_pos
-                    ) {
-                        return None;
-                    }
-                    
-                    //iterate over squares between king and king's target 
-                    //to make sure it doesnt travel through check
-                    let mut sliding_square = old_pos + dir;
-                    while sliding_square != new_pos {
-                        if let Some(_) = board.get(sliding_square).owner() {
-                            return None;
-                        }
-                        if board.any_piece_attacking(pc.invert(), sliding_square) {
-                            return None;
-                        }
-                        sliding_square += dir;
-                    }
-
-                    //make sure rook is where it should be(probably not required)
-                    if board.get(rook_pos) != Rook(pc) {
-                        return None;
-                    }
-                    //make sure castling side is empty
-                    while sliding_square != rook_pos {
-                        if let Some(_) = board.get(sliding_square).owner() {
-                            return None
-                        }
-                        sliding_square += dir;
-                    }
+            //castling is left to castle_moves
+            let enemy_piece_type = board.get(new_pos);
+            //move already known to not move into check
+            match enemy_piece_type.owner() {
+                None => Some(pos::Move {
+                    move_type: pos::MoveType::Move,
+                    old_pos,
+                    new_pos,
+                    piece: piece_type,
+                }),
+                Some(_) => {
                     Some(pos::Move{
-                        move_type: pos::MoveType::Castle(rook_pos),
+                        move_type: pos::MoveType::Capture(enemy_piece_type),
                         old_pos,
                         new_pos,
                         piece: piece_type,
                     })
                 }
-                _ => { //any other king move AKA not castleing
-                    let enemy_piece_type = board.get(new_pos);
-                    //move already known to not move into check
-                    match enemy_piece_type.owner() {
-                        None => Some(pos::Move {
-                            move_type: pos::MoveType::Move,
-                            old_pos,
-                            new_pos,
-                            piece: piece_type,
-                        }),
-                        Some(_) => {
-                            Some(pos::Move{
-                                move_type: pos::MoveType::Capture(enemy_piece_type),
-                                old_pos,
-                                new_pos,
-                                piece: piece_type,
-                            })
-                        }
-                    }
-                }
             }
         },
         Empty => {return None;}
     }.unwrap();
 
     if let (Some(king_pos), check_vec) = check_tuple {
-        if check_vec.len() == 0 || *king_pos == potential_move.old_pos {
+        if check_vec.is_empty() || *king_pos == potential_move.old_pos {
             return Some(potential_move);
         }
+        //en passant takes a pawn that isnt on the square it moves to
+        let captured_square = match potential_move.move_type {
+            pos::MoveType::EnPassant => potential_move.new_pos - pos::Square{rank: player_sign(piece_type), file: 0},
+            _ => potential_move.new_pos,
+        };
         for &check_square in check_vec {
+            if captured_square == check_square {
+                continue;
+            }
             let mut sliding_square = check_square;
             use piece::Piece::*;
             match board.get(check_square) {
@@ -336,23 +280,137 @@ pub fn legal_move(
     Some(potential_move)
 }
 
+//castling to either side. the king always ends on the c or g file and the rook next to it on d or f,
+//wherever they started, so this works for chess960 as well as normal chess
+fn castle_moves(board: &mut board::Board, check_tuple: &(Option::<pos::Square>, std::vec::Vec::<pos::Square>)) -> std::vec::Vec::<pos::Move> {
+    let king_pos = match check_tuple {
+        (Some(king_pos), check_vec) if check_vec.is_empty() => *king_pos,
+        _ => return vec![],
+    };
+    let player = board.active_player();
+    let mut moves = vec![];
+    for kingside in [true, false] {
+        let rook_pos = match board.castle_rook(player, kingside) {
+            Some(r) if r.rank == king_pos.rank && board.get(r) == piece::Piece::Rook(player) => r,
+            _ => continue,
+        };
+        let king_to = pos::Square { rank: king_pos.rank, file: if kingside { 7 } else { 3 } };
+        let rook_to = pos::Square { rank: king_pos.rank, file: if kingside { 6 } else { 4 } };
+        //everything either piece crosses or lands on has to be empty, apart from the two of them
+        let files = [king_pos.file, rook_pos.file, king_to.file, rook_to.file];
+        let (low, high) = (*files.iter().min().unwrap(), *files.iter().max().unwrap());
+        let blocked = (low..=high).any(|file| {
+            file != king_pos.file && file != rook_pos.file
+                && board.get(pos::Square { rank: king_pos.rank, file }).owner().is_some()
+        });
+        if blocked || !board.castle_path_safe(king_pos, king_to, rook_pos) {
+            continue;
+        }
+        moves.push(pos::Move {
+            move_type: pos::MoveType::Castle(rook_pos),
+            old_pos: king_pos,
+            new_pos: king_to,
+            piece: piece::Piece::King(player),
+        });
+    }
+    moves
+}
+
+//crazyhouse drops, anything in the pocket onto any empty square but pawns not on the first or last rank.
+//in check a drop has to land between the king and the checking slider, and double check can't be blocked
+fn drop_moves(board: &board::Board, check_tuple: &(Option::<pos::Square>, std::vec::Vec::<pos::Square>)) -> std::vec::Vec::<pos::Move> {
+    let player = board.active_player();
+    let pocket = *board.get_pocket(player);
+    if pocket.iter().all(|&n| n == 0) {
+        return vec![];
+    }
+    let blocking_squares = match check_tuple {
+        (Some(king_pos), check_vec) if !check_vec.is_empty() => {
+            if check_vec.len() > 1 {
+                return vec![];
+            }
+            let dir = get_move_dir(check_vec[0], *king_pos);
+            let mut squares = vec![];
+            let mut square = check_vec[0] + dir;
+            //knights and pawns never have anything in between
+            if !matches!(board.get(check_vec[0]), piece::Piece::Knight(_) | piece::Piece::Pawn(_)) {
+                while square != *king_pos {
+                    squares.push(square);
+                    square += dir;
+                }
+            }
+            Some(squares)
+        }
+        _ => None,
+    };
+    let mut moves = vec![];
+    for rank in 1..=8 {
+        for file in 1..=8 {
+            let square = pos::Square { rank, file };
+            if board.get(square) != piece::Piece::Empty
+                || blocking_squares.as_ref().is_some_and(|b| !b.contains(&square)) {
+                continue;
+            }
+            for (count, kind) in pocket.iter().zip(board::POCKET_PIECES) {
+                let piece = kind(player);
+                if *count == 0 || (matches!(piece, piece::Piece::Pawn(_)) && (rank == 1 || rank == 8)) {
+                    continue;
+                }
+                moves.push(pos::Move {
+                    move_type: pos::MoveType::Drop,
+                    old_pos: square,
+                    new_pos: square,
+                    piece,
+                });
+            }
+        }
+    }
+    moves
+}
+
 pub fn get_possible_moves(board: &mut board::Board) -> std::vec::Vec::<pos::Move> {
+    //the game is already over
+    if board.variant().game_over(board).is_some() {
+        return Vec::new();
+    }
+    match board.variant() {
+        Variant::Atomic => return atomic_moves(board),
+        Variant::Antichess => return antichess_moves(board),
+        _ => (),
+    }
+    let check_tuple = board.in_check(board.active_player());
+    let mut moves = piece_moves(board, &check_tuple);
+    moves.extend(castle_moves(board, &check_tuple));
+    if board.variant() == Variant::Crazyhouse {
+        moves.extend(drop_moves(board, &check_tuple));
+    }
+    if !board.variant().checks_allowed() {
+        moves.retain(|m| {
+            let mut child = board.clone();
+            child.make_move(m);
+            !child.is_in_check(child.active_player())
+        });
+    }
+    moves
+}
+
+//every move of the pieces on the board, apart from castling. a check tuple without a king skips
+//everything to do with check, for variants that work out legality afterwards
+fn piece_moves(board: &mut board::Board, check_tuple: &(Option::<pos::Square>, std::vec::Vec::<pos::Square>)) -> std::vec::Vec::<pos::Move> {
     use piece::Piece::*;
     let mut last_pawn_promotion: Option::<piece::Piece> = None; //invalid state that gets turned to none later
-    let check_tuple = board.in_check(board.active_player());
+    let variant = board.variant();
     board.get_pieces(board.active_player()).clone().iter()
-        .map(|piece|{match piece.piece_type {
+        .flat_map(|piece|{match piece.piece_type {
             Empty     => &[],
-            Pawn(_)   => match piece.piece_type.owner().unwrap() {
-                piece::Player::Black => match piece.pos.rank {
-                    2 => PAWN_PROMOTION_MOVES,
-                    7 => PAWN_START_MOVES,
-                    _ => PAWN_MOVES,
-                }
-                piece::Player::White => match piece.pos.rank {
-                    7 => PAWN_PROMOTION_MOVES,
-                    2 => PAWN_START_MOVES,
-                    _ => PAWN_MOVES,
+            Pawn(pc)  => {
+                let last_step = if pc == piece::Player::White { 7 } else { 2 };
+                if piece.pos.rank == last_step {
+                    PAWN_PROMOTION_MOVES
+                } else if variant.pawn_double_steps(pc, piece.pos.rank) {
+                    PAWN_START_MOVES
+                } else {
+                    PAWN_MOVES
                 }
             },
             Knight(_) => KNIGHT_MOVES,
@@ -369,7 +427,7 @@ pub fn get_possible_moves(board: &mut board::Board) -> std::vec::Vec::<pos::Move
             on_board(pos)
         })
         .map(|pos| {(piece.clone(), pos)}) //kind of a stupid clone here
-    }).flatten()
+    })
     .filter_map(|(piece, new_pos)| {
         last_pawn_promotion = if let Pawn(_) = piece.piece_type {
             match last_pawn_promotion {
@@ -389,7 +447,7 @@ pub fn get_possible_moves(board: &mut board::Board) -> std::vec::Vec::<pos::Move
             new_pos,
             promote_to: last_pawn_promotion,
         };
-        match legal_move(board, move_candidate, &check_tuple) {
+        match legal_move(board, move_candidate, check_tuple) {
             Some(m) => {
                 match check_tuple.0 {
                     Some(kp) => board.king_safe_after_move(kp, m),
@@ -399,7 +457,50 @@ pub fn get_possible_moves(board: &mut board::Board) -> std::vec::Vec::<pos::Move
             None => None,
         }
     })
-    .collect()
+    .collect::<std::vec::Vec::<pos::Move>>()
+}
+
+//antichess has no check and no castling, pawns can become kings too, and if anything can be taken something has to be
+fn antichess_moves(board: &mut board::Board) -> std::vec::Vec::<pos::Move> {
+    use pos::MoveType::*;
+    let mut moves = piece_moves(board, &(None, vec![]));
+    let to_king: Vec::<pos::Move> = moves.iter()
+        .filter_map(|m| match m.move_type {
+            Promotion(piece::Piece::Queen(p)) => Some(pos::Move { move_type: Promotion(piece::Piece::King(p)), ..*m }),
+            CapturePromotion(c, piece::Piece::Queen(p)) => Some(pos::Move { move_type: CapturePromotion(c, piece::Piece::King(p)), ..*m }),
+            _ => None,
+        })
+        .collect();
+    moves.extend(to_king);
+    if moves.iter().any(|m| m.is_capture()) {
+        moves.retain(|m| m.is_capture());
+    }
+    moves
+}
+
+//atomic legality has too many special cases to work out up front (a capture that blows up the enemy king
+//is fine even in check, kings touching cant check each other) so every move gets tried on a copy
+fn atomic_moves(board: &mut board::Board) -> std::vec::Vec::<pos::Move> {
+    let player = board.active_player();
+    let king_pos = match board.get_pieces(player).iter().find(|s| s.piece_type == piece::Piece::King(player)) {
+        Some(king) => king.pos,
+        None => return vec![],
+    };
+    let mut moves = piece_moves(board, &(None, vec![]));
+    if !board.is_in_check(player) {
+        moves.extend(castle_moves(board, &(Some(king_pos), vec![])));
+    }
+    moves.retain(|m| {
+        //a king taking something would blow itself up
+        if matches!(m.piece, piece::Piece::King(_)) && m.is_capture() {
+            return false;
+        }
+        let mut child = board.clone();
+        child.make_move(m);
+        let has_king = |p: piece::Player| child.get_pieces(p).iter().any(|s| s.piece_type == piece::Piece::King(p));
+        has_king(player) && (!has_king(player.invert()) || !child.is_in_check(player))
+    });
+    moves
 }
 
 /*pub fn get_possible_moves_from_square(
@@ -0,0 +1,185 @@
+use crate::chess::board::Board;
+use crate::chess::piece::{Piece, Player};
+use crate::chess::GameResult;
+
+// the rules a game is played by. the board carries one around, and move generation and make_move
+// check it wherever the rules stop being normal chess
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Variant {
+    Standard,
+    // captured pieces go into the capturer's pocket and can be dropped back onto the board as a move
+    Crazyhouse,
+    // checking the other king a third time wins
+    ThreeCheck,
+    // getting your king to one of the four middle squares wins
+    KingOfTheHill,
+    // captures explode, taking out everything but pawns around them. blowing up the enemy king wins
+    Atomic,
+    // losing chess. taking is compulsory, the king is just another piece, and running out of pieces or moves wins
+    Antichess,
+    // white has no king, just 36 pawns that can double step from the first rank too. black wins by taking them all
+    Horde,
+    // no checks allowed, first king to the eighth rank wins. black moves second so gets a move to catch up
+    RacingKings,
+}
+
+pub const ALL_VARIANTS: &[Variant] = &[
+    Variant::Standard,
+    Variant::Crazyhouse,
+    Variant::ThreeCheck,
+    Variant::KingOfTheHill,
+    Variant::Atomic,
+    Variant::Antichess,
+    Variant::Horde,
+    Variant::RacingKings,
+];
+
+pub const CHECKS_TO_WIN: u8 = 3;
+
+//how a game was won, mates count in every variant
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Win {
+    Checkmate,
+    ThirdCheck,
+    KingOfTheHill,
+    Explosion,
+    OutOfMoves,
+    HordeTaken,
+    Race,
+    // the other player made a move that isn't legal, only engines do that
+    IllegalMove,
+    // the other player ran out of time
+    Timeout,
+}
+
+impl std::fmt::Display for Win {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Self::Checkmate => "Checkmate",
+            Self::ThirdCheck => "Third check",
+            Self::KingOfTheHill => "King of the hill",
+            Self::Explosion => "King blown up",
+            Self::OutOfMoves => "Out of moves",
+            Self::HordeTaken => "Horde taken",
+            Self::Race => "King reached the eighth rank",
+            Self::IllegalMove => "Illegal move",
+            Self::Timeout => "Time out",
+        })
+    }
+}
+
+impl Variant {
+    // names like uci guis and lichess use them
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Crazyhouse => "crazyhouse",
+            Self::ThreeCheck => "3check",
+            Self::KingOfTheHill => "kingofthehill",
+            Self::Atomic => "atomic",
+            Self::Antichess => "antichess",
+            Self::Horde => "horde",
+            Self::RacingKings => "racingkings",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option::<Self> {
+        let name = name.to_ascii_lowercase().replace(['-', '_'], "");
+        let alias = match name.as_str() {
+            "chess" => Self::Standard,
+            "threecheck" => Self::ThreeCheck,
+            "koth" => Self::KingOfTheHill,
+            "giveaway" | "losing" => Self::Antichess,
+            _ => return ALL_VARIANTS.iter().copied().find(|v| v.name() == name),
+        };
+        Some(alias)
+    }
+
+    //whether the syzygy and built in endgame tables mean anything here
+    pub fn uses_tablebases(self) -> bool {
+        self == Self::Standard
+    }
+
+    //the start position, for the variants that have their own
+    pub fn start_fen(self) -> &'static str {
+        match self {
+            Self::Horde => "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1",
+            Self::RacingKings => "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+            Self::Antichess => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            _ => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        }
+    }
+
+    //whether a pawn on this rank can move two squares
+    pub fn pawn_double_steps(self, player: Player, rank: i8) -> bool {
+        let rank = if player == Player::White { rank } else { 9 - rank };
+        rank == 2 || (self == Self::Horde && rank == 1)
+    }
+
+    //whether a move is allowed to give check at all
+    pub fn checks_allowed(self) -> bool {
+        self != Self::RacingKings
+    }
+
+    //a game that has already ended without anyone running out of moves, so the side to move gets none
+    pub fn game_over(self, board: &Board) -> Option::<GameResult> {
+        let mover = board.active_player().invert();
+        let king = |p: Player| board.get_pieces(p).iter().find(|s| s.piece_type == Piece::King(p)).map(|s| s.pos);
+        let win = match self {
+            Self::ThreeCheck => (board.checks_given(mover) >= CHECKS_TO_WIN).then_some((mover, Win::ThirdCheck)),
+            Self::KingOfTheHill => king(mover)
+                .filter(|k| (4..=5).contains(&k.rank) && (4..=5).contains(&k.file))
+                .map(|_| (mover, Win::KingOfTheHill)),
+            Self::Atomic => king(mover.invert()).is_none().then_some((mover, Win::Explosion)),
+            Self::Horde => board.get_pieces(Player::White).is_empty().then_some((Player::Black, Win::HordeTaken)),
+            Self::RacingKings => {
+                let home = |p: Player| king(p).is_some_and(|k| k.rank == 8);
+                match (home(Player::White), home(Player::Black)) {
+                    (true, true) => return Some(GameResult::Draw),
+                    (false, true) => Some((Player::Black, Win::Race)),
+                    //black still gets a move to draw by getting there too, if there's a safe square to step to
+                    (true, false) => {
+                        let catch_up = board.active_player() == Player::Black && king(Player::Black).is_some_and(|k| {
+                            (k.file - 1..=k.file + 1).any(|file| {
+                                let square = crate::chess::pos::Square { rank: 8, file };
+                                k.rank == 7 && (1..=8).contains(&file)
+                                    && board.get(square).owner() != Some(Player::Black)
+                                    && !board.any_piece_attacking(Player::White, square)
+                            })
+                        });
+                        (!catch_up).then_some((Player::White, Win::Race))
+                    }
+                    (false, false) => None,
+                }
+            }
+            _ => None,
+        };
+        win.map(|(winner, win)| GameResult::Win(winner, win))
+    }
+
+    //how it ended when the side to move has no moves
+    pub fn out_of_moves(self, board: &Board) -> GameResult {
+        let player = board.active_player();
+        match self {
+            Self::Antichess => GameResult::Win(player, Win::OutOfMoves),
+            _ if board.is_in_check(player) => GameResult::Win(player.invert(), Win::Checkmate),
+            _ => GameResult::Draw,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_aliases() {
+        for &v in ALL_VARIANTS {
+            assert_eq!(Variant::from_name(v.name()), Some(v));
+        }
+        assert_eq!(Variant::from_name("giveaway"), Some(Variant::Antichess));
+        assert_eq!(Variant::from_name("King-of-the-Hill"), Some(Variant::KingOfTheHill));
+        //losers keeps a royal king and checks, it isnt antichess
+        assert_eq!(Variant::from_name("losers"), None);
+    }
+}
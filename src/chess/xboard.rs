@@ -0,0 +1,369 @@
+use crate::chess::{search, tablebase, time, Game, GameResult, Player};
+use crate::chess::variant::{self, Variant};
+
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+const MAX_THREADS: usize = 256;
+const MAX_HASH_MB: usize = 4096;
+// how cecp writes mates in thinking output, this plus the moves to mate
+const MATE_SCORE: i32 = 100000;
+// a move's thinking time when no level, st or sd was given
+const DEFAULT_MOVE_TIME: u64 = 5000;
+
+//the names xboard knows the variants by, where they differ from ours
+fn xboard_name(variant: Variant) -> &'static str {
+    match variant {
+        Variant::Standard => "normal",
+        Variant::Antichess => "giveaway",
+        _ => variant.name(),
+    }
+}
+
+fn format_score(score: i32) -> i32 {
+    if score >= search::MATE_BOUND {
+        MATE_SCORE + (search::MATE - score + 1) / 2
+    } else if score <= -search::MATE_BOUND {
+        -MATE_SCORE - (search::MATE + score) / 2
+    } else {
+        score
+    }
+}
+
+//the result line an engine sends when a game ends, like 1-0 {Checkmate}
+fn result_line(result: GameResult) -> String {
+    match result {
+        GameResult::Win(Player::White, win) => format!("1-0 {{{}}}", win),
+        GameResult::Win(Player::Black, win) => format!("0-1 {{{}}}", win),
+        GameResult::Draw => "1/2-1/2 {Draw}".to_owned(),
+    }
+}
+
+// level <moves per period> <minutes or minutes:seconds> <increment seconds>, 0 moves means the whole game
+fn parse_level(tokens: &[&str]) -> Option::<(u32, u64, u64)> {
+    let moves = tokens.first()?.parse::<u32>().ok()?;
+    let base = match tokens.get(1)?.split_once(':') {
+        Some((m, s)) => m.parse::<u64>().ok()? * 60_000 + s.parse::<u64>().ok()? * 1000,
+        None => tokens[1].parse::<u64>().ok()? * 60_000,
+    };
+    let inc = (tokens.get(2)?.parse::<f64>().ok()? * 1000.0) as u64;
+    Some((moves, base, inc))
+}
+
+//what level, st, sd, time and otim have set. the clocks are in milliseconds
+#[derive(Clone, Copy, Default)]
+struct TimeSettings {
+    moves_per_period: u32,
+    base: u64,
+    inc: u64,
+    move_time: Option::<u64>,
+    depth: Option::<i32>,
+    own_clock: Option::<u64>,
+    opponent_clock: Option::<u64>,
+}
+
+impl TimeSettings {
+    //moves_made is how many moves the engine has made so far this game
+    fn limits(&self, player: Player, moves_made: u32) -> time::Limits {
+        let mut limits = time::Limits { depth: self.depth, ..time::Limits::default() };
+        if let Some(st) = self.move_time {
+            limits.movetime = Some(st);
+            return limits;
+        }
+        //without a level there is no clock at all, just sd or a few seconds a move
+        if self.base == 0 && self.own_clock.is_none() {
+            if self.depth.is_none() {
+                limits.movetime = Some(DEFAULT_MOVE_TIME);
+            }
+            return limits;
+        }
+        let own = self.own_clock.unwrap_or(self.base);
+        let opponent = self.opponent_clock.unwrap_or(self.base);
+        let (wtime, btime) = match player {
+            Player::White => (own, opponent),
+            Player::Black => (opponent, own),
+        };
+        limits.wtime = Some(wtime);
+        limits.btime = Some(btime);
+        limits.winc = Some(self.inc);
+        limits.binc = Some(self.inc);
+        if self.moves_per_period > 0 {
+            limits.movestogo = Some(self.moves_per_period - moves_made % self.moves_per_period);
+        }
+        limits
+    }
+}
+
+//thinks on another thread so ? can stop it. makes its move on a copy of the game and hands that back
+//along with the searcher, or none if there was nothing to play
+fn start_search(
+    mut searcher: search::Searcher,
+    game: &Game,
+    limits: time::Limits,
+    stop: Arc::<AtomicBool>,
+    post: bool,
+) -> JoinHandle::<(search::Searcher, Option::<Game>)> {
+    tablebase::init_kpk();
+    searcher.set_history(game.history());
+    let mut game = game.clone();
+    stop.store(false, Ordering::Relaxed);
+    std::thread::spawn(move || {
+        let time = time::TimeManager::new(&limits, game.board.active_player(), stop);
+        let start = std::time::Instant::now();
+        let result = searcher.search_timed(
+            &game.board,
+            limits.depth.unwrap_or(search::MAX_DEPTH),
+            time,
+            &mut |r| {
+                if post {
+                    let pv: Vec::<String> = r.pv.iter().map(|m| m.to_string()).collect();
+                    println!(
+                        "{} {} {} {} {}",
+                        r.depth,
+                        format_score(r.score),
+                        start.elapsed().as_millis() / 10,
+                        r.nodes,
+                        pv.join(" ")
+                    );
+                }
+            },
+        );
+        let m = match result.best_move {
+            Some(m) => m,
+            None => return (searcher, None),
+        };
+        println!("move {}", m);
+        game.record_move(&m, None);
+        if let Some(result) = game.result() {
+            println!("{}", result_line(result));
+        }
+        (searcher, Some(game))
+    })
+}
+
+//the game and everything about it that xboard can change
+struct Session {
+    game: Game,
+    // the positions before each move, for undo and remove
+    history: Vec::<Game>,
+    variant: Variant,
+    // the side the engine plays, none in force mode
+    engine: Option::<Player>,
+    post: bool,
+    time: TimeSettings,
+    options: search::SearchOptions,
+    searcher: Option::<search::Searcher>,
+    thread: Option::<JoinHandle::<(search::Searcher, Option::<Game>)>>,
+    stop: Arc::<AtomicBool>,
+}
+
+impl Session {
+    fn new_game(variant: Variant) -> Game {
+        let mut game = Game::from_fen(variant.start_fen());
+        if variant != Variant::Standard {
+            game.set_variant(variant);
+        }
+        game
+    }
+
+    //waits for the engine to finish thinking, and takes its move if it made one
+    fn join(&mut self) {
+        if let Some(t) = self.thread.take() {
+            let (searcher, game) = t.join().expect("search thread panicked");
+            self.searcher = Some(searcher);
+            if let Some(game) = game {
+                self.history.push(std::mem::replace(&mut self.game, game));
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.join();
+    }
+
+    //starts thinking if it's the engine's turn and the game isn't over
+    fn think(&mut self) {
+        let player = self.game.board.active_player();
+        if self.engine != Some(player) || self.game.result().is_some() {
+            return;
+        }
+        let moves_made = self.history.len() as u32 / 2;
+        let limits = self.time.limits(player, moves_made);
+        let searcher = self.searcher.take().expect("searcher missing");
+        self.thread = Some(start_search(searcher, &self.game, limits, self.stop.clone(), self.post));
+    }
+
+    fn user_move(&mut self, input: &str) {
+        self.join();
+        let before = self.game.clone();
+        match self.game.play_move(input) {
+            Ok(()) => {
+                self.history.push(before);
+                if let Some(result) = self.game.result() {
+                    println!("{}", result_line(result));
+                }
+                self.think();
+            }
+            Err(_) => println!("Illegal move: {}", input),
+        }
+    }
+
+    //takes back this many moves, the engine has to be in force mode for xboard to send these
+    fn take_back(&mut self, moves: usize) {
+        self.stop();
+        for _ in 0..moves {
+            if let Some(game) = self.history.pop() {
+                self.game = game;
+            }
+        }
+    }
+
+    fn reset_searcher(&mut self) {
+        self.stop();
+        self.searcher = Some(search::Searcher::new(self.options));
+    }
+}
+
+pub fn run() {
+    let options = search::SearchOptions::default();
+    let mut session = Session {
+        game: Session::new_game(Variant::Standard),
+        history: vec![],
+        variant: Variant::Standard,
+        engine: Some(Player::Black),
+        post: false,
+        time: TimeSettings::default(),
+        options,
+        searcher: Some(search::Searcher::new(options)),
+        thread: None,
+        stop: Arc::new(AtomicBool::new(false)),
+    };
+
+    for line in std::io::stdin().lock().lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        let tokens: Vec::<&str> = line.split_whitespace().collect();
+        let number = |i: usize| tokens.get(i).and_then(|t| t.parse::<u64>().ok());
+        match tokens.first() {
+            None | Some(&"xboard") | Some(&"accepted") | Some(&"rejected") | Some(&"random") | Some(&"computer")
+            | Some(&"hard") | Some(&"easy") | Some(&"name") | Some(&"rating") | Some(&"draw") | Some(&"otherboard") => (),
+            Some(&"protover") => {
+                let vars: Vec::<&str> = variant::ALL_VARIANTS.iter().map(|&v| xboard_name(v)).collect();
+                println!(
+                    "feature myname=\"chess\" ping=1 setboard=1 usermove=1 san=0 time=1 draw=0 sigint=0 sigterm=0 \
+                     reuse=1 analyze=0 colors=0 memory=1 smp=1 variants=\"{}\"",
+                    vars.join(",")
+                );
+                println!("feature done=1");
+            }
+            Some(&"ping") => println!("pong {}", tokens.get(1).unwrap_or(&"")),
+            Some(&"new") => {
+                session.reset_searcher();
+                session.variant = Variant::Standard;
+                session.game = Session::new_game(Variant::Standard);
+                session.history.clear();
+                session.engine = Some(Player::Black);
+                session.time.depth = None;
+            }
+            Some(&"variant") => {
+                session.stop();
+                let name = tokens.get(1).copied().unwrap_or("");
+                let v = if name == "normal" { Some(Variant::Standard) } else { Variant::from_name(name) };
+                match v {
+                    Some(v) => {
+                        session.variant = v;
+                        session.game = Session::new_game(v);
+                        session.history.clear();
+                    }
+                    None => println!("Error (unsupported variant): {}", name),
+                }
+            }
+            Some(&"setboard") => {
+                session.stop();
+                let fen = tokens[1..].join(" ");
+                let mut game = Game::from_fen(&fen);
+                if session.variant != Variant::Standard {
+                    game.set_variant(session.variant);
+                }
+                session.game = game;
+                session.history.clear();
+            }
+            Some(&"usermove") => match tokens.get(1) {
+                Some(m) => session.user_move(m),
+                None => println!("Error (missing move): usermove"),
+            },
+            Some(&"go") => {
+                session.join();
+                session.engine = Some(session.game.board.active_player());
+                session.think();
+            }
+            Some(&"playother") => {
+                session.join();
+                session.engine = Some(session.game.board.active_player().invert());
+            }
+            Some(&"force") | Some(&"result") => {
+                session.stop();
+                session.engine = None;
+            }
+            Some(&"?") => session.stop(),
+            Some(&"undo") => session.take_back(1),
+            Some(&"remove") => session.take_back(2),
+            Some(&"level") => match parse_level(&tokens[1..]) {
+                Some((moves, base, inc)) => {
+                    session.time.moves_per_period = moves;
+                    session.time.base = base;
+                    session.time.inc = inc;
+                    session.time.move_time = None;
+                }
+                None => println!("Error (bad level): {}", line),
+            },
+            Some(&"st") => session.time.move_time = number(1).map(|s| s * 1000),
+            Some(&"sd") => session.time.depth = number(1).map(|d| d as i32),
+            //both in centiseconds, sent before every move
+            Some(&"time") => session.time.own_clock = number(1).map(|cs| cs * 10),
+            Some(&"otim") => session.time.opponent_clock = number(1).map(|cs| cs * 10),
+            Some(&"post") => session.post = true,
+            Some(&"nopost") => session.post = false,
+            Some(&"memory") => {
+                session.options.hash_mb = number(1).unwrap_or(1).clamp(1, MAX_HASH_MB as u64) as usize;
+                session.reset_searcher();
+            }
+            Some(&"cores") => {
+                session.options.threads = number(1).unwrap_or(1).clamp(1, MAX_THREADS as u64) as usize;
+                session.reset_searcher();
+            }
+            Some(&"quit") => break,
+            //without usermove=1 being accepted, moves come on their own
+            Some(&m) => {
+                let looks_like_move = m.contains('@')
+                    || crate::chess::parse_simple_input(m, session.game.board.active_player()).is_ok();
+                if looks_like_move {
+                    session.user_move(m);
+                } else {
+                    println!("Error (unknown command): {}", m);
+                }
+            }
+        }
+    }
+    session.stop();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_missing_clock_still_limits_the_search() {
+        let limits = TimeSettings::default().limits(Player::White, 0);
+        assert_eq!(limits.movetime, Some(DEFAULT_MOVE_TIME));
+        let limits = TimeSettings { depth: Some(6), ..TimeSettings::default() }.limits(Player::White, 0);
+        assert_eq!((limits.movetime, limits.depth), (None, Some(6)));
+        let limits = TimeSettings { base: 60_000, ..TimeSettings::default() }.limits(Player::Black, 0);
+        assert_eq!((limits.movetime, limits.btime), (None, Some(60_000)));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::chess::{board, piece};
+use crate::chess::variant::{Variant, CHECKS_TO_WIN};
+
+//zobrist keys, made at compile time with splitmix64 so they never change between builds
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    (state, z ^ (z >> 31))
+}
+
+// 12 pieces * 64 squares, then 4 castle rights, 8 en passant files and the side to move.
+// crazyhouse adds pocket counts up to 16 for each side's 5 kinds of piece, and the promoted squares.
+// one for each variant, so the same position under other rules is a different entry, then three-check counters
+const KEY_COUNT: usize = CHECKS_OFFSET + 2 * CHECKS_TO_WIN as usize;
+const CASTLE_OFFSET: usize = 12 * 64;
+const EN_PASSANT_OFFSET: usize = CASTLE_OFFSET + 4;
+const BLACK_TO_MOVE: usize = EN_PASSANT_OFFSET + 8;
+const POCKET_OFFSET: usize = BLACK_TO_MOVE + 1;
+const MAX_POCKET: usize = 16;
+const PROMOTED_OFFSET: usize = POCKET_OFFSET + 2 * 5 * MAX_POCKET;
+const VARIANT_OFFSET: usize = PROMOTED_OFFSET + 64;
+const MAX_VARIANTS: usize = 16;
+const CHECKS_OFFSET: usize = VARIANT_OFFSET + MAX_VARIANTS;
+
+const KEYS: [u64; KEY_COUNT] = {
+    let mut keys = [0u64; KEY_COUNT];
+    let mut state = 0x0005_EEDC_4E55_u64;
+    let mut i = 0;
+    while i < KEY_COUNT {
+        let (next_state, key) = splitmix64(state);
+        state = next_state;
+        keys[i] = key;
+        i += 1;
+    }
+    keys
+};
+
+pub fn piece_key(p: piece::Piece, square_index: usize) -> u64 {
+    KEYS[p.index() * 64 + square_index]
+}
+
+pub fn hash(board: &board::Board) -> u64 {
+    use piece::Player::*;
+    let mut key = 0u64;
+    for player in [White, Black] {
+        for state in board.get_pieces(player) {
+            key ^= piece_key(state.piece_type, state.pos.index());
+        }
+    }
+    let rights = [
+        board.castle_rook(White, true).is_some(),
+        board.castle_rook(White, false).is_some(),
+        board.castle_rook(Black, true).is_some(),
+        board.castle_rook(Black, false).is_some(),
+    ];
+    for (i, &right) in rights.iter().enumerate() {
+        if right {
+            key ^= KEYS[CASTLE_OFFSET + i];
+        }
+    }
+    if let Some(ep) = board.en_passant_square() {
+        key ^= KEYS[EN_PASSANT_OFFSET + ep.file as usize - 1];
+    }
+    if board.active_player() == Black {
+        key ^= KEYS[BLACK_TO_MOVE];
+    }
+    for (side, player) in [White, Black].into_iter().enumerate() {
+        for (kind, &count) in board.get_pocket(player).iter().enumerate() {
+            if count > 0 {
+                key ^= KEYS[POCKET_OFFSET + (side * 5 + kind) * MAX_POCKET + (count as usize).min(MAX_POCKET) - 1];
+            }
+        }
+    }
+    let mut promoted = board.promoted_squares();
+    while promoted != 0 {
+        key ^= KEYS[PROMOTED_OFFSET + promoted.trailing_zeros() as usize];
+        promoted &= promoted - 1;
+    }
+    if board.variant() != Variant::Standard {
+        key ^= KEYS[VARIANT_OFFSET + board.variant() as usize];
+    }
+    for (side, player) in [White, Black].into_iter().enumerate() {
+        let checks = board.checks_given(player).min(CHECKS_TO_WIN) as usize;
+        if checks > 0 {
+            key ^= KEYS[CHECKS_OFFSET + side * CHECKS_TO_WIN as usize + checks - 1];
+        }
+    }
+    key
+}
@@ -1,38 +1,384 @@
 mod chess;
-use std::vec::Vec;
 
-fn perft(base_game: &chess::Game, depth: i32) {
-    let mut count = 0u64;
-    let mut gamestates = Vec::<chess::Game>::new();
-    gamestates.push(base_game.clone());
-    for _ in 0..depth {
-        let mut next_gamestates = Vec::<chess::Game>::new();
-        for game in &mut gamestates {
-            game.gen_moves();
-            for m in game.get_moves() {
-                let mut gc = game.clone();
-                gc.make_move(m);
-                //gc.print();
-                next_gamestates.push(gc);
-                count += 1;
-            }
-        }
-        gamestates = next_gamestates;
+//pulls "--name value" out of the args, so the rest can stay positional
+fn take_flag(args: &mut Vec::<String>, name: &str) -> Option::<String> {
+    let i = args.iter().position(|a| a == name)?;
+    args.remove(i);
+    if i < args.len() {
+        Some(args.remove(i))
+    } else {
+        None
     }
+}
 
-    println!("I have peered into {} positions with depth {}, and {} final positions", 
-        count, 
-        depth, 
-        gamestates.len()
-    );
+//plays a game in the terminal, then adds it to the end of the pgn file if there is one
+fn play(mut game: chess::Game, clock: Option::<chess::TimeControl>, pgn: Option::<String>) {
+    if let Some(control) = clock {
+        game.set_clock(control);
+    }
+    game.play();
+    if let Some(path) = pgn {
+        use std::io::Write;
+        let saved = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut f| f.write_all(game.pgn(&[]).as_bytes()));
+        if let Err(e) = saved {
+            println!("couldn't save {}: {}", path, e);
+        }
+    }
 }
 
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// chess                        play a game in the terminal
+// chess perft <depth> [fen]    count positions, to check move generation
+// chess perft-suite [max nodes] check move generation against known counts
+//   perft and perft-suite also take --threads <n> and --hash <mb>
+//   playing, search and perft take --variant <name>: standard, crazyhouse, 3check,
+//   kingofthehill, atomic, antichess, horde or racingkings. they start from that variant's start position
+//   playing takes --clock <control>, like 5+3, 40/90+30:30+30, 3d2 for a 2 second delay or 3b2 for bronstein,
+//   and --pgn <file> to add the game to, with the clock times
+//   playing and everything that searches take --net <file>, a network to evaluate standard chess with
+// chess chess960 [n] [play]   the fen of chess960 start position n, 0 to 959, or a random one. play to play it
+// chess search <depth> [fen]   find the best move in a position
+// chess analyze [fen] [--multipv n] [--movetime ms | --depth n]
+//                              the best n lines with scores and san, updated after every depth.
+//                              runs until enter is pressed without --movetime or --depth
+// chess mate <n> [fen]        prove or refute a mate in n for the side to move, with every key, the answers to
+//                              each defence and whether there are cooks or duals
+// chess helpmate <n> [fen]     every line where the side to move helps the other side mate in n
+// chess selfmate <n> [fen]     the side to move forcing the other side to mate it in n, keys and variations like mate
+// chess puzzle <csv> [--history file]  solve puzzles from a lichess style csv in the terminal. the rating and the
+//                              puzzles tried are kept in the history file, puzzles.history without --history
+// chess uci                    talk uci to a chess gui
+// chess xboard                 talk xboard (cecp) to a chess gui
+// chess match <engine> <engine> [--games n] [--concurrency n] [--openings file] [--clock control | --movetime ms] [--pgn file]
+//                              play uci engines against each other, in pairs of games with colours swapped.
+//                              openings are an epd file or a pgn file, games go to match.pgn without --pgn
+// chess sprt <pgn> [--elo0 n] [--elo1 n] [--alpha p] [--beta p] [--engine name]
+//                              sequential probability ratio test on a match, for the engine named or
+//                              white in the first game. elo0 0, elo1 5, alpha and beta 0.05 by default
+// chess epd <files> [--movetime ms | --depth n]  search every position of epd suites like wac or sts,
+//                              a second each by default, and count the ones where the move is one of bm and not am
+// chess datagen <out> [--games n] [--depth n] [--random-plies n] [--openings file]
+//                              self play games from the openings or the start position plus random moves, 100 games
+//                              at depth 6 with 8 random plies by default. quiet positions with their scores and the
+//                              results go to out, 32 byte records for a .bin file and fen | score | result lines otherwise
+// chess tune <positions> [out.rs] [--epochs n]  texel tuning of the material and piece square tables,
+//                              from lines of a fen and a result like 1-0 or [0.5]. writes tuned.rs without out.rs
+// chess book-build <pgn> <book> [--depth plies] [--min-games n] [--keys file]
+//                              make a polyglot book out of the start of every game
+// chess book-probe <book> [fen] [--keys file]  list the book moves in a position
+//   books are keyed with polyglot's own random numbers, --keys swaps in another table from a file like pg_key.c
+// chess syzygy <dirs> [fen]    win/draw/loss and distance to zeroing from syzygy tables
+// chess tb-gen <dir> [endings] work out KQvK, KRvK, KPvK and KBNvK distance to mate tables, or just the ones named
+// chess tb-probe <dir> [fen]   distance to mate from those tables, for the position and after every move
+// chess bench [depth]          node counts of a fixed depth search, with and without move ordering
+// chess bench-pruning [depth]  node counts with each pruning, reduction and extension turned on alone
+// chess bench-smp [depth] [threads]  time to depth with one thread and with many
 fn main() {
-    // default a couple hundred less moves at depth 5!
-    // "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - " ~100 more moves depth 3
-    // "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - " 2 less moves at depth 3
-    let mut game = chess::Game::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ");
-    //game.play();
-    perft(&game, 4);
-    
+    let mut args: Vec::<String> = std::env::args().collect();
+    let threads = take_flag(&mut args, "--threads")
+        .and_then(|t| t.parse().ok())
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    if let Some(path) = take_flag(&mut args, "--net") {
+        if let Err(msg) = chess::load_network(&path) {
+            println!("{}", msg);
+            std::process::exit(1);
+        }
+    }
+    let hash_mb = take_flag(&mut args, "--hash").and_then(|h| h.parse().ok()).unwrap_or(0);
+    let keys = take_flag(&mut args, "--keys");
+    let depth_flag = take_flag(&mut args, "--depth").and_then(|d| d.parse::<usize>().ok());
+    let min_games = take_flag(&mut args, "--min-games").and_then(|n| n.parse().ok()).unwrap_or(1);
+    let clock = take_flag(&mut args, "--clock").map(|c| {
+        chess::TimeControl::parse(&c).unwrap_or_else(|msg| {
+            println!("{}", msg);
+            std::process::exit(1);
+        })
+    });
+    let pgn = take_flag(&mut args, "--pgn");
+    let history = take_flag(&mut args, "--history");
+    let games = take_flag(&mut args, "--games").and_then(|n| n.parse::<usize>().ok());
+    let random_plies = take_flag(&mut args, "--random-plies").and_then(|n| n.parse().ok()).unwrap_or(8);
+    let concurrency = take_flag(&mut args, "--concurrency").and_then(|n| n.parse().ok()).unwrap_or(1);
+    let openings = take_flag(&mut args, "--openings");
+    let movetime = take_flag(&mut args, "--movetime").and_then(|ms| ms.parse::<u64>().ok());
+    let epochs = take_flag(&mut args, "--epochs").and_then(|n| n.parse().ok()).unwrap_or(500);
+    let multipv = take_flag(&mut args, "--multipv").and_then(|n| n.parse().ok()).unwrap_or(1);
+    let mut float_flag = |name: &str, default: f64| take_flag(&mut args, name).and_then(|v| v.parse().ok()).unwrap_or(default);
+    let sprt_settings = chess::SprtSettings {
+        elo0: float_flag("--elo0", 0.0),
+        elo1: float_flag("--elo1", 5.0),
+        alpha: float_flag("--alpha", 0.05),
+        beta: float_flag("--beta", 0.05),
+        engine: take_flag(&mut args, "--engine"),
+    };
+    //left alone when not given, so a fen with pockets still means crazyhouse
+    let variant = take_flag(&mut args, "--variant").map(|name| {
+        chess::Variant::from_name(&name).unwrap_or_else(|| {
+            println!("unknown variant {}", name);
+            std::process::exit(1);
+        })
+    });
+    let depth = |default: i32| args.get(2).and_then(|d| d.parse().ok()).unwrap_or(default);
+    //horde and racing kings have start positions of their own
+    let start_fen = variant.map_or(START_FEN, |v| v.start_fen());
+    let fen = if args.len() > 3 { args[3..].join(" ") } else { start_fen.to_owned() };
+    //everything that evaluates positions gets the kpk bitbase up front, so no score changes once it's ready.
+    //uci and xboard make it themselves after the handshake, it takes a while in a debug build
+    let evaluates = !matches!(
+        args.get(1).map(|s| s.as_str()),
+        Some("perft" | "perft-suite" | "book-build" | "book-probe" | "syzygy" | "tb-gen" | "tb-probe" | "match" | "sprt"
+            | "uci" | "xboard")
+    );
+    if evaluates {
+        chess::init_kpk();
+    }
+    match args.get(1).map(|s| s.as_str()) {
+        Some("perft") => {
+            chess::perft(&fen, variant, depth(4).max(1) as u32, threads, hash_mb);
+        }
+        Some("perft-suite") => {
+            let max_nodes = args.get(2).and_then(|n| n.parse().ok()).unwrap_or(5_000_000);
+            if !chess::perft_suite(max_nodes, threads, hash_mb) {
+                std::process::exit(1);
+            }
+        }
+        Some("chess960") => {
+            //a fresh random number from std, like the book uses
+            use std::hash::{BuildHasher, Hasher};
+            let index = args.get(2).and_then(|n| n.parse().ok()).unwrap_or_else(|| {
+                std::collections::hash_map::RandomState::new().build_hasher().finish() as usize % 960
+            });
+            println!("{} {}", index % 960, chess::chess960_fen(index));
+            if args.iter().any(|a| a == "play") {
+                let mut game = chess::Game::chess960(index);
+                if let Some(v) = variant {
+                    game.set_variant(v);
+                }
+                play(game, clock, pgn);
+            }
+        }
+        Some("search") => {
+            let mut game = chess::Game::from_fen(&fen);
+            if let Some(v) = variant {
+                game.set_variant(v);
+            }
+            game.print();
+            if let Some(result) = game.result() {
+                match result {
+                    chess::GameResult::Win(chess::Player::White, win) => println!("{}, White wins", win),
+                    chess::GameResult::Win(chess::Player::Black, win) => println!("{}, Black wins", win),
+                    chess::GameResult::Draw => println!("draw"),
+                }
+                return;
+            }
+            match game.best_move(depth(4)) {
+                Some(m) => println!("best move {}", m),
+                None => println!("no legal moves"),
+            }
+            match game.eval() {
+                chess::GameEval::Checkmate(p) => println!("{} wins", match p {
+                    chess::Player::White => "White",
+                    chess::Player::Black => "Black",
+                }),
+                chess::GameEval::Draw => println!("draw"),
+                chess::GameEval::Eval(e) => println!("eval {:+.2}", e),
+            }
+        }
+        Some("book-build") => {
+            let (pgn, book) = match (args.get(2), args.get(3)) {
+                (Some(pgn), Some(book)) => (pgn, book),
+                _ => {
+                    println!("usage: chess book-build <pgn> <book> [--depth plies] [--min-games n] [--keys file]");
+                    std::process::exit(1);
+                }
+            };
+            if let Err(msg) = chess::book_build(pgn, book, depth_flag.unwrap_or(16), min_games, keys.as_deref()) {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some("book-probe") => {
+            let fen = if args.len() > 3 { args[3..].join(" ") } else { START_FEN.to_owned() };
+            let result = match args.get(2) {
+                Some(book) => chess::book_probe(book, &fen, keys.as_deref()),
+                None => Err("usage: chess book-probe <book> [fen] [--keys file]".to_owned()),
+            };
+            if let Err(msg) = result {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some("syzygy") => {
+            let fen = if args.len() > 3 { args[3..].join(" ") } else { START_FEN.to_owned() };
+            let result = match args.get(2) {
+                Some(dirs) => chess::syzygy_probe(dirs, &fen),
+                None => Err("usage: chess syzygy <dirs> [fen]".to_owned()),
+            };
+            if let Err(msg) = result {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some("tb-gen") => {
+            let result = match args.get(2) {
+                Some(dir) => chess::tablebase_generate(dir, &args[3..]),
+                None => Err("usage: chess tb-gen <dir> [endings]".to_owned()),
+            };
+            if let Err(msg) = result {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some("tb-probe") => {
+            let result = match args.get(2) {
+                Some(dir) => chess::tablebase_probe(dir, &fen),
+                None => Err("usage: chess tb-probe <dir> [fen]".to_owned()),
+            };
+            if let Err(msg) = result {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some("match") => {
+            let engines = match (args.get(2), args.get(3)) {
+                (Some(first), Some(second)) => [first.clone(), second.clone()],
+                _ => {
+                    println!("usage: chess match <engine> <engine> [--games n] [--concurrency n] [--openings file] [--clock control | --movetime ms] [--pgn file]");
+                    std::process::exit(1);
+                }
+            };
+            let variant = variant.unwrap_or(chess::Variant::Standard);
+            let openings = match openings.map(|path| chess::load_openings(&path, variant)) {
+                Some(Ok(o)) => o,
+                Some(Err(msg)) => {
+                    println!("{}", msg);
+                    std::process::exit(1);
+                }
+                None => vec![],
+            };
+            let settings = chess::MatchSettings {
+                engines,
+                games: games.unwrap_or(2),
+                concurrency,
+                openings,
+                clock,
+                movetime: std::time::Duration::from_millis(movetime.unwrap_or(100)),
+                variant,
+                pgn: pgn.unwrap_or_else(|| "match.pgn".to_owned()),
+            };
+            if let Err(msg) = chess::play_match(&settings) {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some("sprt") => {
+            let result = match args.get(2) {
+                Some(pgn) => chess::sprt(pgn, &sprt_settings),
+                None => Err("usage: chess sprt <pgn> [--elo0 n] [--elo1 n] [--alpha p] [--beta p] [--engine name]".to_owned()),
+            };
+            if let Err(msg) = result {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some("epd") => {
+            if args.len() < 3 {
+                println!("usage: chess epd <files> [--movetime ms | --depth n]");
+                std::process::exit(1);
+            }
+            if let Err(msg) = chess::epd_suite(&args[2..], movetime.unwrap_or(1000), depth_flag.map(|d| d as i32), threads, hash_mb) {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some("analyze") => {
+            let fen = if args.len() > 2 { args[2..].join(" ") } else { start_fen.to_owned() };
+            if let Err(msg) = chess::analyze(&fen, variant, multipv, movetime, depth_flag.map(|d| d as i32), hash_mb) {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some("datagen") => {
+            let out = match args.get(2) {
+                Some(out) => out.clone(),
+                None => {
+                    println!("usage: chess datagen <out> [--games n] [--depth n] [--random-plies n] [--openings file] [--threads n]");
+                    std::process::exit(1);
+                }
+            };
+            let openings = match openings.map(|path| chess::load_openings(&path, chess::Variant::Standard)) {
+                Some(Ok(o)) => o,
+                Some(Err(msg)) => {
+                    println!("{}", msg);
+                    std::process::exit(1);
+                }
+                None => vec![],
+            };
+            let settings = chess::DatagenSettings {
+                out,
+                games: games.unwrap_or(100),
+                threads,
+                depth: depth_flag.map_or(6, |d| d as i32),
+                random_plies,
+                openings,
+                hash_mb,
+            };
+            if let Err(msg) = chess::datagen(&settings) {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some("tune") => {
+            let result = match args.get(2) {
+                Some(positions) => chess::tune(positions, args.get(3).map_or("tuned.rs", |o| o.as_str()), epochs),
+                None => Err("usage: chess tune <positions> [out.rs] [--epochs n]".to_owned()),
+            };
+            if let Err(msg) = result {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some("puzzle") => {
+            let result = match args.get(2) {
+                Some(csv) => chess::puzzles(csv, history.as_deref().unwrap_or("puzzles.history")),
+                None => Err("usage: chess puzzle <csv> [--history file]".to_owned()),
+            };
+            if let Err(msg) = result {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some(name @ ("mate" | "helpmate" | "selfmate")) => {
+            let stipulation = chess::Stipulation::from_name(name).expect("one of the names matched");
+            let result = match args.get(2).and_then(|n| n.parse().ok()) {
+                Some(n) => chess::solve_mate(&fen, variant, n, stipulation),
+                None => Err(format!("usage: chess {} <n> [fen]", name)),
+            };
+            if let Err(msg) = result {
+                println!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+        Some("uci") => chess::uci(),
+        Some("xboard") => chess::xboard(),
+        Some("bench") => chess::bench(depth(3)),
+        Some("bench-pruning") => chess::bench_pruning(depth(5)),
+        Some("bench-smp") => chess::bench_smp(depth(6), args.get(3).and_then(|t| t.parse().ok()).unwrap_or(4)),
+        _ => {
+            let game = match variant {
+                Some(v) => {
+                    let mut game = chess::Game::from_fen(start_fen);
+                    game.set_variant(v);
+                    game
+                }
+                None => chess::Game::new(),
+            };
+            play(game, clock, pgn);
+        }
+    }
 }